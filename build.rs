@@ -10,9 +10,20 @@ fn main() -> color_eyre::Result<()> {
     let out_dir = PathBuf::from(env::var("OUT_DIR")?);
 
     // compile WASM modules
-    let wasm_path = PathBuf::from("src/helloworld.wast");
-    build_wasm(&wasm_path, out_dir)
-        .with_context(|| format!("building WASM `{}` failed!", wasm_path.display()))?;
+    for wasm_path in ["src/helloworld.wast", "src/random.wast"] {
+        let wasm_path = PathBuf::from(wasm_path);
+        build_wasm(&wasm_path, &out_dir)
+            .with_context(|| format!("building WASM `{}` failed!", wasm_path.display()))?;
+    }
+
+    // pack the initramfs
+    pack_initramfs("initramfs", &out_dir).context("packing initramfs failed!")?;
+
+    // generate WASI bindings
+    gen_wasi(&out_dir).context("generating WASI bindings failed!")?;
+
+    // generate WASM spec tests
+    gen_spec_tests(&out_dir).context("generating WASM spec tests failed!")?;
 
     // generate version info env vars
     gen_version()?;
@@ -36,7 +47,7 @@ fn gen_version() -> Result<()> {
 
 fn build_wasm(wasm: impl AsRef<Path>, out_dir: impl AsRef<Path>) -> Result<()> {
     let wasm = wasm.as_ref();
-    // Build our helloworld.wast into binary.
+    // Build the .wast file into binary.
     let binary = wat::parse_file(wasm)?;
     let file_stem = wasm
         .file_stem()
@@ -49,3 +60,817 @@ fn build_wasm(wasm: impl AsRef<Path>, out_dir: impl AsRef<Path>) -> Result<()> {
     println!("cargo:rerun-if-changed={}", wasm.display());
     Ok(())
 }
+
+/// Packs every WASM module in `dir` into a `newc`-format cpio archive, which
+/// is embedded in the kernel as its initramfs.
+///
+/// `.wasm` files are packed as-is, while `.wat` and `.wast` files are compiled
+/// to binary first, and packed as `<name>.wasm`.
+fn pack_initramfs(dir: impl AsRef<Path>, out_dir: impl AsRef<Path>) -> Result<()> {
+    let dir = dir.as_ref();
+    println!("cargo:rerun-if-changed={}", dir.display());
+
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    // sort the entries so that the archive is reproducible.
+    paths.sort();
+
+    let mut archive = Vec::new();
+    for (ino, path) in paths.iter().enumerate() {
+        let (Some(stem), Some(ext)) = (path.file_stem(), path.extension()) else {
+            continue;
+        };
+        let binary = match ext.to_str() {
+            Some("wasm") => fs::read(path)?,
+            Some("wat" | "wast") => wat::parse_file(path)
+                .with_context(|| format!("building WASM `{}` failed!", path.display()))?,
+            _ => continue,
+        };
+        let name = format!("{}.wasm", stem.to_string_lossy());
+        cpio_entry(&mut archive, ino + 1, 0o100444, &name, &binary);
+        println!("cargo:rerun-if-changed={}", path.display());
+    }
+    cpio_entry(&mut archive, 0, 0, "TRAILER!!!", &[]);
+
+    fs::write(out_dir.as_ref().join("initramfs.cpio"), archive)?;
+    Ok(())
+}
+
+/// Appends a `newc`-format cpio entry to `archive`.
+fn cpio_entry(archive: &mut Vec<u8>, ino: usize, mode: u32, name: &str, data: &[u8]) {
+    fn pad(archive: &mut Vec<u8>) {
+        while archive.len() % 4 != 0 {
+            archive.push(0);
+        }
+    }
+
+    let fields = [
+        ino,
+        mode as usize,
+        0, // uid
+        0, // gid
+        1, // nlink
+        0, // mtime
+        data.len(),
+        0, // devmajor
+        0, // devminor
+        0, // rdevmajor
+        0, // rdevminor
+        name.len() + 1,
+        0, // check
+    ];
+    archive.extend_from_slice(b"070701");
+    for field in fields {
+        archive.extend_from_slice(format!("{field:08x}").as_bytes());
+    }
+    archive.extend_from_slice(name.as_bytes());
+    archive.push(0);
+    pad(archive);
+    archive.extend_from_slice(data);
+    pad(archive);
+}
+
+/// The directory containing the WebAssembly spec test scripts run by
+/// `wasm::spec`.
+///
+/// These aren't copies of the spec test suite's scripts: each one is a
+/// curated subset of one or more upstream scripts, trimmed to the commands
+/// that exercise the kernel's interpreter and host. Commands are added to
+/// them by hand, rather than by copying newer versions of upstream scripts.
+const SPEC_DIR: &str = "src/wasm/spec";
+
+/// Generates a `mycotest::decl_test!` for every `.wast` script in
+/// [`SPEC_DIR`], which runs the script's commands against a `wasm::Host`.
+///
+/// Every module in a script is compiled to `OUT_DIR/spec/<script>.<n>.wasm`
+/// and embedded in the kernel with `include_bytes!`. The tests are written to
+/// `OUT_DIR/wasm_spec_tests.rs`, which is included by `src/wasm/spec.rs`.
+///
+/// Commands that can't be run by the kernel are skipped, and the generated
+/// test logs each skipped command and why it was skipped. These are
+/// `assert_malformed` scripts in the text format (which test the text parser,
+/// not the interpreter), and SIMD, reference types, and exception handling,
+/// which the interpreter doesn't support.
+fn gen_spec_tests(out_dir: impl AsRef<Path>) -> Result<()> {
+    use std::fmt::Write;
+
+    let out_dir = out_dir.as_ref();
+    let module_dir = out_dir.join("spec");
+    fs::create_dir_all(&module_dir)?;
+    println!("cargo:rerun-if-changed={SPEC_DIR}");
+
+    let mut paths = fs::read_dir(SPEC_DIR)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.sort();
+
+    let mut out = String::from(
+        "// This file is generated by `build.rs` from the spec test scripts in\n\
+         // `src/wasm/spec`. Do not edit it by hand!\n",
+    );
+    for path in paths {
+        if path.extension().and_then(|ext| ext.to_str()) != Some("wast") {
+            continue;
+        }
+        println!("cargo:rerun-if-changed={}", path.display());
+        let stem = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| eyre!("spec script path has no file stem"))?;
+        let src = fs::read_to_string(&path)?;
+        let body = gen_spec_script(stem, &src, &module_dir)
+            .with_context(|| format!("generating spec test `{}` failed!", path.display()))?;
+
+        writeln!(out, "\nmycotest::decl_test! {{")?;
+        writeln!(
+            out,
+            "    fn {}() -> mycotest::TestResult {{",
+            stem.replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+        )?;
+        writeln!(
+            out,
+            "        let mut script = Script::new({:?});",
+            path.display().to_string()
+        )?;
+        out.push_str(&body);
+        writeln!(out, "        Ok(())")?;
+        writeln!(out, "    }}")?;
+        writeln!(out, "}}")?;
+    }
+
+    fs::write(out_dir.join("wasm_spec_tests.rs"), out)?;
+    Ok(())
+}
+
+/// Generates the body of the test for one spec script.
+fn gen_spec_script(stem: &str, src: &str, module_dir: &Path) -> Result<String> {
+    use std::fmt::Write;
+    use wast::{
+        parser::{self, ParseBuffer},
+        QuoteWat, Wast, WastDirective, WastExecute, Wat,
+    };
+
+    let buf = ParseBuffer::new(src).map_err(|mut e| {
+        e.set_text(src);
+        eyre!("{e}")
+    })?;
+    let script = parser::parse::<Wast>(&buf).map_err(|mut e| {
+        e.set_text(src);
+        eyre!("{e}")
+    })?;
+
+    let mut out = String::new();
+    let mut modules = 0;
+    // writes `binary` to `OUT_DIR` and returns an expression that includes it.
+    let mut include_module = |binary: Vec<u8>| -> Result<String> {
+        let name = format!("{stem}.{modules}.wasm");
+        modules += 1;
+        fs::write(module_dir.join(&name), binary)?;
+        Ok(format!(
+            "include_bytes!(concat!(env!(\"OUT_DIR\"), \"/spec/{name}\"))"
+        ))
+    };
+
+    for directive in script.directives {
+        let line = directive.span().linecol_in(src).0 + 1;
+        let stmt: Result<String, &str> = match directive {
+            WastDirective::Wat(mut module) => {
+                let id = match module {
+                    QuoteWat::Wat(Wat::Module(ref module)) => module.id.map(|id| id.name()),
+                    _ => None,
+                };
+                let binary = include_module(module.encode()?)?;
+                Ok(format!("script.module({line}, {id:?}, {binary})"))
+            }
+            WastDirective::Register { name, module, .. } => {
+                let module = module.map(|id| id.name());
+                Ok(format!("script.register({line}, {name:?}, {module:?})"))
+            }
+            WastDirective::Invoke(invoke) => {
+                spec_invoke(&invoke).map(|invoke| format!("script.invoke({line}, {invoke})"))
+            }
+            WastDirective::AssertReturn { exec, results, .. } => {
+                let expected = results
+                    .iter()
+                    .map(spec_expected)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map(|expected| expected.join(", "));
+                match (exec, expected) {
+                    (_, Err(skip)) => Err(skip),
+                    (WastExecute::Invoke(invoke), Ok(expected)) => {
+                        spec_invoke(&invoke).map(|invoke| {
+                            format!("script.assert_return({line}, {invoke}, &[{expected}])")
+                        })
+                    }
+                    (WastExecute::Get { module, global }, Ok(expected)) => {
+                        let module = module.map(|id| id.name());
+                        Ok(format!(
+                            "script.assert_global({line}, {module:?}, {global:?}, &[{expected}])"
+                        ))
+                    }
+                    (WastExecute::Wat(_), _) => Err("`assert_return` of a module"),
+                }
+            }
+            WastDirective::AssertTrap { exec, message, .. } => match exec {
+                WastExecute::Invoke(invoke) => spec_invoke(&invoke)
+                    .map(|invoke| format!("script.assert_trap({line}, {invoke}, {message:?})")),
+                WastExecute::Wat(mut module) => {
+                    let binary = include_module(module.encode()?)?;
+                    Ok(format!(
+                        "script.assert_start_trap({line}, {binary}, {message:?})"
+                    ))
+                }
+                WastExecute::Get { .. } => Err("`assert_trap` of a global"),
+            },
+            WastDirective::AssertExhaustion { call, message, .. } => spec_invoke(&call)
+                .map(|invoke| format!("script.assert_trap({line}, {invoke}, {message:?})")),
+            WastDirective::AssertInvalid {
+                mut module,
+                message,
+                ..
+            }
+            | WastDirective::AssertMalformed {
+                mut module,
+                message,
+                ..
+            } => match module.encode() {
+                Ok(binary) => {
+                    let binary = include_module(binary)?;
+                    Ok(format!(
+                        "script.assert_invalid({line}, {binary}, {message:?})"
+                    ))
+                }
+                // modules in the text format which can't be encoded test the
+                // text parser, rather than the interpreter.
+                Err(_) => Err("malformed text module"),
+            },
+            WastDirective::AssertUnlinkable {
+                mut module,
+                message,
+                ..
+            } => {
+                let binary = include_module(module.encode()?)?;
+                Ok(format!(
+                    "script.assert_unlinkable({line}, {binary}, {message:?})"
+                ))
+            }
+            WastDirective::AssertException { .. } => Err("exception handling is not supported"),
+        };
+
+        match stmt {
+            Ok(stmt) => writeln!(out, "        {stmt}?;")?,
+            Err(reason) => writeln!(out, "        script.skip({line}, {reason:?});")?,
+        }
+    }
+
+    Ok(out)
+}
+
+/// Returns the arguments to a `Script` method that invokes an export, or why
+/// the invocation must be skipped.
+fn spec_invoke(invoke: &wast::WastInvoke<'_>) -> Result<String, &'static str> {
+    use wast::{core::WastArgCore, WastArg};
+
+    let args = invoke
+        .args
+        .iter()
+        .map(|arg| match arg {
+            WastArg::Core(WastArgCore::I32(v)) => Ok(format!("wasmi::RuntimeValue::I32({v})")),
+            WastArg::Core(WastArgCore::I64(v)) => Ok(format!("wasmi::RuntimeValue::I64({v})")),
+            WastArg::Core(WastArgCore::F32(v)) => Ok(spec_f32(v.bits)),
+            WastArg::Core(WastArgCore::F64(v)) => Ok(spec_f64(v.bits)),
+            WastArg::Core(WastArgCore::V128(_)) => Err("SIMD is not supported"),
+            WastArg::Core(_) => Err("reference types are not supported"),
+            WastArg::Component(_) => Err("components are not supported"),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let module = invoke.module.map(|id| id.name());
+    Ok(format!(
+        "{module:?}, {:?}, &[{}]",
+        invoke.name,
+        args.join(", ")
+    ))
+}
+
+/// Returns an `Expect` for a result of an `assert_return`, or why the
+/// assertion must be skipped.
+fn spec_expected(ret: &wast::WastRet<'_>) -> Result<String, &'static str> {
+    use wast::{
+        core::{NanPattern, WastRetCore},
+        WastRet,
+    };
+
+    let value = match ret {
+        WastRet::Core(WastRetCore::I32(v)) => format!("wasmi::RuntimeValue::I32({v})"),
+        WastRet::Core(WastRetCore::I64(v)) => format!("wasmi::RuntimeValue::I64({v})"),
+        WastRet::Core(WastRetCore::F32(NanPattern::Value(v))) => spec_f32(v.bits),
+        WastRet::Core(WastRetCore::F64(NanPattern::Value(v))) => spec_f64(v.bits),
+        WastRet::Core(WastRetCore::F32(NanPattern::CanonicalNan))
+        | WastRet::Core(WastRetCore::F64(NanPattern::CanonicalNan)) => {
+            return Ok("Expect::CanonicalNan".to_string())
+        }
+        WastRet::Core(WastRetCore::F32(NanPattern::ArithmeticNan))
+        | WastRet::Core(WastRetCore::F64(NanPattern::ArithmeticNan)) => {
+            return Ok("Expect::ArithmeticNan".to_string())
+        }
+        WastRet::Core(WastRetCore::V128(_)) => return Err("SIMD is not supported"),
+        WastRet::Core(WastRetCore::Either(_)) => return Err("`either` results are not supported"),
+        WastRet::Core(_) => return Err("reference types are not supported"),
+        WastRet::Component(_) => return Err("components are not supported"),
+    };
+    Ok(format!("Expect::Value({value})"))
+}
+
+fn spec_f32(bits: u32) -> String {
+    format!("wasmi::RuntimeValue::F32(wasmi::nan_preserving_float::F32::from_bits({bits:#x}))")
+}
+
+fn spec_f64(bits: u64) -> String {
+    format!("wasmi::RuntimeValue::F64(wasmi::nan_preserving_float::F64::from_bits({bits:#x}))")
+}
+
+/// A WASI host module generated from a `witx` definition.
+struct WasiModule {
+    /// The name of the WASM import module.
+    name: &'static str,
+    /// The name of the generated Rust module.
+    rust_mod: &'static str,
+    /// The prefix for this module's `HostFunc` variants.
+    variant_prefix: &'static str,
+    witx: &'static str,
+}
+
+const WASI_MODULES: &[WasiModule] = &[
+    WasiModule {
+        name: "wasi_snapshot_preview1",
+        rust_mod: "preview1",
+        variant_prefix: "Preview1",
+        witx: "src/wasm/witx/preview1/wasi_snapshot_preview1.witx",
+    },
+    WasiModule {
+        name: "wasi_unstable",
+        rust_mod: "unstable",
+        variant_prefix: "Unstable",
+        witx: "src/wasm/witx/preview0/wasi_unstable.witx",
+    },
+];
+
+const GENERATED_HEADER: &str = "\
+// This file is generated by `build.rs` from the WASI `witx` definitions in
+// `src/wasm/witx`. Do not edit it by hand!
+";
+
+/// Generates the WASI ABI types, the `host_funcs!` table for every WASI
+/// function, and `ENOSYS` stubs for every WASI function.
+///
+/// This writes three files to `OUT_DIR`:
+///
+/// - `wasi_types.rs`: errno values, enums, flags, and the memory layouts of
+///   WASI structs, one module per WASI version.
+/// - `wasi_stubs.rs`: a function for every WASI import that returns
+///   `ENOSYS`, one module per WASI version. Implemented host functions shadow
+///   these.
+/// - `wasi_host_funcs.rs`: a `wasi_host_funcs!` macro, which invokes
+///   `host_funcs!` with the signatures of every WASI import, followed by any
+///   additional host functions passed to it.
+fn gen_wasi(out_dir: impl AsRef<Path>) -> Result<()> {
+    use std::fmt::Write;
+
+    let out_dir = out_dir.as_ref();
+    let mut types = String::from(GENERATED_HEADER);
+    let mut stubs = String::from(GENERATED_HEADER);
+    let mut host_funcs = String::from(GENERATED_HEADER);
+    let mut table = String::new();
+
+    for module in WASI_MODULES {
+        let doc = witx::load(&[module.witx])
+            .map_err(|error| eyre!("failed to load `{}`:\n{}", module.witx, error.report()))?;
+        let witx_mod = doc
+            .module(&witx::Id::new(module.name))
+            .ok_or_else(|| eyre!("`{}` does not define `{}`", module.witx, module.name))?;
+
+        writeln!(types, "\n/// `{}` types.", module.name)?;
+        writeln!(types, "pub mod {} {{", module.rust_mod)?;
+        for ty in doc.typenames() {
+            gen_wasi_type(&mut types, &ty)?;
+        }
+        writeln!(types, "}}")?;
+
+        writeln!(stubs, "\n/// Unsupported `{}` functions.", module.name)?;
+        writeln!(stubs, "pub mod {} {{", module.rust_mod)?;
+        writeln!(
+            stubs,
+            "    use crate::wasm::{{wasi::Errno, GuestPtr, GuestSlice, Host}};"
+        )?;
+        for func in witx_mod.funcs() {
+            let name = func.name.as_str();
+            let params = wasi_func_params(&func);
+            let returns = !func.results.is_empty();
+
+            // host_funcs! entry
+            write!(table, "            fn {:?}::{:?}(", module.name, name)?;
+            for (i, (param, ty)) in params.iter().enumerate() {
+                let sep = if i == 0 { "" } else { ", " };
+                write!(table, "{sep}{param}: {ty}")?;
+            }
+            table.push(')');
+            if returns {
+                table.push_str(" -> Errno");
+            }
+            writeln!(
+                table,
+                "\n                as {}{} impl wasi::{}::{};",
+                module.variant_prefix,
+                camel_case(name),
+                module.rust_mod,
+                name,
+            )?;
+
+            // ENOSYS stub
+            write!(stubs, "\n    pub fn {name}(_host: &mut Host")?;
+            for (param, ty) in &params {
+                write!(stubs, ", _{param}: {ty}")?;
+            }
+            if returns {
+                writeln!(stubs, ") -> Result<Errno, wasmi::Trap> {{")?;
+                writeln!(
+                    stubs,
+                    "        tracing::warn!(\"{}::{name} is not supported\");",
+                    module.name
+                )?;
+                writeln!(stubs, "        Ok(Errno::Nosys)")?;
+            } else {
+                // functions that don't return can't return `ENOSYS`, so just
+                // trap instead.
+                writeln!(stubs, ") -> Result<(), wasmi::Trap> {{")?;
+                writeln!(
+                    stubs,
+                    "        tracing::warn!(\"{}::{name} is not supported\");",
+                    module.name
+                )?;
+                writeln!(stubs, "        Err(wasmi::TrapKind::Unreachable.into())")?;
+            }
+            writeln!(stubs, "    }}")?;
+        }
+        writeln!(stubs, "}}")?;
+
+        println!("cargo:rerun-if-changed={}", module.witx);
+    }
+
+    writeln!(
+        host_funcs,
+        "
+/// Invokes `host_funcs!` with every function in the WASI `witx` definitions,
+/// followed by any additional host functions passed to this macro.
+macro_rules! wasi_host_funcs {{
+    ($($rest:tt)*) => {{
+        host_funcs! {{
+{table}
+            $($rest)*
+        }}
+    }};
+}}"
+    )?;
+
+    fs::write(out_dir.join("wasi_types.rs"), types)?;
+    fs::write(out_dir.join("wasi_stubs.rs"), stubs)?;
+    fs::write(out_dir.join("wasi_host_funcs.rs"), host_funcs)?;
+
+    println!("cargo:rerun-if-changed=src/wasm/witx");
+    Ok(())
+}
+
+/// Generates the Rust definition of a WASI type.
+fn gen_wasi_type(out: &mut String, ty: &witx::NamedType) -> Result<()> {
+    use std::fmt::Write;
+    use witx::{Layout, Type};
+
+    let name = ty.name.as_str();
+    let type_name = camel_case(name);
+    write_docs(out, "    ", &ty.docs)?;
+
+    // aliases of other named types.
+    if let witx::TypeRef::Name(other) = &ty.tref {
+        writeln!(
+            out,
+            "    pub type {type_name} = {};",
+            camel_case(other.name.as_str())
+        )?;
+        return Ok(());
+    }
+
+    match &**ty.type_() {
+        Type::Variant(variant) if variant.is_enum() => {
+            let repr = int_repr(variant.tag_repr);
+            writeln!(out, "    #[repr({repr})]")?;
+            writeln!(out, "    #[derive(Copy, Clone, Debug, Eq, PartialEq)]")?;
+            writeln!(out, "    pub enum {type_name} {{")?;
+            for (i, case) in variant.cases.iter().enumerate() {
+                write_docs(out, "        ", &case.docs)?;
+                writeln!(
+                    out,
+                    "        {} = {i},",
+                    case_name(&type_name, case.name.as_str())
+                )?;
+            }
+            writeln!(out, "    }}\n")?;
+
+            writeln!(out, "    impl {type_name} {{")?;
+            writeln!(out, "        pub const fn name(self) -> &'static str {{")?;
+            writeln!(out, "            match self {{")?;
+            for case in &variant.cases {
+                writeln!(
+                    out,
+                    "                Self::{} => {:?},",
+                    case_name(&type_name, case.name.as_str()),
+                    case.name.as_str(),
+                )?;
+            }
+            writeln!(out, "            }}\n        }}\n    }}\n")?;
+
+            writeln!(out, "    impl TryFrom<{repr}> for {type_name} {{")?;
+            writeln!(out, "        type Error = {repr};")?;
+            writeln!(
+                out,
+                "        fn try_from(value: {repr}) -> Result<Self, Self::Error> {{"
+            )?;
+            writeln!(out, "            match value {{")?;
+            for (i, case) in variant.cases.iter().enumerate() {
+                writeln!(
+                    out,
+                    "                {i} => Ok(Self::{}),",
+                    case_name(&type_name, case.name.as_str())
+                )?;
+            }
+            writeln!(out, "                _ => Err(value),")?;
+            writeln!(out, "            }}\n        }}\n    }}\n")?;
+
+            writeln!(out, "    impl From<{type_name}> for {repr} {{")?;
+            writeln!(out, "        fn from(value: {type_name}) -> Self {{")?;
+            writeln!(out, "            value as {repr}")?;
+            writeln!(out, "        }}\n    }}\n")?;
+
+            writeln!(out, "    impl core::fmt::Display for {type_name} {{")?;
+            writeln!(
+                out,
+                "        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {{"
+            )?;
+            writeln!(out, "            f.write_str(self.name())")?;
+            writeln!(out, "        }}\n    }}\n")?;
+        }
+        Type::Variant(variant) => {
+            let layout = variant.mem_size_align();
+            writeln!(out, "    pub mod {name} {{")?;
+            write_layout(out, layout)?;
+            writeln!(out, "        /// The offset of this union's payload.")?;
+            writeln!(
+                out,
+                "        pub const PAYLOAD_OFFSET: u32 = {};",
+                variant.payload_offset()
+            )?;
+            writeln!(out, "    }}\n")?;
+        }
+        Type::Record(record) => match record.bitflags_repr() {
+            Some(repr) => {
+                let repr = int_repr(repr);
+                writeln!(out, "    pub type {type_name} = {repr};")?;
+                writeln!(out, "    /// Flags for [`{type_name}`].")?;
+                writeln!(out, "    pub mod {name} {{")?;
+                for (i, member) in record.members.iter().enumerate() {
+                    write_docs(out, "        ", &member.docs)?;
+                    writeln!(
+                        out,
+                        "        pub const {}: super::{type_name} = 1 << {i};",
+                        member.name.as_str().to_uppercase(),
+                    )?;
+                }
+                writeln!(out, "    }}\n")?;
+            }
+            None => {
+                writeln!(out, "    pub mod {name} {{")?;
+                write_layout(out, record.mem_size_align())?;
+                for member in record.member_layout() {
+                    write_docs(out, "        ", &member.member.docs)?;
+                    writeln!(
+                        out,
+                        "        pub const {}_OFFSET: u32 = {};",
+                        member.member.name.as_str().to_uppercase(),
+                        member.offset,
+                    )?;
+                }
+                writeln!(out, "    }}\n")?;
+            }
+        },
+        Type::Builtin(builtin) => {
+            writeln!(
+                out,
+                "    pub type {type_name} = {};\n",
+                builtin_type(*builtin)
+            )?;
+        }
+        Type::Handle(_) => {
+            writeln!(out, "    pub type {type_name} = u32;\n")?;
+        }
+        // lists and pointers are just `u32` offsets into linear memory, so
+        // there's nothing to generate for them.
+        Type::List(_) | Type::Pointer(_) | Type::ConstPointer(_) => {}
+    }
+
+    Ok(())
+}
+
+/// Returns the names and Rust types of the parameters that a WASI function is
+/// lowered to.
+///
+/// This matches `witx::InterfaceFunc::wasm_signature`, but also names the
+/// parameters. Pointers are typed as `GuestPtr`s and lists as `GuestSlice`s
+/// when their element types have a Rust equivalent in guest memory, and are
+/// plain `u32` offsets into linear memory otherwise.
+fn wasi_func_params(func: &witx::InterfaceFunc) -> Vec<(String, String)> {
+    use witx::{BuiltinType, IntRepr, Type};
+
+    let mut params = Vec::new();
+    for param in &func.params {
+        let name = rust_ident(param.name.as_str());
+        let ty = match &**param.tref.type_() {
+            Type::Builtin(BuiltinType::S64) => "i64".to_string(),
+            Type::Builtin(BuiltinType::U64) => "u64".to_string(),
+            Type::Builtin(BuiltinType::S8 | BuiltinType::S16 | BuiltinType::S32) => {
+                "i32".to_string()
+            }
+            Type::Builtin(BuiltinType::F32) => "f32".to_string(),
+            Type::Builtin(BuiltinType::F64) => "f64".to_string(),
+            Type::Record(record) if record.bitflags_repr() == Some(IntRepr::U64) => {
+                "u64".to_string()
+            }
+            Type::Pointer(pointee) | Type::ConstPointer(pointee) => {
+                guest_ptr_type(pointee).unwrap_or_else(|| "u32".to_string())
+            }
+            Type::List(elem) => match guest_type(elem) {
+                Some(elem) => format!("GuestSlice<{elem}>"),
+                None => {
+                    params.push((name.clone(), "u32".to_string()));
+                    params.push((format!("{name}_len"), "u32".to_string()));
+                    continue;
+                }
+            },
+            _ => "u32".to_string(),
+        };
+        params.push((name, ty));
+    }
+
+    // results other than the errno are returned through out pointers.
+    for result in &func.results {
+        let Type::Variant(variant) = &**result.tref.type_() else {
+            continue;
+        };
+        let Some(ok) = variant.cases.first().and_then(|case| case.tref.as_ref()) else {
+            continue;
+        };
+        let ret_ptr = |ty| guest_ptr_type(ty).unwrap_or_else(|| "u32".to_string());
+        match &**ok.type_() {
+            Type::Record(record) if record.is_tuple() => {
+                for (i, member) in record.members.iter().enumerate() {
+                    params.push((format!("ret{i}_ptr"), ret_ptr(&member.tref)));
+                }
+            }
+            _ => params.push(("ret_ptr".to_string(), ret_ptr(ok))),
+        }
+    }
+
+    params
+}
+
+/// Returns the `GuestPtr` type of a pointer to `pointee`, if `pointee` has a
+/// Rust equivalent in guest memory.
+fn guest_ptr_type(pointee: &witx::TypeRef) -> Option<String> {
+    guest_type(pointee).map(|ty| format!("GuestPtr<{ty}>"))
+}
+
+/// Returns the Rust type that represents `tref` in guest memory, if there is
+/// one.
+///
+/// Records of a pointer and a length, such as `iovec`, are laid out like a
+/// `GuestSlice`.
+fn guest_type(tref: &witx::TypeRef) -> Option<String> {
+    use witx::{BuiltinType, Type};
+
+    let ty = match &**tref.type_() {
+        Type::Builtin(BuiltinType::U8 { .. } | BuiltinType::Char) => "u8".to_string(),
+        Type::Builtin(BuiltinType::U16) => "u16".to_string(),
+        Type::Builtin(BuiltinType::U32 { .. }) | Type::Handle(_) => "u32".to_string(),
+        Type::Builtin(BuiltinType::U64) => "u64".to_string(),
+        Type::Builtin(BuiltinType::S32) => "i32".to_string(),
+        Type::Builtin(BuiltinType::S64) => "i64".to_string(),
+        Type::Pointer(pointee) | Type::ConstPointer(pointee) => guest_ptr_type(pointee)?,
+        Type::Record(record) => match &record.members[..] {
+            [ptr, len] if matches!(&**len.tref.type_(), Type::Builtin(BuiltinType::U32 { .. })) => {
+                match &**ptr.tref.type_() {
+                    Type::Pointer(elem) | Type::ConstPointer(elem) => {
+                        format!("GuestSlice<{}>", guest_type(elem)?)
+                    }
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(ty)
+}
+
+fn write_docs(out: &mut String, indent: &str, docs: &str) -> Result<()> {
+    use std::fmt::Write;
+    for line in docs.lines() {
+        let line = line.trim_end();
+        if line.is_empty() {
+            writeln!(out, "{indent}///")?;
+        } else {
+            writeln!(out, "{indent}/// {line}")?;
+        }
+    }
+    Ok(())
+}
+
+fn write_layout(out: &mut String, layout: witx::SizeAlign) -> Result<()> {
+    use std::fmt::Write;
+    writeln!(
+        out,
+        "        /// The size of this type in linear memory, in bytes."
+    )?;
+    writeln!(out, "        pub const SIZE: u32 = {};", layout.size)?;
+    writeln!(
+        out,
+        "        /// The alignment of this type in linear memory, in bytes."
+    )?;
+    writeln!(out, "        pub const ALIGN: u32 = {};", layout.align)?;
+    Ok(())
+}
+
+fn int_repr(repr: witx::IntRepr) -> &'static str {
+    match repr {
+        witx::IntRepr::U8 => "u8",
+        witx::IntRepr::U16 => "u16",
+        witx::IntRepr::U32 => "u32",
+        witx::IntRepr::U64 => "u64",
+    }
+}
+
+fn builtin_type(builtin: witx::BuiltinType) -> &'static str {
+    use witx::BuiltinType;
+    match builtin {
+        BuiltinType::Char => "char",
+        BuiltinType::U8 { .. } => "u8",
+        BuiltinType::U16 => "u16",
+        BuiltinType::U32 { .. } => "u32",
+        BuiltinType::U64 => "u64",
+        BuiltinType::S8 => "i8",
+        BuiltinType::S16 => "i16",
+        BuiltinType::S32 => "i32",
+        BuiltinType::S64 => "i64",
+        BuiltinType::F32 => "f32",
+        BuiltinType::F64 => "f64",
+    }
+}
+
+/// Converts a `snake_case` witx identifier to `CamelCase`.
+fn camel_case(name: &str) -> String {
+    name.split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// Returns the name of an enum variant. Some witx case names (such as
+/// `errno::2big`) start with a digit, so they are prefixed with the first
+/// letter of the type name.
+fn case_name(type_name: &str, case: &str) -> String {
+    let name = camel_case(case);
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("{}{name}", &type_name[..1])
+    } else {
+        name
+    }
+}
+
+/// Escapes witx identifiers which are Rust keywords (such as `poll_oneoff`'s
+/// `in` and `out` parameters).
+fn rust_ident(name: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "as", "async", "await", "box", "break", "const", "continue", "crate", "dyn", "else",
+        "enum", "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod",
+        "move", "mut", "pub", "ref", "return", "self", "static", "struct", "super", "trait",
+        "true", "type", "unsafe", "use", "where", "while", "yield",
+    ];
+    if KEYWORDS.contains(&name) {
+        format!("{name}_")
+    } else {
+        name.to_string()
+    }
+}
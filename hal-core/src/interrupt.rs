@@ -59,6 +59,11 @@ pub trait Handlers<R: fmt::Debug + fmt::Display> {
     /// with the scancode read from the PS/2 keyboard controller.
     fn ps2_keyboard(scancode: u8);
 
+    /// Called when a serial port receives data. The handler is provided with
+    /// the number of the port that received it (e.g. 1 for COM1), and the byte
+    /// that was received.
+    fn serial_input(port: u8, byte: u8);
+
     fn test_interrupt<C>(_cx: C)
     where
         C: ctx::Context<Registers = R>,
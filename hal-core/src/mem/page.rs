@@ -347,9 +347,22 @@ pub trait PageFlags<S: Size>: fmt::Debug {
     /// Manual control of page flags can be used to violate Rust invariants.
     unsafe fn set_present(&mut self, present: bool);
 
+    /// Set whether or not accesses to this page may be cached.
+    ///
+    /// Pages containing memory-mapped I/O registers should not be cached, so
+    /// that every read and write reaches the device.
+    ///
+    /// # Safety
+    ///
+    /// Manual control of page flags can be used to violate Rust invariants.
+    /// Mapping the same physical memory both cached and uncached may cause
+    /// undefined behavior.
+    unsafe fn set_cached(&mut self, cached: bool);
+
     fn is_writable(&self) -> bool;
     fn is_executable(&self) -> bool;
     fn is_present(&self) -> bool;
+    fn is_cached(&self) -> bool;
 
     /// Commit the changes to the page table.
     ///
@@ -775,6 +788,19 @@ where
         self
     }
 
+    /// Set whether or not accesses to this page may be cached.
+    ///
+    /// # Safety
+    ///
+    /// Manual control of page flags can be used to violate Rust invariants.
+    /// Mapping the same physical memory both cached and uncached may cause
+    /// undefined behavior.
+    #[inline]
+    pub unsafe fn set_cached(self, cached: bool) -> Self {
+        self.entry.set_cached(cached);
+        self
+    }
+
     #[inline]
     pub fn is_writable(&self) -> bool {
         self.entry.is_writable()
@@ -790,6 +816,11 @@ where
         self.entry.is_present()
     }
 
+    #[inline]
+    pub fn is_cached(&self) -> bool {
+        self.entry.is_cached()
+    }
+
     #[inline]
     pub fn commit(self) -> Page<VAddr, S> {
         tracing::debug!(
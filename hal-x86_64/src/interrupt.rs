@@ -2,7 +2,6 @@ use crate::{cpu, mm, segment, time, VAddr};
 use core::{arch::asm, marker::PhantomData, time::Duration};
 use hal_core::interrupt::Control;
 use hal_core::interrupt::{ctx, Handlers};
-use hal_core::mem::page;
 use mycelium_util::{
     bits, fmt,
     sync::{
@@ -223,6 +222,25 @@ impl Controller {
         }
     }
 
+    /// Loads the IDT on an application processor.
+    ///
+    /// All CPU cores share the same IDT, whose handlers are registered by
+    /// [`Controller::init`] on the boot processor, but each core must load it
+    /// before it can handle interrupts.
+    pub fn load_idt() {
+        let idt = IDT.lock();
+        // Safety: the IDT is in a static, so it's valid for `'static`.
+        unsafe {
+            idt.load_raw();
+        }
+    }
+
+    /// Returns the interrupt controller, or `None` if hardware interrupts have
+    /// not yet been [enabled](Self::enable_hardware_interrupts).
+    pub fn try_get() -> Option<&'static Self> {
+        INTERRUPT_CONTROLLER.try_get()
+    }
+
     pub fn mask_isa_irq(&self, irq: IsaInterrupt) {
         match self.model {
             InterruptModel::Pic(ref pics) => pics.lock().mask(irq),
@@ -252,18 +270,14 @@ impl Controller {
     }
 
     /// This should *not* be called by the boot processor
-    pub fn initialize_local_apic<A>(
+    pub fn initialize_local_apic(
         &self,
-        frame_alloc: &A,
-        pagectrl: &mut impl page::Map<mm::size::Size4Kb, A>,
-    ) -> Result<(), apic::local::LocalApicError>
-    where
-        A: page::Alloc<mm::size::Size4Kb>,
-    {
+        mmio: &impl mm::MapMmio,
+    ) -> Result<(), apic::local::LocalApicError> {
         let _deferred = disable_scoped();
         let hdl = self.local_apic_handle()?;
         unsafe {
-            hdl.initialize(frame_alloc, pagectrl, Idt::LOCAL_APIC_SPURIOUS as u8);
+            hdl.initialize(mmio, Idt::LOCAL_APIC_SPURIOUS as u8);
         }
         Ok(())
     }
@@ -281,7 +295,7 @@ impl Controller {
 
     pub fn enable_hardware_interrupts(
         acpi: Option<&acpi::InterruptModel>,
-        frame_alloc: &impl page::Alloc<mm::size::Size4Kb>,
+        mmio: &impl mm::MapMmio,
     ) -> &'static Self {
         let mut pics = pic::CascadedPic::new();
         // regardless of whether APIC or PIC interrupt handling will be used,
@@ -300,8 +314,6 @@ impl Controller {
             Some(acpi::InterruptModel::Apic(apic_info)) => {
                 tracing::info!("detected APIC interrupt model");
 
-                let mut pagectrl = mm::PageCtrl::current();
-
                 // disable the 8259 PICs so that we can use APIC interrupts instead
                 unsafe {
                     pics.disable();
@@ -309,12 +321,12 @@ impl Controller {
                 tracing::info!("disabled 8259 PICs");
 
                 // configure the I/O APIC(s)
-                let io = IoApicSet::new(apic_info, frame_alloc, &mut pagectrl, Idt::ISA_BASE as u8);
+                let io = IoApicSet::new(apic_info, mmio, Idt::ISA_BASE as u8);
 
                 // configure and initialize the local APIC on the boot processor
                 let local = apic::local::Handle::new();
                 unsafe {
-                    local.initialize(frame_alloc, &mut pagectrl, Idt::LOCAL_APIC_SPURIOUS as u8);
+                    local.initialize(mmio, Idt::LOCAL_APIC_SPURIOUS as u8);
                 }
 
                 let model = InterruptModel::Apic { local, io };
@@ -369,6 +381,7 @@ impl Controller {
         // guess...
         controller.unmask_isa_irq(IsaInterrupt::PitTimer);
         controller.unmask_isa_irq(IsaInterrupt::Ps2Keyboard);
+        controller.unmask_isa_irq(IsaInterrupt::Com1);
         controller
     }
 
@@ -514,10 +527,12 @@ impl hal_core::interrupt::Control for Idt {
         // interrupt models.
         self.register_isa_isr(IsaInterrupt::PitTimer, isr::pit_timer::<H> as *const ());
         self.register_isa_isr(IsaInterrupt::Ps2Keyboard, isr::keyboard::<H> as *const ());
+        self.register_isa_isr(IsaInterrupt::Com1, isr::com1::<H> as *const ());
 
         // local APIC specific hardware interrupts
         self.register_isr(Self::LOCAL_APIC_SPURIOUS, isr::spurious as *const ());
         self.register_isr(Self::LOCAL_APIC_TIMER, isr::apic_timer::<H> as *const ());
+        self.register_isr(Self::TLB_SHOOTDOWN, isr::tlb_shootdown as *const ());
 
         // vector 69 (nice) is reserved by the HAL for testing the IDT.
         self.register_isr(69, isr::test::<H> as *const ());
@@ -713,6 +728,24 @@ mod isr {
         }
     }
 
+    pub(super) extern "x86-interrupt" fn tlb_shootdown(_regs: Registers) {
+        crate::mm::tlb::handle_shootdown();
+        unsafe {
+            match INTERRUPT_CONTROLLER.get_unchecked().model {
+                InterruptModel::Pic(_) => unreachable!(),
+                InterruptModel::Apic { ref local, .. } => {
+                    match local.with(|apic| apic.end_interrupt()) {
+                        Ok(_) => {}
+                        Err(e) => unreachable!(
+                            "IPIs are not sent to cores whose local APIC is \
+                             uninitialized! {e:?}",
+                        ),
+                    }
+                }
+            }
+        }
+    }
+
     pub(super) extern "x86-interrupt" fn keyboard<H: Handlers<Registers>>(_regs: Registers) {
         // 0x60 is a magic PC/AT number.
         static PORT: cpu::Port = cpu::Port::at(0x60);
@@ -727,6 +760,21 @@ mod isr {
         }
     }
 
+    pub(super) extern "x86-interrupt" fn com1<H: Handlers<Registers>>(_regs: Registers) {
+        if let Some(com1) = crate::serial::com1() {
+            // load-bearing read - the UART keeps the interrupt raised until its
+            // receive buffer has been drained.
+            unsafe {
+                com1.drain_received(|byte| H::serial_input(1, byte));
+            }
+        }
+        unsafe {
+            INTERRUPT_CONTROLLER
+                .get_unchecked()
+                .end_isa_irq(IsaInterrupt::Com1);
+        }
+    }
+
     pub(super) extern "x86-interrupt" fn test<H: Handlers<Registers>>(mut registers: Registers) {
         H::test_interrupt(Context {
             registers: &mut registers,
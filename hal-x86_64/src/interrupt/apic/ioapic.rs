@@ -1,9 +1,5 @@
 use super::{PinPolarity, TriggerMode};
-use crate::{
-    cpu::FeatureNotSupported,
-    interrupt::IsaInterrupt,
-    mm::{self, page, size::Size4Kb, PhysPage, VirtPage},
-};
+use crate::{cpu::FeatureNotSupported, interrupt::IsaInterrupt, mm::MapMmio};
 use hal_core::PAddr;
 use mycelium_util::{
     bits::{bitfield, enum_from_bits},
@@ -100,12 +96,7 @@ struct IsaOverride {
 // === impl IoApicSet ===
 
 impl IoApicSet {
-    pub fn new(
-        madt: &acpi::platform::interrupt::Apic,
-        frame_alloc: &impl hal_core::mem::page::Alloc<mm::size::Size4Kb>,
-        pagectrl: &mut crate::mm::PageCtrl,
-        isa_base: u8,
-    ) -> Self {
+    pub fn new(madt: &acpi::platform::interrupt::Apic, mmio: &impl MapMmio, isa_base: u8) -> Self {
         // The ACPI Multiple APIC Descriptor Table (MADT) tells us where to find
         // the I/O APICs, as well as information about how the ISA standard
         // interrupts are routed to I/O APIC inputs on this system.
@@ -131,8 +122,7 @@ impl IoApicSet {
         for (n, ioapic) in madt.io_apics.iter().enumerate() {
             let addr = PAddr::from_u64(ioapic.address as u64);
             tracing::debug!(ioapic.paddr = ?addr, "IOAPIC {n}");
-            this.ioapics
-                .push(Mutex::new(IoApic::new(addr, pagectrl, frame_alloc)));
+            this.ioapics.push(Mutex::new(IoApic::new(addr, mmio)));
         }
 
         // Okay, so here's where it gets ~*weird*~.
@@ -183,11 +173,17 @@ impl IoApicSet {
         //   so, neither inner loop actually loops that many times.
         // - finally, we only do this once on boot, so who cares?
 
+        // Send the ISA interrupts to the boot processor (which is the core
+        // we're running on), rather than broadcasting them to every core. The
+        // ISA interrupt handlers mustn't run on more than one core at a time.
+        let boot_apic_id = raw_cpuid::CpuId::new()
+            .get_feature_info()
+            .map_or(0, |info| info.initial_local_apic_id());
         let base_entry = RedirectionEntry::new()
             .with(RedirectionEntry::DELIVERY, DeliveryMode::Normal)
             .with(RedirectionEntry::REMOTE_IRR, false)
             .with(RedirectionEntry::MASKED, true)
-            .with(RedirectionEntry::DESTINATION, 0xff);
+            .with(RedirectionEntry::DESTINATION, boot_apic_id);
         for irq in IsaInterrupt::ALL {
             // Assume the IRQ is mapped to the I/O APIC pin corresponding to
             // that ISA IRQ number, and is active-high and edge-triggered.
@@ -312,41 +308,20 @@ impl IoApic {
     ///
     /// - `base_addr`: The [`PAddr`] of the I/O APIC's memory-mapped register
     ///   page.
-    /// - `pagectrl`: a [page mapper](page::Map) used to ensure that the MMIO
-    ///   register page is mapped and writable.
-    /// - `frame_alloc`: a [frame allocator](page::Alloc) used to allocate page
-    ///   frame(s) while mapping the MMIO register page.
+    /// - `mmio`: used to map the I/O APIC's memory-mapped registers.
     ///
     /// # Returns
     /// - `Some(IoApic)` if this CPU supports the APIC interrupt model.
     /// - `None` if this CPU does not support APIC interrupt handling.
-    pub fn try_new<A>(
-        base_paddr: PAddr,
-        pagectrl: &mut impl page::Map<Size4Kb, A>,
-        frame_alloc: &A,
-    ) -> Result<Self, FeatureNotSupported>
-    where
-        A: page::Alloc<Size4Kb>,
-    {
+    pub fn try_new(base_paddr: PAddr, mmio: &impl MapMmio) -> Result<Self, FeatureNotSupported> {
         if !super::is_supported() {
             tracing::warn!("tried to construct an IO APIC, but the CPU does not support the APIC interrupt model");
             return Err(FeatureNotSupported::new("APIC interrupt model"));
         }
 
-        let base = mm::kernel_vaddr_of(base_paddr);
-        tracing::debug!(?base, ?base_paddr, "found I/O APIC base address");
-
-        unsafe {
-            // ensure the I/O APIC's MMIO page is mapped and writable.
-            let virt = VirtPage::<Size4Kb>::containing_fixed(base);
-            let phys = PhysPage::<Size4Kb>::containing_fixed(base_paddr);
-            tracing::debug!(?virt, ?phys, "mapping I/O APIC MMIO page...");
-            pagectrl
-                .map_page(virt, phys, frame_alloc)
-                .set_writable(true)
-                .commit();
-            tracing::debug!("mapped I/O APIC MMIO page!");
-        }
+        tracing::debug!(?base_paddr, "mapping I/O APIC MMIO registers...");
+        let base = unsafe { mmio.map_mmio(base_paddr, core::mem::size_of::<MmioRegisters>()) };
+        tracing::debug!(?base, "mapped I/O APIC MMIO registers");
 
         let registers = unsafe { Volatile::new(&mut *base.as_mut_ptr::<MmioRegisters>()) };
         let mut ioapic = Self { registers };
@@ -361,11 +336,8 @@ impl IoApic {
     }
 
     #[inline]
-    pub fn new<A>(addr: PAddr, pagectrl: &mut impl page::Map<Size4Kb, A>, frame_alloc: &A) -> Self
-    where
-        A: page::Alloc<Size4Kb>,
-    {
-        Self::try_new(addr, pagectrl, frame_alloc).unwrap()
+    pub fn new(addr: PAddr, mmio: &impl MapMmio) -> Self {
+        Self::try_new(addr, mmio).unwrap()
     }
 
     /// Returns the IO APIC's ID.
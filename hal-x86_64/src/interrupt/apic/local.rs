@@ -1,10 +1,10 @@
 //! Local APIC
 pub use self::register::{ErrorStatus, Version};
-use self::register::{LvtTimer, TimerMode};
-use super::{PinPolarity, TriggerMode};
+use self::register::{IcrLow, IpiDelivery, LvtTimer, TimerMode};
+use super::{ioapic::DestinationMode, PinPolarity, TriggerMode};
 use crate::{
     cpu::{local, FeatureNotSupported, Msr},
-    mm::{self, page, size::Size4Kb, PhysPage, VirtPage},
+    mm::{page::StaticSize, size::Size4Kb, MapMmio},
     time::{Duration, InvalidDuration},
 };
 use core::{
@@ -12,9 +12,13 @@ use core::{
     convert::TryInto,
     marker::PhantomData,
     num::NonZeroU32,
+    sync::atomic::{AtomicUsize, Ordering},
 };
 use hal_core::{PAddr, VAddr};
-use mycelium_util::fmt;
+use mycelium_util::{
+    fmt,
+    sync::{blocking::Mutex, spin::Spinlock},
+};
 use raw_cpuid::CpuId;
 use register::TimerDivisor;
 use volatile::{access, Volatile};
@@ -79,6 +83,25 @@ pub trait RegisterAccess {
     ) -> Volatile<&'static mut Self::Target, Self::Access>;
 }
 
+/// The timer calibration measured by the first core to calibrate its local
+/// APIC timer.
+///
+/// Every core's local APIC timer runs at the same frequency, so the other
+/// cores reuse this calibration. They couldn't measure it themselves anyway:
+/// calibration uses the PIT, whose interrupts are only delivered to the boot
+/// processor.
+static CALIBRATION: Mutex<Option<TimerCalibration>, Spinlock> =
+    Mutex::new_with_raw_mutex(None, Spinlock::new());
+
+/// The physical address of the local APIC MMIO page that has been mapped, and
+/// the virtual address it was mapped at, so that cores which share it don't
+/// map it again.
+static MAPPED_BASE: Mutex<Option<(PAddr, VAddr)>, Spinlock> =
+    Mutex::new_with_raw_mutex(None, Spinlock::new());
+
+/// The number of CPU cores whose local APICs have been initialized.
+static ONLINE_CORES: AtomicUsize = AtomicUsize::new(0);
+
 #[derive(Debug, Eq, PartialEq, thiserror::Error)]
 pub enum LocalApicError {
     /// The system is configured to use the PIC interrupt model rather than the
@@ -106,45 +129,43 @@ impl LocalApic {
     ///
     /// # Arguments
     ///
-    /// - `pagectrl`: a [page mapper](page::Map) used to ensure that the local
-    ///   APIC's memory-mapped register page is mapped and writable.
-    /// - `frame_alloc`: a [frame allocator](page::Alloc) used to allocate page
-    ///   frame(s) while mapping the MMIO register page.
+    /// - `mmio`: used to map the local APIC's memory-mapped register page, if
+    ///   it hasn't been mapped by another core already.
     ///
     /// # Returns
     ///
     /// - [`Ok`]`(LocalApic)` if this CPU supports the APIC interrupt model.
     /// - [`Err`]`(`[`FeatureNotSupported`]`)` if this CPU does not support APIC
     ///   interrupt handling.
-    pub fn try_new<A>(
-        pagectrl: &mut impl page::Map<Size4Kb, A>,
-        frame_alloc: &A,
-    ) -> Result<Self, FeatureNotSupported>
-    where
-        A: page::Alloc<Size4Kb>,
-    {
+    pub fn try_new(mmio: &impl MapMmio) -> Result<Self, FeatureNotSupported> {
         if !super::is_supported() {
             return Err(FeatureNotSupported::new("APIC interrupt model"));
         }
 
         let msr = Msr::ia32_apic_base();
         let base_paddr = PAddr::from_u64(msr.read() & Self::BASE_PADDR_MASK);
-        let base = mm::kernel_vaddr_of(base_paddr);
-        tracing::debug!(?base, "found local APIC base address");
+        tracing::debug!(?base_paddr, "found local APIC base address");
+
+        // every core's local APIC is usually at the same address, so it only
+        // needs to be mapped once.
+        let base = {
+            let mut mapped = MAPPED_BASE.lock();
+            match *mapped {
+                Some((paddr, base)) if paddr == base_paddr => {
+                    tracing::debug!(?base, "local APIC MMIO page already mapped");
+                    base
+                }
+                _ => {
+                    tracing::debug!("mapping local APIC MMIO page...");
+                    let base = unsafe { mmio.map_mmio(base_paddr, Size4Kb::SIZE) };
+                    tracing::debug!(?base, "mapped local APIC MMIO page");
+                    *mapped = Some((base_paddr, base));
+                    base
+                }
+            }
+        };
         assert_ne!(base, VAddr::from_u64(0));
 
-        unsafe {
-            // ensure the local APIC's MMIO page is mapped and writable.
-            let virt = VirtPage::<Size4Kb>::containing_fixed(base);
-            let phys = PhysPage::<Size4Kb>::containing_fixed(base_paddr);
-            tracing::debug!(?virt, ?phys, "mapping local APIC MMIO page...");
-            pagectrl
-                .map_page(virt, phys, frame_alloc)
-                .set_writable(true)
-                .commit();
-            tracing::debug!("mapped local APIC MMIO page");
-        }
-
         Ok(Self {
             msr,
             base,
@@ -154,11 +175,8 @@ impl LocalApic {
 
     #[must_use]
     #[inline]
-    pub fn new<A>(pagectrl: &mut impl page::Map<Size4Kb, A>, frame_alloc: &A) -> Self
-    where
-        A: page::Alloc<Size4Kb>,
-    {
-        Self::try_new(pagectrl, frame_alloc).unwrap()
+    pub fn new(mmio: &impl MapMmio) -> Self {
+        Self::try_new(mmio).unwrap()
     }
 
     pub fn enable(&self, spurious_vector: u8) {
@@ -199,10 +217,35 @@ impl LocalApic {
                 return;
             }
         }
-        self.timer_calibration.set(Some(TimerCalibration {
-            frequency_hz: self.calibrate_frequency_hz(divisor),
-            divisor,
-        }));
+
+        let calibration = {
+            let mut shared = CALIBRATION.lock();
+            match *shared {
+                Some(calibration) if calibration.divisor == divisor => {
+                    tracing::debug!(
+                        ?divisor,
+                        frequency_hz = calibration.frequency_hz,
+                        "reusing local APIC timer calibration"
+                    );
+                    calibration
+                }
+                _ => {
+                    let calibration = TimerCalibration {
+                        frequency_hz: self.calibrate_frequency_hz(divisor),
+                        divisor,
+                    };
+                    *shared = Some(calibration);
+                    calibration
+                }
+            }
+        };
+        self.timer_calibration.set(Some(calibration));
+    }
+
+    /// Returns this local APIC's ID.
+    pub fn id(&self) -> u8 {
+        let id = unsafe { self.register(register::ID).read() };
+        (id >> 24) as u8
     }
 
     /// Reads the local APIC's version register.
@@ -482,6 +525,72 @@ impl LocalApic {
         self.register(register::END_OF_INTERRUPT).write(0);
     }
 
+    /// Sends an INIT inter-processor interrupt (IPI) to the processor whose
+    /// local APIC has the ID `apic_id`, putting it in the wait-for-SIPI state.
+    ///
+    /// # Safety
+    ///
+    /// This resets the target processor, so it must not be running anything.
+    pub unsafe fn send_init_ipi(&self, apic_id: u8) {
+        let init = IcrLow::new()
+            .with(IcrLow::DELIVERY, IpiDelivery::Init)
+            .with(IcrLow::TRIGGER, TriggerMode::Level);
+        self.send_ipi(apic_id, init.with(IcrLow::ASSERT, true));
+        // older processors also require an INIT level de-assert IPI.
+        self.send_ipi(apic_id, init.with(IcrLow::ASSERT, false));
+    }
+
+    /// Sends a startup inter-processor interrupt (SIPI) to the processor whose
+    /// local APIC has the ID `apic_id`, which starts executing real-mode code
+    /// at the physical address `page * 4096`.
+    ///
+    /// Per the Intel SDM, Vol. 3A, Section 8.4.4.1, the target processor must
+    /// first be sent an [INIT IPI](Self::send_init_ipi), and a SIPI may need
+    /// to be sent twice.
+    ///
+    /// # Safety
+    ///
+    /// `page` must contain code that can start a processor.
+    pub unsafe fn send_startup_ipi(&self, apic_id: u8, page: u8) {
+        let sipi = IcrLow::new()
+            .with(IcrLow::VECTOR, page)
+            .with(IcrLow::DELIVERY, IpiDelivery::Startup)
+            .with(IcrLow::ASSERT, true);
+        self.send_ipi(apic_id, sipi);
+    }
+
+    /// Sends a fixed inter-processor interrupt with the given `vector` to
+    /// every processor except this one.
+    ///
+    /// # Safety
+    ///
+    /// Every other processor must have an ISR for `vector`.
+    pub unsafe fn send_ipi_to_others(&self, vector: u8) {
+        let ipi = IcrLow::new()
+            .with(IcrLow::VECTOR, vector)
+            .with(IcrLow::DELIVERY, IpiDelivery::Fixed)
+            .with(IcrLow::ASSERT, true)
+            .with(IcrLow::SHORTHAND, IpiShorthand::AllExcludingSelf);
+        self.send_ipi(0, ipi);
+    }
+
+    unsafe fn send_ipi(&self, apic_id: u8, icr: IcrLow) {
+        tracing::trace!(apic_id, %icr, "sending IPI");
+        // writing the low half of the ICR sends the IPI, so the destination
+        // must be written first.
+        self.register(register::ICR_HIGH)
+            .write((apic_id as u32) << 24);
+        self.register(register::ICR_LOW).write(icr);
+
+        while self
+            .register(register::ICR_LOW)
+            .read()
+            .get(IcrLow::SEND_PENDING)
+        {
+            core::hint::spin_loop();
+        }
+    }
+
     /// Reads the error stauts register (`ESR`) of the local APIC.
     ///
     /// Calling this method resets the value of the error status register. Any
@@ -550,6 +659,11 @@ impl LocalApic {
     }
 }
 
+/// Returns the number of CPU cores whose local APICs have been initialized.
+pub(crate) fn online_cores() -> usize {
+    ONLINE_CORES.load(Ordering::Acquire)
+}
+
 impl Handle {
     pub(in crate::interrupt) const fn new() -> Self {
         Self(local::LocalKey::new(|| RefCell::new(None)))
@@ -567,23 +681,17 @@ impl Handle {
         })
     }
 
-    pub(in crate::interrupt) unsafe fn initialize<A>(
-        &self,
-        frame_alloc: &A,
-        pagectrl: &mut impl page::Map<Size4Kb, A>,
-        spurious_vector: u8,
-    ) where
-        A: page::Alloc<Size4Kb>,
-    {
+    pub(in crate::interrupt) unsafe fn initialize(&self, mmio: &impl MapMmio, spurious_vector: u8) {
         self.0.with(|slot| {
             let mut slot = slot.borrow_mut();
             if slot.is_some() {
                 // already initialized, bail.
                 return;
             }
-            let apic = LocalApic::new(pagectrl, frame_alloc);
+            let apic = LocalApic::new(mmio);
             apic.enable(spurious_vector);
             *slot = Some(apic);
+            ONLINE_CORES.fetch_add(1, Ordering::AcqRel);
         })
     }
 }
@@ -716,7 +824,18 @@ pub mod register {
         /// *Access**: read/write
         LVT_CMCI = 0x2f0, ReadWrite;
 
-        ICR_LOW = 0x300, ReadWrite;
+        /// Interrupt Command Register (ICR), low half
+        ///
+        /// Writing this register sends an inter-processor interrupt.
+        ///
+        /// **Access**: read/write
+        ICR_LOW<IcrLow> = 0x300, ReadWrite;
+
+        /// Interrupt Command Register (ICR), high half
+        ///
+        /// Bits 24-31 contain the destination APIC ID.
+        ///
+        /// **Access**: read/write
         ICR_HIGH = 0x310, ReadWrite;
 
         LVT_TIMER<LvtTimer> = 0x320, ReadWrite;
@@ -782,6 +901,64 @@ pub mod register {
         }
     }
 
+    bitfield! {
+        /// Value of the low half of the Interrupt Command Register (ICR).
+        ///
+        /// See Intel SDM Vol. 3A, Ch. 10, Section 10.6.1, "Interrupt Command
+        /// Register (ICR)".
+        pub struct IcrLow<u32> {
+            /// The vector of the interrupt, or the page number of the startup
+            /// code for a SIPI.
+            pub const VECTOR: u8;
+            pub const DELIVERY: IpiDelivery;
+            pub const DEST_MODE: DestinationMode;
+            /// Set while the IPI has not yet been accepted by its destination.
+            /// Read only.
+            pub const SEND_PENDING: bool;
+            const _RESERVED_0 = 1;
+            /// Clear only for an INIT level de-assert IPI.
+            pub const ASSERT: bool;
+            pub const TRIGGER: TriggerMode;
+            const _RESERVED_1 = 2;
+            /// Sends the IPI to a set of processors, rather than the one in the
+            /// ICR's destination field.
+            pub const SHORTHAND: IpiShorthand;
+        }
+    }
+
+    enum_from_bits! {
+        #[derive(Debug, Eq, PartialEq)]
+        pub enum IpiDelivery<u8> {
+            /// Delivers the interrupt in the vector field.
+            Fixed = 0b000,
+            /// Delivers the interrupt in the vector field to the processor
+            /// with the lowest priority.
+            LowestPriority = 0b001,
+            /// System Management Interrupt (SMI).
+            SystemManagement = 0b010,
+            /// Non-Maskable Interrupt (NMI).
+            NonMaskable = 0b100,
+            /// Resets the destination processor to the wait-for-SIPI state.
+            Init = 0b101,
+            /// Startup IPI (SIPI).
+            Startup = 0b110,
+        }
+    }
+
+    enum_from_bits! {
+        #[derive(Debug, Eq, PartialEq)]
+        pub enum IpiShorthand<u8> {
+            /// Send the IPI to the processor in the destination field.
+            None = 0b00,
+            /// Send the IPI to the sending processor.
+            ToSelf = 0b01,
+            /// Send the IPI to every processor, including the sender.
+            All = 0b10,
+            /// Send the IPI to every processor except the sender.
+            AllExcludingSelf = 0b11,
+        }
+    }
+
     enum_from_bits! {
         #[derive(Debug, Eq, PartialEq)]
         pub enum TimerMode<u8> {
@@ -924,6 +1101,35 @@ mod tests {
         register::LvtTimer::assert_valid();
     }
 
+    #[test]
+    fn icr_low_is_valid() {
+        register::IcrLow::assert_valid();
+    }
+
+    #[test]
+    fn icr_low_offsets() {
+        assert_eq!(
+            register::IcrLow::DELIVERY.least_significant_index(),
+            8,
+            "delivery mode LSB"
+        );
+        assert_eq!(
+            register::IcrLow::SEND_PENDING.least_significant_index(),
+            12,
+            "send pending"
+        );
+        assert_eq!(
+            register::IcrLow::ASSERT.least_significant_index(),
+            14,
+            "level assert"
+        );
+        assert_eq!(
+            register::IcrLow::SHORTHAND.least_significant_index(),
+            18,
+            "shorthand LSB"
+        );
+    }
+
     #[test]
     fn lvt_timer_offsets() {
         assert_eq!(
@@ -160,6 +160,10 @@ impl Idt {
     /// Base offset for ISA hardware interrupts.
     pub const ISA_BASE: usize = 0x20;
 
+    /// Inter-processor interrupt vector used to invalidate pages in other
+    /// CPU cores' TLBs when a page mapping is changed.
+    pub const TLB_SHOOTDOWN: usize = (Self::NUM_VECTORS - 3);
+
     /// Local APIC timer interrupt vector mapped by
     /// [`Controller::enable_hardware_interrupts`].
     ///
@@ -14,12 +14,29 @@ use hal_core::{
     Address,
 };
 use mycelium_util::fmt;
+use raw_cpuid::CpuId;
 pub const MIN_PAGE_SIZE: usize = Size4Kb::SIZE;
 const ENTRIES: usize = 512;
 
 pub type VirtPage<S> = Page<VAddr, S>;
 pub type PhysPage<S> = Page<PAddr, S>;
 
+/// Maps memory-mapped I/O registers into the kernel's address space.
+///
+/// The kernel decides where MMIO registers are mapped, so drivers in this
+/// crate that need their registers mapped, such as the local and I/O APICs,
+/// are given an implementation of this trait.
+pub trait MapMmio {
+    /// Maps the `size` bytes of MMIO registers at `paddr` writable and
+    /// uncached, returning the virtual address `paddr` was mapped at.
+    ///
+    /// # Safety
+    ///
+    /// `paddr` must be the address of MMIO registers, and not RAM that is used
+    /// for something else.
+    unsafe fn map_mmio(&self, paddr: PAddr, size: usize) -> VAddr;
+}
+
 pub struct PageCtrl {
     pml4: NonNull<PageTable<level::Pml4>>,
 }
@@ -123,6 +140,97 @@ pub fn vm_offset() -> VAddr {
     VAddr::from_usize(off)
 }
 
+/// Returns `true` if this CPU supports mapping 1 GiB pages.
+pub fn supports_1gb_pages() -> bool {
+    CpuId::new()
+        .get_extended_processor_and_feature_identifiers()
+        .map(|features| features.has_1gib_pages())
+        .unwrap_or(false)
+}
+
+/// Maps `region` of physical memory into the kernel's physical memory map,
+/// at [`kernel_vaddr_of`] its base address, returning the number of bytes
+/// that were newly mapped.
+///
+/// Each part of the region is mapped with the largest page that fits it: 1
+/// GiB pages if the CPU [supports them](supports_1gb_pages), then 2 MiB
+/// pages, and 4 KiB pages for the ends of the region that aren't aligned to a
+/// larger page. Parts of the region that are already mapped (such as by the
+/// bootloader) are left alone.
+///
+/// Pages are mapped writable and cacheable, so `region` must be RAM rather
+/// than MMIO. Page tables are allocated from `frame_alloc`.
+pub fn map_physical_memory<A>(
+    pagectrl: &mut PageCtrl,
+    region: &hal_core::mem::Region,
+    frame_alloc: &A,
+) -> usize
+where
+    A: page::Alloc<Size4Kb> + page::Alloc<Size2Mb> + page::Alloc<Size1Gb>,
+{
+    let _span = tracing::debug_span!("map_physical_memory", ?region).entered();
+    let has_1gb_pages = supports_1gb_pages();
+    let end = region.end_addr().align_up(Size4Kb::SIZE);
+    let mut paddr = region.base_addr().align_down(Size4Kb::SIZE);
+    let mut mapped = [0usize; 3];
+
+    while paddr < end {
+        let vaddr = kernel_vaddr_of(paddr);
+        let unmapped = match pagectrl.mapping_of(vaddr) {
+            Ok(size) => {
+                // skip to the end of the page that already maps this address.
+                let size = size.as_usize();
+                paddr = paddr.offset((size - vaddr.as_usize() % size) as isize);
+                continue;
+            }
+            Err(unmapped) => unmapped.as_usize(),
+        };
+        let remaining = paddr.difference(end) as usize;
+        let fits = |size: usize| {
+            unmapped >= size
+                && remaining >= size
+                && paddr.is_aligned(size)
+                && vaddr.is_aligned(size)
+        };
+
+        let size = unsafe {
+            if has_1gb_pages && fits(Size1Gb::SIZE) {
+                let virt = VirtPage::<Size1Gb>::containing_fixed(vaddr);
+                let phys = PhysPage::<Size1Gb>::containing_fixed(paddr);
+                pagectrl
+                    .map_page(virt, phys, frame_alloc)
+                    .set_writable(true)
+                    .commit();
+                mapped[2] += 1;
+                Size1Gb::SIZE
+            } else if fits(Size2Mb::SIZE) {
+                let virt = VirtPage::<Size2Mb>::containing_fixed(vaddr);
+                let phys = PhysPage::<Size2Mb>::containing_fixed(paddr);
+                pagectrl
+                    .map_page(virt, phys, frame_alloc)
+                    .set_writable(true)
+                    .commit();
+                mapped[1] += 1;
+                Size2Mb::SIZE
+            } else {
+                let virt = VirtPage::<Size4Kb>::containing_fixed(vaddr);
+                let phys = PhysPage::<Size4Kb>::containing_fixed(paddr);
+                pagectrl
+                    .map_page(virt, phys, frame_alloc)
+                    .set_writable(true)
+                    .commit();
+                mapped[0] += 1;
+                Size4Kb::SIZE
+            }
+        };
+        paddr = paddr.offset(size as isize);
+    }
+
+    let [pages_4k, pages_2m, pages_1g] = mapped;
+    tracing::debug!(pages_4k, pages_2m, pages_1g, "mapped physical memory");
+    pages_4k * Size4Kb::SIZE + pages_2m * Size2Mb::SIZE + pages_1g * Size1Gb::SIZE
+}
+
 impl PageTable<level::Pml4> {
     fn current(vm_offset: VAddr) -> &'static mut Self {
         let (phys, _) = crate::control_regs::cr3::read();
@@ -165,27 +273,120 @@ where
             .create_next_table(virt, frame_alloc);
         tracing::debug!(?page_table);
 
-        let entry = &mut page_table[virt];
-        tracing::trace!(?entry);
+        page_table[virt].map(virt, phys)
+    }
+
+    /// # Panics
+    ///
+    /// - If the virtual page is not mapped.
+    fn flags_mut(&mut self, virt: Page<VAddr, Size4Kb>) -> page::Handle<'_, Size4Kb, Self::Entry> {
+        let _span = tracing::debug_span!("flags_mut", ?virt).entered();
+        self.page_table_mut(virt)[virt].flags_mut(virt)
+    }
+
+    /// # Safety
+    ///
+    /// Unmapping a page can break pretty much everything.
+    unsafe fn unmap(&mut self, virt: Page<VAddr, Size4Kb>) -> Page<PAddr, Size4Kb> {
+        let _span = tracing::debug_span!("unmap", ?virt).entered();
+        self.page_table_mut(virt)[virt].unmap(virt)
+    }
+}
+
+impl<A> Map<Size2Mb, A> for PageCtrl
+where
+    A: page::Alloc<Size2Mb> + page::Alloc<Size4Kb>,
+{
+    type Entry = Entry<level::Pd>;
+
+    /// Maps a 2 MiB page. Any page tables needed to map it are allocated as
+    /// 4 KiB frames from `frame_alloc`.
+    ///
+    /// # Panics
+    ///
+    /// - If `virt` is already mapped, including by a page table of 4 KiB
+    ///   pages.
+    /// - If `virt` is part of a 1 GiB page.
+    unsafe fn map_page(
+        &mut self,
+        virt: Page<VAddr, Size2Mb>,
+        phys: Page<PAddr, Size2Mb>,
+        frame_alloc: &A,
+    ) -> page::Handle<'_, Size2Mb, Self::Entry> {
+        let _span = tracing::debug_span!("map_page", ?virt, ?phys).entered();
+        let page_directory = self
+            .pml4
+            .as_mut()
+            .create_next_table(virt, frame_alloc)
+            .create_next_table(virt, frame_alloc);
+        tracing::debug!(?page_directory);
+
+        page_directory[virt].map(virt, phys)
+    }
+
+    /// # Panics
+    ///
+    /// - If the virtual page is not mapped by a 2 MiB page.
+    fn flags_mut(&mut self, virt: Page<VAddr, Size2Mb>) -> page::Handle<'_, Size2Mb, Self::Entry> {
+        let _span = tracing::debug_span!("flags_mut", ?virt).entered();
+        self.page_directory_mut(virt)[virt].flags_mut(virt)
+    }
+
+    /// # Safety
+    ///
+    /// Unmapping a page can break pretty much everything.
+    unsafe fn unmap(&mut self, virt: Page<VAddr, Size2Mb>) -> Page<PAddr, Size2Mb> {
+        let _span = tracing::debug_span!("unmap", ?virt).entered();
+        self.page_directory_mut(virt)[virt].unmap(virt)
+    }
+}
+
+impl<A> Map<Size1Gb, A> for PageCtrl
+where
+    A: page::Alloc<Size1Gb> + page::Alloc<Size4Kb>,
+{
+    type Entry = Entry<level::Pdpt>;
+
+    /// Maps a 1 GiB page. Any page tables needed to map it are allocated as
+    /// 4 KiB frames from `frame_alloc`.
+    ///
+    /// # Panics
+    ///
+    /// - If the CPU doesn't support 1 GiB pages (see
+    ///   [`supports_1gb_pages`]).
+    /// - If `virt` is already mapped, including by a page directory of
+    ///   smaller pages.
+    unsafe fn map_page(
+        &mut self,
+        virt: Page<VAddr, Size1Gb>,
+        phys: Page<PAddr, Size1Gb>,
+        frame_alloc: &A,
+    ) -> page::Handle<'_, Size1Gb, Self::Entry> {
+        let _span = tracing::debug_span!("map_page", ?virt, ?phys).entered();
         assert!(
-            !entry.is_present(),
-            "mapped page table entry already in use"
+            supports_1gb_pages(),
+            "cannot map {virt:?}: this CPU does not support 1GB pages"
         );
-        assert!(!entry.is_huge(), "huge bit should not be set for 4KB entry");
-        let entry = entry.set_phys_page(phys).set_present(true);
-        tracing::trace!(?entry, "flags set");
-        page::Handle::new(virt, entry)
+        let pdpt = self.pml4.as_mut().create_next_table(virt, frame_alloc);
+        tracing::debug!(?pdpt);
+
+        pdpt[virt].map(virt, phys)
     }
 
-    fn flags_mut(&mut self, _virt: Page<VAddr, Size4Kb>) -> page::Handle<'_, Size4Kb, Self::Entry> {
-        unimplemented!()
+    /// # Panics
+    ///
+    /// - If the virtual page is not mapped by a 1 GiB page.
+    fn flags_mut(&mut self, virt: Page<VAddr, Size1Gb>) -> page::Handle<'_, Size1Gb, Self::Entry> {
+        let _span = tracing::debug_span!("flags_mut", ?virt).entered();
+        self.pdpt_mut(virt)[virt].flags_mut(virt)
     }
 
     /// # Safety
     ///
     /// Unmapping a page can break pretty much everything.
-    unsafe fn unmap(&mut self, _virt: Page<VAddr, Size4Kb>) -> Page<PAddr, Size4Kb> {
-        unimplemented!()
+    unsafe fn unmap(&mut self, virt: Page<VAddr, Size1Gb>) -> Page<PAddr, Size1Gb> {
+        let _span = tracing::debug_span!("unmap", ?virt).entered();
+        self.pdpt_mut(virt)[virt].unmap(virt)
     }
 }
 
@@ -200,6 +401,56 @@ where
 }
 
 impl PageCtrl {
+    /// Returns the PDPT that maps `virt`.
+    ///
+    /// # Panics
+    ///
+    /// - If no PDPT maps `virt`.
+    fn pdpt_mut<S: Size>(&mut self, virt: Page<VAddr, S>) -> &mut PageTable<level::Pdpt> {
+        let pml4 = unsafe { self.pml4.as_mut() };
+        next_table_of(pml4, virt)
+    }
+
+    /// Returns the page directory that maps `virt`.
+    ///
+    /// # Panics
+    ///
+    /// - If no page directory maps `virt`, or it's part of a 1 GiB page.
+    fn page_directory_mut<S: Size>(&mut self, virt: Page<VAddr, S>) -> &mut PageTable<level::Pd> {
+        next_table_of(self.pdpt_mut(virt), virt)
+    }
+
+    /// Returns the page table that maps `virt`.
+    ///
+    /// # Panics
+    ///
+    /// - If no page table maps `virt`, or it's part of a huge page.
+    fn page_table_mut(&mut self, virt: Page<VAddr, Size4Kb>) -> &mut PageTable<level::Pt> {
+        next_table_of(self.page_directory_mut(virt), virt)
+    }
+
+    /// Returns the size of the page that maps `virt` if it's mapped, or the
+    /// size of the largest unmapped region containing it that a single page
+    /// table entry covers if it isn't.
+    fn mapping_of(&self, virt: VAddr) -> Result<AnySize, AnySize> {
+        let virt = VirtPage::<Size4Kb>::containing_fixed(virt);
+        let pml4 = unsafe { self.pml4.as_ref() };
+        let pdpt = pml4.next_table(virt).ok_or(AnySize::Size1Gb)?;
+        if pdpt[virt].is_present() && pdpt[virt].is_huge() {
+            return Ok(AnySize::Size1Gb);
+        }
+        let page_directory = pdpt.next_table(virt).ok_or(AnySize::Size1Gb)?;
+        if page_directory[virt].is_present() && page_directory[virt].is_huge() {
+            return Ok(AnySize::Size2Mb);
+        }
+        let page_table = page_directory.next_table(virt).ok_or(AnySize::Size2Mb)?;
+        if page_table[virt].is_present() {
+            Ok(AnySize::Size4Kb)
+        } else {
+            Err(AnySize::Size4Kb)
+        }
+    }
+
     pub fn current() -> Self {
         let vm_offset = VM_OFFSET.load(Ordering::Acquire);
         assert_ne!(
@@ -215,6 +466,26 @@ impl PageCtrl {
     }
 }
 
+/// Returns the next table below `table` that maps `virt`.
+///
+/// # Panics
+///
+/// - If `virt` isn't mapped by `table`, or it's part of a huge page mapped by
+///   `table`.
+fn next_table_of<L: level::Recursive, S: Size>(
+    table: &mut PageTable<L>,
+    virt: Page<VAddr, S>,
+) -> &mut PageTable<L::Next> {
+    assert!(
+        !table[virt].is_huge(),
+        "{virt:?} is part of a huge page mapped by the {}",
+        L::NAME
+    );
+    table
+        .next_table_mut(virt)
+        .unwrap_or_else(|| panic!("{virt:?} is not mapped: no {} entry", L::NAME))
+}
+
 impl<S> TranslatePage<S> for PageTable<level::Pml4>
 where
     S: Size,
@@ -449,6 +720,10 @@ impl<L> Entry<L> {
     const DIRTY: u64 = 1 << 6;
     const HUGE: u64 = 1 << 7;
     const GLOBAL: u64 = 1 << 8;
+    /// Ignored by the CPU. Set by [`Entry::flags_mut`], so that committing
+    /// the changed flags shoots down the stale TLB entries for the page on
+    /// every core, rather than only flushing it on this one.
+    const SHOOTDOWN: u64 = 1 << 9;
     const NOEXEC: u64 = 1 << 63;
 
     const ADDR_MASK: u64 = 0x000f_ffff_ffff_f000;
@@ -489,7 +764,17 @@ impl<L> Entry<L> {
         self
     }
 
-    #[allow(dead_code)] // we'll need this later
+    /// Disables caching of the page entirely, rather than only write-back
+    /// caching, if `cached` is `false`.
+    fn set_cached(&mut self, cached: bool) -> &mut Self {
+        if cached {
+            self.entry &= !(Self::CACHE_DISABLE | Self::WRITE_THROUGH);
+        } else {
+            self.entry |= Self::CACHE_DISABLE | Self::WRITE_THROUGH;
+        }
+        self
+    }
+
     fn set_huge(&mut self, huge: bool) -> &mut Self {
         if huge {
             self.entry |= Self::HUGE;
@@ -522,6 +807,10 @@ impl<L> Entry<L> {
         self.entry & Self::WRITABLE != 0
     }
 
+    fn is_cached(&self) -> bool {
+        self.entry & Self::CACHE_DISABLE == 0
+    }
+
     fn phys_addr(&self) -> PAddr {
         PAddr::from_u64(self.entry & Self::ADDR_MASK)
     }
@@ -544,6 +833,53 @@ impl<L: level::PointsToPage> Entry<L> {
         self.set_phys_addr(page.base_addr());
         self
     }
+
+    /// Maps `virt`, which this entry is the entry for, to `phys`.
+    ///
+    /// # Panics
+    ///
+    /// - If the entry is already in use.
+    fn map(
+        &mut self,
+        virt: Page<VAddr, L::Size>,
+        phys: Page<PAddr, L::Size>,
+    ) -> page::Handle<'_, L::Size, Self> {
+        tracing::trace!(entry = ?self);
+        assert!(
+            !self.is_present(),
+            "mapped {} entry already in use",
+            L::NAME
+        );
+        self.set_phys_page(phys)
+            .set_huge(L::IS_HUGE)
+            .set_present(true);
+        tracing::trace!(entry = ?self, "flags set");
+        page::Handle::new(virt, self)
+    }
+
+    /// # Panics
+    ///
+    /// - If this entry doesn't map a page of size `L::Size`.
+    fn flags_mut(&mut self, virt: Page<VAddr, L::Size>) -> page::Handle<'_, L::Size, Self> {
+        if let Err(error) = self.phys_page() {
+            panic!("cannot change flags of {virt:?}: {error}");
+        }
+        self.entry |= Self::SHOOTDOWN;
+        page::Handle::new(virt, self)
+    }
+
+    /// # Panics
+    ///
+    /// - If this entry doesn't map a page of size `L::Size`.
+    unsafe fn unmap(&mut self, virt: Page<VAddr, L::Size>) -> Page<PAddr, L::Size> {
+        let phys = self
+            .phys_page()
+            .unwrap_or_else(|error| panic!("cannot unmap {virt:?}: {error}"));
+        *self = Entry::none();
+        tracing::trace!(?phys, "unmapped");
+        tlb::shootdown_page(virt.base_addr());
+        phys
+    }
 }
 
 impl<L: level::PointsToPage> page::PageFlags<L::Size> for Entry<L> {
@@ -577,9 +913,26 @@ impl<L: level::PointsToPage> page::PageFlags<L::Size> for Entry<L> {
         self.set_present(present);
     }
 
+    #[inline]
+    fn is_cached(&self) -> bool {
+        self.is_cached()
+    }
+
+    #[inline]
+    unsafe fn set_cached(&mut self, cached: bool) {
+        self.set_cached(cached);
+    }
+
     fn commit(&mut self, page: Page<VAddr, L::Size>) {
+        // other cores may only have cached the page's translation if it was
+        // already mapped, so a new mapping is only flushed on this core.
         unsafe {
-            tlb::flush_page(page.base_addr());
+            if self.entry & Self::SHOOTDOWN != 0 {
+                self.entry &= !Self::SHOOTDOWN;
+                tlb::shootdown_page(page.base_addr());
+            } else {
+                tlb::flush_page(page.base_addr());
+            }
         }
     }
 }
@@ -860,9 +1213,22 @@ pub mod level {
 
 pub(crate) mod tlb {
     use crate::control_regs::cr3;
+    use crate::interrupt::{apic::local, Controller, Idt};
     use crate::VAddr;
-    use core::arch::asm;
+    use core::{
+        arch::asm,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
     use hal_core::Address;
+    use mycelium_util::sync::{blocking::Mutex, spin::Spinlock};
+
+    /// Held by the core that's currently shooting down a page, since there's
+    /// only room for one page at a time in `SHOOTDOWN_ADDR`.
+    static SHOOTDOWN_LOCK: Mutex<(), Spinlock> = Mutex::new_with_raw_mutex((), Spinlock::new());
+    /// The address of the page being shot down.
+    static SHOOTDOWN_ADDR: AtomicUsize = AtomicUsize::new(0);
+    /// The number of cores that have yet to flush the page being shot down.
+    static SHOOTDOWN_PENDING: AtomicUsize = AtomicUsize::new(0);
 
     #[allow(dead_code)] // we'll need this later
     pub(crate) unsafe fn flush_all() {
@@ -877,6 +1243,53 @@ pub(crate) mod tlb {
         tracing::trace!(?addr, "flush_page");
         asm!("invlpg [{0}]", in(reg) addr.as_usize() as u64);
     }
+
+    /// Flushes the page containing `addr` from the TLB of every CPU core.
+    ///
+    /// If other cores are running, this sends them a
+    /// [`TLB_SHOOTDOWN`](Idt::TLB_SHOOTDOWN) IPI and waits for all of them to
+    /// flush the page. While APs are running, this must therefore be called
+    /// with interrupts enabled: otherwise, two cores shooting down pages at
+    /// the same time would wait on each other forever.
+    pub(crate) unsafe fn shootdown_page(addr: VAddr) {
+        flush_page(addr);
+
+        let others = local::online_cores().saturating_sub(1);
+        if others == 0 {
+            return;
+        }
+        let Some(controller) = Controller::try_get() else {
+            return;
+        };
+
+        let _lock = SHOOTDOWN_LOCK.lock();
+        SHOOTDOWN_ADDR.store(addr.as_usize(), Ordering::Release);
+        SHOOTDOWN_PENDING.store(others, Ordering::Release);
+        let sent = controller.with_local_apic(|apic| {
+            apic.send_ipi_to_others(Idt::TLB_SHOOTDOWN as u8);
+        });
+        if let Err(error) = sent {
+            // if this core's local APIC isn't initialized, it can't be running
+            // alongside the others yet.
+            tracing::warn!(%error, ?addr, "could not send TLB shootdown IPI");
+            SHOOTDOWN_PENDING.store(0, Ordering::Release);
+            return;
+        }
+
+        while SHOOTDOWN_PENDING.load(Ordering::Acquire) != 0 {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Handles a [`TLB_SHOOTDOWN`](Idt::TLB_SHOOTDOWN) IPI sent by
+    /// [`shootdown_page`] on another core.
+    pub(crate) fn handle_shootdown() {
+        let addr = VAddr::from_usize(SHOOTDOWN_ADDR.load(Ordering::Acquire));
+        unsafe {
+            flush_page(addr);
+        }
+        SHOOTDOWN_PENDING.fetch_sub(1, Ordering::AcqRel);
+    }
 }
 
 mycotest::decl_test! {
@@ -920,3 +1333,30 @@ mycotest::decl_test! {
         Ok(())
     }
 }
+
+mycotest::decl_test! {
+    fn unmap_and_flags_mut() -> mycotest::TestResult {
+        let mut ctrl = PageCtrl::current();
+
+        // We shouldn't need to allocate page frames for this test.
+        let frame_alloc = page::EmptyAlloc::default();
+        let frame = Page::containing_fixed(PAddr::from_usize(0xb8000));
+        let page = VirtPage::<Size4Kb>::containing_fixed(VAddr::from_usize(0x1000));
+        unsafe {
+            ctrl.map_page(page, frame, &frame_alloc).set_writable(true).commit()
+        };
+
+        // `flags_mut` and `unmap` don't take an allocator, so the allocator
+        // type can't be inferred.
+        let flags = Map::<Size4Kb, page::EmptyAlloc>::flags_mut(&mut ctrl, page);
+        mycotest::assert!(flags.is_writable());
+        unsafe { flags.set_writable(false).commit() };
+        let flags = Map::<Size4Kb, page::EmptyAlloc>::flags_mut(&mut ctrl, page);
+        mycotest::assert!(!flags.is_writable(), "page should no longer be writable");
+
+        let unmapped = unsafe { Map::<Size4Kb, page::EmptyAlloc>::unmap(&mut ctrl, page) };
+        mycotest::assert_eq!(unmapped, frame, "unmap should return the mapped frame");
+        mycotest::assert!(ctrl.translate_page(page).is_err(), "page should no longer be mapped");
+        Ok(())
+    }
+}
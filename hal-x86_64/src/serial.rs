@@ -34,6 +34,9 @@ pub fn com4() -> Option<&'static Port> {
 // #[derive(Debug)]
 pub struct Port {
     inner: Mutex<Registers, Spinlock>,
+    /// The port's base I/O address, used to read received data without
+    /// locking the port.
+    base: u16,
 }
 
 // #[derive(Debug)]
@@ -115,6 +118,7 @@ impl Port {
 
         Ok(Self {
             inner: Mutex::new_with_raw_mutex(registers, Spinlock::new()),
+            base: port,
         })
     }
 
@@ -128,6 +132,34 @@ impl Port {
         }
     }
 
+    /// Reads every byte waiting in the port's receive buffer, calling `f`
+    /// with each one.
+    ///
+    /// This doesn't lock the port, so that it can be called from the port's
+    /// interrupt handler even if the interrupted code holds the lock to write
+    /// to the port. Reading the receive buffer doesn't disturb a write in
+    /// progress, as the transmit and receive registers are separate. If the
+    /// interrupted code is changing the port's baud rate, though, the data
+    /// register is temporarily the divisor latch, so nothing is read.
+    ///
+    /// # Safety
+    ///
+    /// This may only be called from the port's interrupt handler, so that
+    /// nothing else reads from the port at the same time.
+    pub unsafe fn drain_received(&self, mut f: impl FnMut(u8)) {
+        let data = cpu::Port::at(self.base);
+        let line_ctrl = cpu::Port::at(self.base + 3);
+        let status = cpu::Port::at(self.base + 5);
+
+        if line_ctrl.readb() & Registers::DLAB_BIT != 0 {
+            return;
+        }
+
+        while status.readb() & 1 != 0 {
+            f(data.readb());
+        }
+    }
+
     /// Forcibly unlock the serial port, releasing any locks held by other cores
     /// or in other functions.
     ///
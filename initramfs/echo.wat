@@ -0,0 +1,60 @@
+;; Prints its arguments to stdout, separated by spaces.
+;;
+;; run echo hello world
+
+(module
+    ;; (*argc, *argv_buf_size) -> errno
+    (import "wasi_snapshot_preview1" "args_sizes_get" (func $args_sizes_get (param i32 i32) (result i32)))
+    ;; (**argv, *argv_buf) -> errno
+    (import "wasi_snapshot_preview1" "args_get" (func $args_get (param i32 i32) (result i32)))
+    ;; (fd, *iovs, iovs_len, *nwritten) -> errno
+    (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+
+    (memory 1)
+    (export "memory" (memory 0))
+
+    ;; Memory layout:
+    ;;   0: argc
+    ;;   4: argv_buf_size
+    ;;   8: iovec
+    ;;  16: nwritten
+    ;;  20: a newline, for when there are no arguments
+    ;; 1024: argv
+    ;; 4096: argv_buf
+    (data (i32.const 20) "\n")
+
+    (func $main (export "_start")
+        (local $start i32)
+        (local $end i32)
+        (local $ptr i32)
+
+        (drop (call $args_sizes_get (i32.const 0) (i32.const 4)))
+        (drop (call $args_get (i32.const 1024) (i32.const 4096)))
+
+        ;; the first argument is the program name, so with no other arguments,
+        ;; just print a newline.
+        (if (i32.le_u (i32.load (i32.const 0)) (i32.const 1))
+            (then
+                (local.set $start (i32.const 20))
+                (local.set $end (i32.const 21)))
+            (else
+                ;; the arguments are stored contiguously, so replace the NUL
+                ;; terminators with spaces, and the last one with a newline,
+                ;; and write them all at once.
+                (local.set $start (i32.load (i32.const 1028)))
+                (local.set $end (i32.add (i32.const 4096) (i32.load (i32.const 4))))
+                (local.set $ptr (local.get $start))
+                (block $done
+                    (loop $next
+                        (br_if $done (i32.ge_u (local.get $ptr) (local.get $end)))
+                        (if (i32.eqz (i32.load8_u (local.get $ptr)))
+                            (then (i32.store8 (local.get $ptr) (i32.const 32))))
+                        (local.set $ptr (i32.add (local.get $ptr) (i32.const 1)))
+                        (br $next)))
+                (i32.store8 (i32.sub (local.get $end) (i32.const 1)) (i32.const 10))))
+
+        (i32.store (i32.const 8) (local.get $start))
+        (i32.store (i32.const 12) (i32.sub (local.get $end) (local.get $start)))
+        (drop (call $fd_write (i32.const 1) (i32.const 8) (i32.const 1) (i32.const 16)))
+    )
+)
@@ -44,9 +44,12 @@ impl TaskId {
         self.0 == 0
     }
 
+    /// Returns the task ID as a `u64`.
+    ///
+    /// No task will ever be assigned ID 0.
+    #[must_use]
     #[inline]
-    #[allow(dead_code)] // currently only used when tracing is enabled
-    pub(crate) fn as_u64(self) -> u64 {
+    pub fn as_u64(self) -> u64 {
         self.0
     }
 }
@@ -1,5 +1,5 @@
 use bootloader_api::config::{BootloaderConfig, Mapping};
-use hal_core::boot::BootInfo;
+use hal_core::{boot::BootInfo, PAddr, VAddr};
 use hal_x86_64::{
     cpu::{self, local::GsLocalData},
     time, vga,
@@ -13,9 +13,11 @@ mod acpi;
 mod boot;
 mod framebuf;
 pub mod interrupt;
+mod mptable;
 mod oops;
 pub mod pci;
 pub mod shell;
+mod smp;
 pub use self::{
     boot::ArchInfo,
     oops::{oops, Oops},
@@ -26,11 +28,24 @@ mod tests;
 
 pub type MinPageSize = mm::size::Size4Kb;
 
+/// The base address of the range of kernel virtual addresses handed out by
+/// the [virtual memory manager](crate::vm).
+///
+/// The bootloader's dynamic range ends just below this address (see
+/// [`BOOTLOADER_CONFIG`]), so everything it places at an address of its own
+/// choosing, such as the physical memory map, the boot info, and the recursive
+/// page table entry, is mapped below it. This range is the 512 GiB mapped by
+/// PML4 entry 510, which therefore starts out empty.
+pub const KERNEL_VM_BASE: u64 = 0xFFFF_FF00_0000_0000;
+/// The size of the range starting at [`KERNEL_VM_BASE`].
+pub const KERNEL_VM_SIZE: usize = 512 * 1024 * 1024 * 1024;
+
 pub static BOOTLOADER_CONFIG: BootloaderConfig = {
     let mut config = BootloaderConfig::new_default();
     config.mappings.physical_memory = Some(Mapping::Dynamic);
     // the kernel is mapped into the higher half of the virtual address space.
     config.mappings.dynamic_range_start = Some(0xFFFF_8000_0000_0000);
+    config.mappings.dynamic_range_end = Some(KERNEL_VM_BASE - 1);
     config.mappings.page_table_recursive = Some(Mapping::Dynamic);
 
     config
@@ -58,7 +73,8 @@ pub fn arch_entry(info: &'static mut bootloader_api::BootInfo) -> ! {
     crate::kernel_start(boot_info, archinfo);
 }
 
-pub fn init(_info: &impl BootInfo, archinfo: &ArchInfo) -> maitake::time::Clock {
+pub fn init(info: &impl BootInfo, archinfo: &ArchInfo) -> maitake::time::Clock {
+    map_physical_memory(info);
     pci::init_pci();
 
     // init boot processor's core-local data
@@ -66,6 +82,9 @@ pub fn init(_info: &impl BootInfo, archinfo: &ArchInfo) -> maitake::time::Clock
         GsLocalData::init();
     }
     tracing::info!("set up the boot processor's local data");
+    // the timer interrupt checks whether it's running on the boot processor,
+    // so initialize that local key now, rather than allocating it in the ISR.
+    assert!(smp::is_boot_processor());
 
     if let Some(rsdp) = archinfo.rsdp_addr {
         let acpi = acpi::acpi_tables(rsdp);
@@ -73,9 +92,10 @@ pub fn init(_info: &impl BootInfo, archinfo: &ArchInfo) -> maitake::time::Clock
         match platform_info {
             Ok(platform) => {
                 tracing::debug!("found ACPI platform info");
+                tracing::info!(?platform.power_profile);
                 let irq_ctrl =
                     interrupt::enable_hardware_interrupts(Some(&platform.interrupt_model));
-                acpi::bringup_smp(&platform)
+                acpi::bringup_smp(&platform.interrupt_model, platform.processor_info.as_ref())
                     .expect("failed to bring up application processors! this is bad news!");
                 irq_ctrl
             }
@@ -85,9 +105,30 @@ pub fn init(_info: &impl BootInfo, archinfo: &ArchInfo) -> maitake::time::Clock
             }
         }
     } else {
-        // TODO(eliza): try using MP Table to bringup application processors?
-        tracing::warn!("no RSDP from bootloader, skipping SMP bringup");
-        interrupt::enable_hardware_interrupts(None)
+        tracing::warn!("no RSDP from bootloader, trying the MP table...");
+        match mptable::find() {
+            Ok(table) => {
+                tracing::debug!("found MP configuration table");
+                // without an I/O APIC, the MP table doesn't describe an APIC
+                // system: leave the IMCR routing interrupts to the PIC, and
+                // don't try to start the application processors.
+                if matches!(table.interrupt_model, ::acpi::InterruptModel::Apic(_)) {
+                    mptable::enable_apic_mode(&table);
+                    let irq_ctrl =
+                        interrupt::enable_hardware_interrupts(Some(&table.interrupt_model));
+                    acpi::bringup_smp(&table.interrupt_model, Some(&table.processor_info))
+                        .expect("failed to bring up application processors! this is bad news!");
+                    irq_ctrl
+                } else {
+                    tracing::warn!("MP table lists no enabled I/O APICs, skipping SMP bringup");
+                    interrupt::enable_hardware_interrupts(Some(&table.interrupt_model))
+                }
+            }
+            Err(error) => {
+                tracing::warn!(%error, "no MP table, skipping SMP bringup");
+                interrupt::enable_hardware_interrupts(None)
+            }
+        }
     };
 
     time::Rdtsc::new()
@@ -103,6 +144,51 @@ pub fn init(_info: &impl BootInfo, archinfo: &ArchInfo) -> maitake::time::Clock
         .unwrap_or(interrupt::IDIOTIC_CLOCK)
 }
 
+/// Maps MMIO registers for the HAL's drivers with [`vm::map_mmio`].
+///
+/// [`vm::map_mmio`]: crate::vm::map_mmio
+struct KernelMmio;
+
+impl mm::MapMmio for KernelMmio {
+    unsafe fn map_mmio(&self, paddr: PAddr, size: usize) -> VAddr {
+        crate::vm::map_mmio(paddr, size)
+            .unwrap_or_else(|error| panic!("failed to map MMIO registers at {paddr:?}: {error}"))
+            .base_addr()
+    }
+}
+
+/// Makes sure all of the RAM in the boot info's memory map is in the physical
+/// memory map, using huge pages wherever possible.
+///
+/// The bootloader maps physical memory itself, including the regions the
+/// firmware reserves for its tables, so this usually has nothing to do, but
+/// any RAM it left out is mapped here. Regions of unknown kind may be MMIO,
+/// and bad memory shouldn't be touched at all, so neither is mapped: the
+/// physical memory map is writable and cached, which is only appropriate for
+/// RAM.
+fn map_physical_memory(info: &impl BootInfo) {
+    use hal_core::mem::RegionKind;
+
+    let mut pagectrl = mm::PageCtrl::current();
+    let mapped = info
+        .memory_map()
+        .filter(|region| {
+            let kind = region.kind();
+            let is_ram = kind != RegionKind::UNKNOWN && kind != RegionKind::BAD;
+            if !is_ram {
+                tracing::debug!(?region, "not mapping non-RAM region");
+            }
+            is_ram
+        })
+        .map(|region| mm::map_physical_memory(&mut pagectrl, &region, &crate::ALLOC))
+        .sum::<usize>();
+    tracing::info!(
+        mapped_bytes = mapped,
+        supports_1gb_pages = mm::supports_1gb_pages(),
+        "mapped physical memory"
+    );
+}
+
 // TODO(eliza): this is now in arch because it uses the serial port, would be
 // nice if that was cross platform...
 #[cfg(test)]
@@ -18,26 +18,31 @@ pub(super) fn acpi_tables(
     Ok(tables)
 }
 
-#[tracing::instrument(err, skip(platform))]
-pub fn bringup_smp(platform: &acpi::PlatformInfo) -> Result<(), Error> {
+/// Starts the application processors described by `processor_info`.
+///
+/// The interrupt model and processor info may come from the ACPI MADT or, on
+/// systems without ACPI, from the [MP table](super::mptable).
+#[tracing::instrument(err, skip(interrupt_model, processor_info))]
+pub fn bringup_smp(
+    interrupt_model: &acpi::InterruptModel,
+    processor_info: Option<&acpi::platform::ProcessorInfo>,
+) -> Result<(), Error> {
     use acpi::platform::{self, interrupt::InterruptModel};
 
-    tracing::info!(?platform.power_profile);
-
-    let apic = match platform.interrupt_model {
+    let apic = match interrupt_model {
         acpi::InterruptModel::Apic(ref apic) => {
             tracing::info!("APIC interrupt model detected");
             apic
         }
         InterruptModel::Unknown => {
             return Err(Error::Other(
-                "MADT does not indicate support for APIC interrupt model!",
+                "platform does not indicate support for APIC interrupt model!",
             ));
         }
         ref model => {
             tracing::warn!(?model, "unknown interrupt model detected");
             return Err(Error::Other(
-                "MADT does not indicate support for APIC interrupt model!",
+                "platform does not indicate support for APIC interrupt model!",
             ));
         }
     };
@@ -47,10 +52,7 @@ pub fn bringup_smp(platform: &acpi::PlatformInfo) -> Result<(), Error> {
     let platform::ProcessorInfo {
         ref application_processors,
         ref boot_processor,
-    } = platform
-        .processor_info
-        .as_ref()
-        .ok_or(Error::Other("no processor information found in MADT!"))?;
+    } = processor_info.ok_or(Error::Other("no processor information found!"))?;
     tracing::info!("boot processor seems normalish");
     tracing::debug!(?boot_processor);
     tracing::info!(
@@ -58,11 +60,26 @@ pub fn bringup_smp(platform: &acpi::PlatformInfo) -> Result<(), Error> {
         application_processors.len()
     );
     tracing::debug!(?application_processors);
-    tracing::warn!("not starting app processors (SMP support isn't done yet)");
+
+    let apic_ids = application_processors
+        .iter()
+        .filter(|ap| ap.state != platform::ProcessorState::Disabled)
+        .map(|ap| ap.local_apic_id as u8);
+    let started = super::smp::start_aps(apic_ids);
+    tracing::info!(
+        "started {started}/{} application processors",
+        application_processors.len()
+    );
 
     Ok(())
 }
 
+/// Reads ACPI tables through the physical memory map.
+///
+/// ACPI tables are in RAM the firmware has reserved, which the bootloader's
+/// physical memory map covers along with the rest of the memory map, so
+/// they're read through it (cached, like the rest of RAM) the same way the
+/// [MP table](super::mptable) is, rather than getting mappings of their own.
 #[derive(Clone)]
 pub(super) struct IdentityMappedAcpiHandler;
 
@@ -77,9 +94,9 @@ impl AcpiHandler for IdentityMappedAcpiHandler {
         let vptr = vaddr
             .as_non_null()
             .expect("virtual address for ACPI region is not null");
-        // we have identity mapped all physical memory, so we don't actually
-        // have to map any pages --- just tell the ACPI crate that it can use
-        // this region.
+        // all physical memory is already mapped, so we don't actually have to
+        // map any pages --- just tell the ACPI crate that it can use this
+        // region.
         acpi::PhysicalMapping::new(physical_address, vptr, size, size, Self)
     }
 
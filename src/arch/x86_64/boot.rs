@@ -50,7 +50,24 @@ impl BootInfo for BootloaderApiBootInfo {
                 size as usize + 1
             };
             let kind = convert_region_kind(region.kind);
-            mem::Region::new(start, size, kind)
+            let region = mem::Region::new(start, size, kind);
+
+            // the page that application processors are started from must not
+            // be handed out by the page allocator. any free memory below it
+            // is also left unused, which is fine, since there isn't much.
+            let trampoline = PAddr::from_u64(super::smp::TRAMPOLINE_PADDR);
+            let trampoline_end = trampoline.offset(4096);
+            if kind != mem::RegionKind::FREE
+                || region.end_addr() <= trampoline
+                || start >= trampoline_end
+            {
+                return region;
+            }
+            if region.end_addr() <= trampoline_end {
+                return mem::Region::new(start, size, mem::RegionKind::USED);
+            }
+            let trimmed = start.difference(trampoline_end) as usize;
+            mem::Region::new(trampoline_end, size - trimmed, kind)
         }
         self.inner.memory_regions[..].iter().map(convert_region)
     }
@@ -1,4 +1,5 @@
 use super::{oops, Oops};
+use alloc::boxed::Box;
 use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use hal_core::{interrupt, VAddr};
 pub use hal_x86_64::interrupt::*;
@@ -21,7 +22,7 @@ pub fn enable_exceptions() {
 
 #[tracing::instrument(skip(acpi))]
 pub fn enable_hardware_interrupts(acpi: Option<&acpi::InterruptModel>) -> &'static Controller {
-    let irq_ctrl = Controller::enable_hardware_interrupts(acpi, &crate::ALLOC);
+    let irq_ctrl = Controller::enable_hardware_interrupts(acpi, &super::KernelMmio);
 
     irq_ctrl
         .start_periodic_timer(IDIOTIC_CLOCK_INTERVAL)
@@ -54,7 +55,7 @@ static TSS: sync::Lazy<task::StateSegment> = sync::Lazy::new(|| {
 
 pub(in crate::arch) static GDT: sync::InitOnce<Gdt> = sync::InitOnce::uninitialized();
 
-const IDIOTIC_CLOCK_INTERVAL: time::Duration = time::Duration::from_millis(10);
+pub(super) const IDIOTIC_CLOCK_INTERVAL: time::Duration = time::Duration::from_millis(10);
 
 static IDIOTIC_CLOCK_TICKS: AtomicU64 = AtomicU64::new(0);
 
@@ -107,13 +108,24 @@ impl hal_core::interrupt::Handlers<Registers> for InterruptHandlers {
     }
 
     fn timer_tick() {
-        IDIOTIC_CLOCK_TICKS.fetch_add(1, Ordering::Release);
+        // every core's local APIC timer fires this interrupt, but the clock
+        // should only be advanced once per tick.
+        if super::smp::is_boot_processor() {
+            IDIOTIC_CLOCK_TICKS.fetch_add(1, Ordering::Release);
+        }
     }
 
     fn ps2_keyboard(scancode: u8) {
         crate::drivers::ps2_keyboard::handle_scancode(scancode)
     }
 
+    fn serial_input(port: u8, byte: u8) {
+        // only COM1 is used for input, for now.
+        if port == 1 {
+            crate::drivers::serial::handle_byte(byte)
+        }
+    }
+
     fn test_interrupt<C>(cx: C)
     where
         C: hal_core::interrupt::ctx::Context<Registers = Registers>,
@@ -127,6 +139,28 @@ impl hal_core::interrupt::Handlers<Registers> for InterruptHandlers {
 #[tracing::instrument(level = tracing::Level::DEBUG)]
 pub(super) fn init_gdt() {
     tracing::trace!("initializing GDT...");
+    let (gdt, tss_selector) = new_gdt(&TSS);
+
+    // all done! long mode barely uses this thing lol.
+    GDT.init(gdt);
+    load_gdt(GDT.get(), tss_selector);
+}
+
+/// Initializes the GDT and TSS of an application processor.
+///
+/// Each core needs its own TSS, and therefore its own GDT, since a TSS
+/// descriptor is marked busy when it's loaded. `double_fault_stack` is the
+/// top of the stack this core's ISRs use during a double fault.
+#[tracing::instrument(level = tracing::Level::DEBUG)]
+pub(super) fn init_ap_gdt(double_fault_stack: VAddr) {
+    tracing::trace!("initializing AP GDT...");
+    let mut tss = Box::new(task::StateSegment::empty());
+    tss.interrupt_stacks[Idt::DOUBLE_FAULT_IST_OFFSET] = double_fault_stack;
+    let (gdt, tss_selector) = new_gdt(Box::leak(tss));
+    load_gdt(Box::leak(Box::new(gdt)), tss_selector);
+}
+
+fn new_gdt(tss: &'static task::StateSegment) -> (Gdt, segment::Selector) {
     let mut gdt = Gdt::new();
 
     // add one kernel code segment
@@ -140,7 +174,7 @@ pub(super) fn init_gdt() {
 
     // add the TSS.
 
-    let tss = segment::SystemDescriptor::tss(&TSS);
+    let tss = segment::SystemDescriptor::tss(tss);
     let tss_selector = gdt.add_sys_segment(tss);
     tracing::debug!(
         tss.descriptor = fmt::alt(tss),
@@ -148,11 +182,10 @@ pub(super) fn init_gdt() {
         "added TSS"
     );
 
-    // all done! long mode barely uses this thing lol.
-    GDT.init(gdt);
+    (gdt, tss_selector)
+}
 
-    // load the GDT
-    let gdt = GDT.get();
+fn load_gdt(gdt: &'static Gdt, tss_selector: segment::Selector) {
     tracing::debug!(GDT = ?gdt, "GDT initialized");
     gdt.load();
 
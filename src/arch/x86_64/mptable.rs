@@ -0,0 +1,355 @@
+//! Intel MultiProcessor Specification (MP) table parsing.
+//!
+//! Legacy BIOS systems that don't provide an ACPI RSDP may still describe
+//! their processors and I/O APICs in an MP configuration table. The table is
+//! found through the MP floating pointer structure, which the BIOS places in
+//! the first KiB of the Extended BIOS Data Area (EBDA), the last KiB of base
+//! memory, or the BIOS ROM between `0xF0000` and `0xFFFFF`.
+//!
+//! The information in the table is converted to the same types the ACPI MADT
+//! is parsed into, so that it can be used to configure the I/O APICs and start
+//! the application processors in the same way.
+//!
+//! See: <https://wiki.osdev.org/Symmetric_Multiprocessing> and the Intel
+//! MultiProcessor Specification, version 1.4.
+use acpi::platform::{
+    interrupt::{Apic, InterruptSourceOverride, IoApic, Polarity, TriggerMode},
+    Processor, ProcessorInfo, ProcessorState,
+};
+use alloc::vec::Vec;
+use core::fmt;
+use hal_core::PAddr;
+use hal_x86_64::{cpu::Port, mm};
+
+/// The processors and interrupt routing described by an MP table.
+#[derive(Debug)]
+pub(super) struct MpTable {
+    pub(super) interrupt_model: acpi::InterruptModel,
+    pub(super) processor_info: ProcessorInfo,
+    /// Whether the system has an Interrupt Mode Configuration Register, and
+    /// boots in PIC mode.
+    has_imcr: bool,
+}
+
+#[derive(Debug)]
+pub(super) enum Error {
+    /// No MP floating pointer structure was found.
+    NotFound,
+    /// The system uses one of the MP specification's default configurations,
+    /// rather than providing a configuration table.
+    DefaultConfig(u8),
+    /// The configuration table has a bad signature, checksum, or length.
+    BadTable(&'static str),
+    /// The configuration table doesn't describe any processors.
+    NoProcessors,
+}
+
+const FLOATING_PTR_SIG: &[u8; 4] = b"_MP_";
+const FLOATING_PTR_LEN: usize = 16;
+const CONFIG_TABLE_SIG: &[u8; 4] = b"PCMP";
+const CONFIG_HEADER_LEN: usize = 44;
+
+/// Physical addresses of the EBDA segment and the size of base memory in KiB,
+/// in the BIOS Data Area.
+const BDA_EBDA_SEGMENT: u64 = 0x40e;
+const BDA_BASE_MEM_KIB: u64 = 0x413;
+
+/// The number of I/O APIC inputs assumed to precede each I/O APIC when
+/// numbering global system interrupts, since the MP table doesn't say.
+///
+/// This is the number of inputs on the 82093AA I/O APIC, and on QEMU's.
+const IOAPIC_INPUTS: u32 = 24;
+
+// MP configuration table entry types.
+const ENTRY_PROCESSOR: u8 = 0;
+const ENTRY_BUS: u8 = 1;
+const ENTRY_IOAPIC: u8 = 2;
+const ENTRY_IO_INTERRUPT: u8 = 3;
+const ENTRY_LOCAL_INTERRUPT: u8 = 4;
+
+/// Searches for the MP floating pointer structure, and parses the
+/// configuration table it points to.
+#[tracing::instrument(level = "debug")]
+pub(super) fn find() -> Result<MpTable, Error> {
+    let floating = find_floating_ptr().ok_or(Error::NotFound)?;
+    tracing::debug!(paddr = ?floating, "found MP floating pointer structure");
+    let floating = unsafe { phys_bytes(floating, FLOATING_PTR_LEN) };
+
+    let features = &floating[11..16];
+    if features[0] != 0 {
+        return Err(Error::DefaultConfig(features[0]));
+    }
+    let has_imcr = features[1] & (1 << 7) != 0;
+
+    let table_addr = read_u32(floating, 4) as u64;
+    if table_addr == 0 {
+        return Err(Error::BadTable(
+            "floating pointer has no configuration table",
+        ));
+    }
+    let header = unsafe { phys_bytes(PAddr::from_u64(table_addr), CONFIG_HEADER_LEN) };
+    if &header[0..4] != CONFIG_TABLE_SIG {
+        return Err(Error::BadTable("bad configuration table signature"));
+    }
+    let len = read_u16(header, 4) as usize;
+    if len < CONFIG_HEADER_LEN {
+        return Err(Error::BadTable("configuration table is too short"));
+    }
+    let table = unsafe { phys_bytes(PAddr::from_u64(table_addr), len) };
+    if !checksum_ok(table) {
+        return Err(Error::BadTable("bad configuration table checksum"));
+    }
+
+    let mut parsed = Parser::new(read_u32(table, 36) as u64);
+    let n_entries = read_u16(table, 34);
+    let mut entries = &table[CONFIG_HEADER_LEN..];
+    for _ in 0..n_entries {
+        let len = match entries.first() {
+            Some(&ENTRY_PROCESSOR) => 20,
+            Some(_) => 8,
+            None => return Err(Error::BadTable("configuration table entries are truncated")),
+        };
+        let entry = entries
+            .get(..len)
+            .ok_or(Error::BadTable("configuration table entries are truncated"))?;
+        parsed.entry(entry);
+        entries = &entries[len..];
+    }
+
+    parsed.finish(has_imcr)
+}
+
+/// Returns the physical address of the MP floating pointer structure, if
+/// there is one.
+fn find_floating_ptr() -> Option<PAddr> {
+    let (ebda, base_mem_end) = unsafe {
+        let ebda_segment = read_u16(phys_bytes(PAddr::from_u64(BDA_EBDA_SEGMENT), 2), 0);
+        let base_mem_kib = read_u16(phys_bytes(PAddr::from_u64(BDA_BASE_MEM_KIB), 2), 0);
+        ((ebda_segment as u64) << 4, base_mem_kib as u64 * 1024)
+    };
+
+    let mut areas = [(0, 0); 3];
+    if ebda != 0 {
+        areas[0] = (ebda, 1024);
+    }
+    if base_mem_end >= 1024 {
+        areas[1] = (base_mem_end - 1024, 1024);
+    }
+    areas[2] = (0xf0000, 0x10000);
+
+    areas
+        .into_iter()
+        .filter(|&(_, len)| len > 0)
+        .find_map(|(base, len)| scan_floating_ptr(base, len))
+}
+
+fn scan_floating_ptr(base: u64, len: usize) -> Option<PAddr> {
+    tracing::trace!(base = ?mycelium_util::fmt::hex(base), len, "scanning for MP floating pointer");
+    let area = unsafe { phys_bytes(PAddr::from_u64(base), len) };
+    area.chunks_exact(FLOATING_PTR_LEN)
+        .position(|chunk| {
+            // the length is in 16-byte units.
+            &chunk[0..4] == FLOATING_PTR_SIG && chunk[8] == 1 && checksum_ok(chunk)
+        })
+        .map(|idx| PAddr::from_u64(base + (idx * FLOATING_PTR_LEN) as u64))
+}
+
+/// Switches a system that has an Interrupt Mode Configuration Register from
+/// PIC mode to symmetric I/O mode, so that interrupts are delivered through
+/// the APICs rather than the 8259 PICs.
+pub(super) fn enable_apic_mode(table: &MpTable) {
+    if !table.has_imcr {
+        return;
+    }
+    tracing::debug!("IMCR present, switching to symmetric I/O mode");
+    unsafe {
+        // select the IMCR...
+        Port::at(0x22).writeb(0x70);
+        // ...and route interrupts to the APIC.
+        Port::at(0x23).writeb(0x01);
+    }
+}
+
+/// # Safety
+///
+/// `len` bytes starting at `paddr` must be readable physical memory.
+unsafe fn phys_bytes(paddr: PAddr, len: usize) -> &'static [u8] {
+    let vaddr = mm::kernel_vaddr_of(paddr);
+    core::slice::from_raw_parts(vaddr.as_ptr::<u8>(), len)
+}
+
+fn checksum_ok(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) == 0
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    let mut word = [0; 4];
+    word.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(word)
+}
+
+/// Converts configuration table entries into the ACPI crate's types.
+struct Parser {
+    local_apic_address: u64,
+    boot_processor: Option<Processor>,
+    application_processors: Vec<Processor>,
+    io_apics: Vec<IoApic>,
+    /// The IDs of the ISA buses.
+    isa_buses: Vec<u8>,
+    /// I/O interrupt assignment entries, which are converted once all the
+    /// buses and I/O APICs are known.
+    io_interrupts: Vec<[u8; 8]>,
+}
+
+impl Parser {
+    fn new(local_apic_address: u64) -> Self {
+        Self {
+            local_apic_address,
+            boot_processor: None,
+            application_processors: Vec::new(),
+            io_apics: Vec::new(),
+            isa_buses: Vec::new(),
+            io_interrupts: Vec::new(),
+        }
+    }
+
+    fn entry(&mut self, entry: &[u8]) {
+        match entry[0] {
+            ENTRY_PROCESSOR => {
+                let flags = entry[3];
+                let enabled = flags & 1 != 0;
+                let is_bsp = flags & (1 << 1) != 0;
+                let state = match (enabled, is_bsp) {
+                    (false, _) => ProcessorState::Disabled,
+                    (true, true) => ProcessorState::Running,
+                    (true, false) => ProcessorState::WaitingForSipi,
+                };
+                let processor = Processor {
+                    // the MP table doesn't have processor UIDs, so just use
+                    // the order the processors are listed in.
+                    processor_uid: self.application_processors.len() as u32
+                        + self.boot_processor.is_some() as u32,
+                    local_apic_id: entry[1] as u32,
+                    state,
+                    is_ap: !is_bsp,
+                };
+                tracing::trace!(?processor);
+                if is_bsp {
+                    self.boot_processor = Some(processor);
+                } else {
+                    self.application_processors.push(processor);
+                }
+            }
+            ENTRY_BUS => {
+                let bus_type = &entry[2..8];
+                tracing::trace!(bus.id = entry[1], bus.ty = ?core::str::from_utf8(bus_type));
+                if bus_type.starts_with(b"ISA") {
+                    self.isa_buses.push(entry[1]);
+                }
+            }
+            ENTRY_IOAPIC => {
+                let enabled = entry[3] & 1 != 0;
+                if !enabled {
+                    tracing::debug!(ioapic.id = entry[1], "skipping disabled I/O APIC");
+                    return;
+                }
+                let ioapic = IoApic {
+                    id: entry[1],
+                    address: read_u32(entry, 4),
+                    global_system_interrupt_base: self.io_apics.len() as u32 * IOAPIC_INPUTS,
+                };
+                tracing::trace!(?ioapic);
+                self.io_apics.push(ioapic);
+            }
+            ENTRY_IO_INTERRUPT => {
+                let mut io_interrupt = [0; 8];
+                io_interrupt.copy_from_slice(entry);
+                self.io_interrupts.push(io_interrupt);
+            }
+            ENTRY_LOCAL_INTERRUPT => {
+                // the local APICs' LINT pins are configured the same way
+                // regardless of what the table says.
+            }
+            ty => tracing::debug!(ty, "skipping unknown MP table entry"),
+        }
+    }
+
+    /// Converts I/O interrupt assignments from ISA buses into interrupt source
+    /// overrides.
+    fn interrupt_source_overrides(&self) -> Vec<InterruptSourceOverride> {
+        const INTERRUPT_TYPE_INT: u8 = 0;
+        self.io_interrupts
+            .iter()
+            .filter(|entry| entry[1] == INTERRUPT_TYPE_INT && self.isa_buses.contains(&entry[4]))
+            .filter_map(|entry| {
+                let flags = read_u16(entry, 2);
+                let polarity = match flags & 0b11 {
+                    0b01 => Polarity::ActiveHigh,
+                    0b11 => Polarity::ActiveLow,
+                    _ => Polarity::SameAsBus,
+                };
+                let trigger_mode = match (flags >> 2) & 0b11 {
+                    0b01 => TriggerMode::Edge,
+                    0b11 => TriggerMode::Level,
+                    _ => TriggerMode::SameAsBus,
+                };
+                // an ID of 0xff means "all I/O APICs", which is only
+                // meaningful if there's only one.
+                let ioapic = self
+                    .io_apics
+                    .iter()
+                    .find(|ioapic| entry[6] == 0xff || ioapic.id == entry[6])?;
+                Some(InterruptSourceOverride {
+                    isa_source: entry[5],
+                    global_system_interrupt: ioapic.global_system_interrupt_base + entry[7] as u32,
+                    polarity,
+                    trigger_mode,
+                })
+            })
+            .collect()
+    }
+
+    fn finish(self, has_imcr: bool) -> Result<MpTable, Error> {
+        let interrupt_source_overrides = self.interrupt_source_overrides();
+        let boot_processor = self.boot_processor.ok_or(Error::NoProcessors)?;
+        let interrupt_model = if self.io_apics.is_empty() {
+            acpi::InterruptModel::Unknown
+        } else {
+            acpi::InterruptModel::Apic(Apic {
+                local_apic_address: self.local_apic_address,
+                io_apics: self.io_apics,
+                interrupt_source_overrides,
+                local_apic_nmi_lines: Vec::new(),
+                nmi_sources: Vec::new(),
+                also_has_legacy_pics: true,
+            })
+        };
+        Ok(MpTable {
+            interrupt_model,
+            processor_info: ProcessorInfo {
+                boot_processor,
+                application_processors: self.application_processors,
+            },
+            has_imcr,
+        })
+    }
+}
+
+// === impl Error ===
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => f.write_str("no MP floating pointer structure found"),
+            Self::DefaultConfig(config) => {
+                write!(f, "MP default configuration {config} is not supported")
+            }
+            Self::BadTable(msg) => write!(f, "bad MP configuration table: {msg}"),
+            Self::NoProcessors => f.write_str("MP configuration table has no boot processor"),
+        }
+    }
+}
@@ -0,0 +1,300 @@
+//! Starting application processors.
+//!
+//! Each application processor (AP) is started by the boot processor sending
+//! it an INIT IPI, followed by two STARTUP IPIs, through the local APIC. An AP
+//! starts executing in real mode at the page named by the STARTUP IPI, so a
+//! small trampoline is copied to [`TRAMPOLINE_PADDR`], which switches the AP to
+//! long mode using the kernel's page tables and calls [`ap_entry`] on a newly
+//! allocated stack.
+//!
+//! APs are started one at a time, since they all share the trampoline's
+//! arguments.
+use super::interrupt::{self, Controller};
+use crate::vm;
+use core::{
+    arch::global_asm,
+    cell::Cell,
+    ptr,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+use hal_core::{
+    mem::page::{Map, Page, TranslatePage},
+    Address, PAddr, VAddr,
+};
+use hal_x86_64::{
+    control_regs::cr3,
+    cpu::local::{GsLocalData, LocalKey},
+    mm::{self, size::Size4Kb, PageCtrl},
+    time::PIT,
+};
+use mycelium_util::fmt;
+
+/// The physical address the AP trampoline is copied to.
+///
+/// This must be page-aligned and below 1 MiB, since the STARTUP IPI names the
+/// page an AP starts executing at, in real mode.
+pub(super) const TRAMPOLINE_PADDR: u64 = 0x8000;
+
+const AP_STACK_SIZE: usize = 32 * 4096;
+const AP_DOUBLE_FAULT_STACK_SIZE: usize = 8 * 4096;
+
+/// Arguments passed to an AP by the trampoline.
+///
+/// The layout of this struct must match the offsets used by the trampoline.
+#[repr(C)]
+#[derive(Debug)]
+struct TrampolineArgs {
+    /// The physical address of the PML4 to load into `cr3`.
+    pml4: u64,
+    /// The top of the AP's stack.
+    stack_top: u64,
+    /// The address of the function the AP calls once it's in long mode.
+    entry: u64,
+    /// The top of the AP's double fault stack, passed to `entry`.
+    double_fault_stack: u64,
+}
+
+/// Set by an AP once it's started.
+static AP_STARTED: AtomicBool = AtomicBool::new(false);
+
+static IS_AP: LocalKey<Cell<bool>> = LocalKey::new(|| Cell::new(false));
+
+global_asm! {
+    ".pushsection .text.ap_trampoline, \"ax\"",
+    ".balign 16",
+    ".global ap_trampoline_start",
+    ".global ap_trampoline_args",
+    ".global ap_trampoline_end",
+    ".code16",
+    "ap_trampoline_start:",
+    "cli",
+    "cld",
+    "xor ax, ax",
+    "mov ds, ax",
+    "lgdt [{base} + (ap_trampoline_gdtr - ap_trampoline_start)]",
+    // enable PAE
+    "mov eax, cr4",
+    "or eax, 1 << 5",
+    "mov cr4, eax",
+    // load the kernel's page tables
+    "mov eax, dword ptr [{base} + (ap_trampoline_args - ap_trampoline_start)]",
+    "mov cr3, eax",
+    // enable long mode and no-execute pages in the EFER
+    "mov ecx, 0xC0000080",
+    "rdmsr",
+    "or eax, (1 << 8) | (1 << 11)",
+    "wrmsr",
+    // enable paging, write protection, and protected mode
+    "mov eax, cr0",
+    "or eax, (1 << 31) | (1 << 16) | 1",
+    "mov cr0, eax",
+    // far jump to the 64-bit code segment
+    ".byte 0xea",
+    ".2byte {base} + (ap_trampoline_long - ap_trampoline_start)",
+    ".2byte 0x08",
+    ".code64",
+    "ap_trampoline_long:",
+    "mov ax, 0x10",
+    "mov ds, ax",
+    "mov es, ax",
+    "mov ss, ax",
+    "xor ax, ax",
+    "mov fs, ax",
+    "mov gs, ax",
+    "mov rsp, qword ptr [{base} + (ap_trampoline_args - ap_trampoline_start) + 8]",
+    "mov rax, qword ptr [{base} + (ap_trampoline_args - ap_trampoline_start) + 16]",
+    "mov rdi, qword ptr [{base} + (ap_trampoline_args - ap_trampoline_start) + 24]",
+    "call rax",
+    "ud2",
+    ".balign 8",
+    "ap_trampoline_gdt:",
+    ".8byte 0",
+    // 64-bit code segment
+    ".8byte 0x00af9a000000ffff",
+    // data segment
+    ".8byte 0x00cf92000000ffff",
+    "ap_trampoline_gdtr:",
+    ".2byte ap_trampoline_gdtr - ap_trampoline_gdt - 1",
+    ".4byte {base} + (ap_trampoline_gdt - ap_trampoline_start)",
+    ".balign 8",
+    "ap_trampoline_args:",
+    ".zero 32",
+    "ap_trampoline_end:",
+    ".popsection",
+    base = const TRAMPOLINE_PADDR,
+}
+
+extern "C" {
+    static ap_trampoline_start: u8;
+    static ap_trampoline_args: u8;
+    static ap_trampoline_end: u8;
+}
+
+/// Returns `true` if the current CPU core is the boot processor.
+pub(super) fn is_boot_processor() -> bool {
+    !IS_AP.with(Cell::get)
+}
+
+/// Starts the application processors with the provided local APIC IDs,
+/// returning the number of APs that were started.
+///
+/// This must be called on the boot processor, after hardware interrupts have
+/// been enabled. APs are started in order, and startup stops at the first AP
+/// that doesn't respond.
+#[tracing::instrument(skip(apic_ids))]
+pub(super) fn start_aps(apic_ids: impl IntoIterator<Item = u8>) -> usize {
+    let Some(controller) = Controller::try_get() else {
+        tracing::warn!("hardware interrupts are not enabled, can't start APs");
+        return 0;
+    };
+
+    let (pml4, _) = cr3::read();
+    let pml4 = pml4.base_addr().as_usize() as u64;
+    if pml4 > u32::MAX as u64 {
+        // the trampoline loads `cr3` before it's in long mode.
+        tracing::warn!(pml4 = ?fmt::hex(pml4), "PML4 is above 4 GiB, can't start APs");
+        return 0;
+    }
+
+    let args = match install_trampoline() {
+        Ok(args) => args,
+        Err(error) => {
+            tracing::warn!(error, "can't start APs");
+            return 0;
+        }
+    };
+
+    let sleep = |ms| {
+        PIT.lock()
+            .sleep_blocking(Duration::from_millis(ms))
+            .expect("sleeping on the boot processor should succeed")
+    };
+    const STARTUP_PAGE: u8 = (TRAMPOLINE_PADDR >> 12) as u8;
+
+    let mut started = 0;
+    for apic_id in apic_ids {
+        let _span = tracing::debug_span!("start_ap", apic_id).entered();
+        let stacks = alloc_stack(AP_STACK_SIZE)
+            .and_then(|stack| Ok((stack, alloc_stack(AP_DOUBLE_FAULT_STACK_SIZE)?)));
+        let (stack_top, double_fault_stack) = match stacks {
+            Ok(stacks) => stacks,
+            Err(error) => {
+                tracing::warn!(%error, "can't allocate AP stacks");
+                break;
+            }
+        };
+        unsafe {
+            args.write_volatile(TrampolineArgs {
+                pml4,
+                stack_top,
+                entry: ap_entry as usize as u64,
+                double_fault_stack,
+            });
+        }
+        AP_STARTED.store(false, Ordering::Release);
+
+        let sent = controller.with_local_apic(|apic| unsafe {
+            apic.send_init_ipi(apic_id);
+            sleep(10);
+            apic.send_startup_ipi(apic_id, STARTUP_PAGE);
+            sleep(1);
+            if !AP_STARTED.load(Ordering::Acquire) {
+                apic.send_startup_ipi(apic_id, STARTUP_PAGE);
+            }
+        });
+        if let Err(error) = sent {
+            tracing::warn!(%error, "can't send IPIs to APs");
+            break;
+        }
+
+        // give the AP up to 100 ms to start.
+        for _ in 0..100 {
+            if AP_STARTED.load(Ordering::Acquire) {
+                break;
+            }
+            sleep(1);
+        }
+
+        if !AP_STARTED.load(Ordering::Acquire) {
+            // if the AP starts later, it would read the trampoline arguments
+            // for the next AP, so don't start any more.
+            tracing::warn!(apic_id, "AP did not start, giving up on starting APs");
+            break;
+        }
+
+        tracing::info!(apic_id, "started AP");
+        started += 1;
+    }
+
+    started
+}
+
+/// Copies the trampoline to [`TRAMPOLINE_PADDR`], and identity maps it so that
+/// it can keep executing once the AP enables paging. Returns a pointer to the
+/// trampoline's arguments.
+fn install_trampoline() -> Result<*mut TrampolineArgs, &'static str> {
+    let paddr = PAddr::from_u64(TRAMPOLINE_PADDR);
+    let (start, args, end) = unsafe {
+        (
+            ptr::addr_of!(ap_trampoline_start),
+            ptr::addr_of!(ap_trampoline_args),
+            ptr::addr_of!(ap_trampoline_end),
+        )
+    };
+    let len = end as usize - start as usize;
+    assert!(len <= 4096, "AP trampoline must fit in one page");
+
+    let vaddr = mm::kernel_vaddr_of(paddr);
+    unsafe {
+        ptr::copy_nonoverlapping(start, vaddr.as_mut_ptr::<u8>(), len);
+    }
+
+    let mut pagectrl = PageCtrl::current();
+    let virt = Page::<VAddr, Size4Kb>::containing_fixed(VAddr::from_u64(TRAMPOLINE_PADDR));
+    let phys = Page::<PAddr, Size4Kb>::containing_fixed(paddr);
+    match pagectrl.translate_page(virt) {
+        Ok(mapped) if mapped == phys => {}
+        Ok(_) => return Err("the AP trampoline's address is already mapped"),
+        Err(_) => {
+            pagectrl.identity_map(phys, &crate::ALLOC).commit();
+        }
+    }
+
+    Ok(vaddr
+        .offset(args as isize - start as isize)
+        .as_mut_ptr::<TrampolineArgs>())
+}
+
+/// Allocates a stack for an AP, returning the address of its top.
+///
+/// AP stacks are never freed, since APs are never stopped.
+fn alloc_stack(size: usize) -> Result<u64, vm::Error> {
+    let stack = vm::alloc_stack(size)?;
+    Ok(stack.end_addr().as_usize() as u64)
+}
+
+/// The first Rust code run by an AP, called by the trampoline.
+extern "C" fn ap_entry(double_fault_stack: u64) -> ! {
+    Controller::load_idt();
+    GsLocalData::init();
+    interrupt::init_ap_gdt(VAddr::from_u64(double_fault_stack));
+    IS_AP.with(|is_ap| is_ap.set(true));
+
+    let controller =
+        Controller::try_get().expect("APs are only started once hardware interrupts are enabled");
+    controller
+        .initialize_local_apic(&super::KernelMmio)
+        .expect("APs are only started with the APIC interrupt model");
+    controller
+        .start_periodic_timer(interrupt::IDIOTIC_CLOCK_INTERVAL)
+        .expect("failed to start periodic timer");
+
+    AP_STARTED.store(true, Ordering::Release);
+
+    let mut core = crate::rt::Core::new();
+    loop {
+        core.run();
+        tracing::warn!("AP core stopped running, restarting it");
+    }
+}
@@ -114,32 +114,74 @@ mycotest::decl_test! {
             tracing::info!(?res, "deallocated page 1");
             res
         })?;
-        // TODO(eliza): when 2mb pages work, test that too...
-        // let page3 = tracing::info_span!("alloc page 3").in_scope(|| {
-        //     let res = crate::ALLOC.alloc(mm::size::Size2Mb);
-        //     tracing::info!(?res);
-        //     res
-        // })?;
+        let page3 = tracing::info_span!("alloc page 3").in_scope(|| {
+            let res = crate::ALLOC.alloc(mm::size::Size2Mb);
+            tracing::info!(?res);
+            res
+        })?;
         tracing::info_span!("dealloc page 2").in_scope(|| {
             let res = crate::ALLOC.dealloc(page2);
             tracing::info!(?res, "deallocated page 2");
             res
         })?;
-        // let page4 = tracing::info_span!("alloc page 4").in_scope(|| {
-        //     let res = crate::ALLOC.alloc(mm::size::Size2Mb);
-        //     tracing::info!(?res);
-        //     res
-        // })?;
-        // tracing::info_span!("dealloc page 3").in_scope(|| {
-        //     let res = crate::ALLOC.dealloc(page3);
-        //     tracing::info!(?res, "deallocated page 3");
-        //     res
-        // })?;
-        // tracing::info_span!("dealloc page 4").in_scope(|| {
-        //     let res = crate::ALLOC.dealloc(page4);
-        //     tracing::info!(?res, "deallocated page 4");
-        //     res
-        // })?;
+        let page4 = tracing::info_span!("alloc page 4").in_scope(|| {
+            let res = crate::ALLOC.alloc(mm::size::Size2Mb);
+            tracing::info!(?res);
+            res
+        })?;
+        assert_ne!(page3, page4);
+        tracing::info_span!("dealloc page 3").in_scope(|| {
+            let res = crate::ALLOC.dealloc(page3);
+            tracing::info!(?res, "deallocated page 3");
+            res
+        })?;
+        tracing::info_span!("dealloc page 4").in_scope(|| {
+            let res = crate::ALLOC.dealloc(page4);
+            tracing::info!(?res, "deallocated page 4");
+            res
+        })?;
+        Ok(())
+    }
+}
+
+mycotest::decl_test! {
+    fn map_and_unmap_2mb_page() -> mycotest::TestResult {
+        use hal_core::{
+            mem::page::{Alloc, Map, Page, StaticSize, TranslatePage},
+            VAddr,
+        };
+
+        let Ok(frame) = crate::ALLOC.alloc(mm::size::Size2Mb) else {
+            mycotest::fail!("allocating a 2MB frame failed");
+        };
+        // nothing else maps anything this far into the lower half.
+        let page = Page::containing_fixed(VAddr::from_u64(0x1000_0000_0000));
+        let mut ctrl = mm::PageCtrl::current();
+        let page = unsafe {
+            ctrl.map_page(page, frame, &crate::ALLOC)
+                .set_writable(true)
+                .commit()
+        };
+        tracing::info!(?page, ?frame, "mapped 2MB page");
+        mycotest::assert_eq!(ctrl.translate_page(page), Ok(frame));
+
+        // write to both ends of the page.
+        let last = page.base_addr().offset(mm::size::Size2Mb::SIZE as isize - 8);
+        unsafe {
+            page.base_addr().as_mut_ptr::<u64>().write_volatile(0xfeed_face);
+            last.as_mut_ptr::<u64>().write_volatile(0xdead_beef);
+            mycotest::assert_eq!(page.base_addr().as_ptr::<u64>().read_volatile(), 0xfeed_face);
+            mycotest::assert_eq!(last.as_ptr::<u64>().read_volatile(), 0xdead_beef);
+        }
+
+        let unmapped =
+            unsafe { Map::<mm::size::Size2Mb, crate::allocator::Allocator>::unmap(&mut ctrl, page) };
+        mycotest::assert_eq!(unmapped, frame);
+        mycotest::assert!(ctrl.translate_page(page).is_err());
+
+        if crate::ALLOC.dealloc(frame).is_err() {
+            mycotest::fail!("deallocating the 2MB frame failed");
+        }
         Ok(())
     }
 }
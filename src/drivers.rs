@@ -2,3 +2,4 @@
 pub mod ide;
 pub mod pci;
 pub mod ps2_keyboard;
+pub mod serial;
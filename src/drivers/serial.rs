@@ -0,0 +1,161 @@
+//! Serial port input.
+//!
+//! Bytes received on the first serial port (COM1 on x86) are buffered by
+//! [`handle_byte`], which is called by the port's ISR, and can be awaited
+//! with [`next_byte`]. A [`KeyDecoder`] turns those bytes into the same
+//! [`DecodedKey`]s the PS/2 keyboard produces, so that the shell can be
+//! driven from a terminal attached to the serial port, such as QEMU's stdio
+//! when running with `cargo inoculate run --serial`.
+use core::sync::atomic::{AtomicUsize, Ordering};
+use maitake::sync::WaitQueue;
+use mycelium_util::fmt;
+use pc_keyboard::{DecodedKey, KeyCode};
+
+pub struct SerialInput {
+    // TODO(eliza): this should use some kind of broadcast channel that waits
+    // for *all* readers to consume each byte...
+    buf: thingbuf::StaticThingBuf<u8, 256>,
+    waiters: WaitQueue,
+    /// The number of bytes dropped because the buffer was full, since they
+    /// were last reported.
+    ///
+    /// Bytes are dropped in the ISR, which mustn't log, so they're counted
+    /// here and reported by [`next_byte`] instead.
+    dropped: AtomicUsize,
+}
+
+/// Turns bytes sent by a terminal into key presses.
+///
+/// Printable ASCII characters, Tab, and Enter are decoded as themselves. Both
+/// DEL and BS are decoded as Backspace, as terminals differ on which one the
+/// Backspace key sends. The ANSI escape sequences for the arrow keys, Home,
+/// End, and Delete are decoded as those keys; other escape sequences and
+/// non-ASCII bytes are ignored.
+#[derive(Debug, Default)]
+pub struct KeyDecoder {
+    state: State,
+    /// Whether the last byte was a carriage return, so that the line feed of
+    /// a CRLF isn't decoded as a second Enter.
+    after_cr: bool,
+}
+
+#[derive(Debug, Default)]
+enum State {
+    #[default]
+    Ground,
+    /// Got ESC.
+    Escape,
+    /// Got ESC `[` or ESC `O`, and the numeric parameter so far, if any.
+    Csi(Option<u8>),
+}
+
+const ESC: u8 = 0x1b;
+
+static SERIAL: SerialInput = SerialInput {
+    buf: thingbuf::StaticThingBuf::new(),
+    waiters: WaitQueue::new(),
+    dropped: AtomicUsize::new(0),
+};
+
+pub async fn next_byte() -> u8 {
+    SERIAL.report_dropped();
+    if let Some(byte) = SERIAL.buf.pop() {
+        return byte;
+    }
+
+    SERIAL
+        .waiters
+        .wait()
+        .await
+        .expect("serial input waiters should never be closed, this is a bug");
+    SERIAL.report_dropped();
+    SERIAL
+        .buf
+        .pop()
+        .expect("we just got woken up, there should be a byte in the buffer")
+}
+
+pub(crate) fn handle_byte(byte: u8) {
+    if SERIAL.buf.push(byte).is_err() {
+        SERIAL.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+    SERIAL.waiters.wake_all();
+}
+
+// === impl SerialInput ===
+
+impl SerialInput {
+    fn report_dropped(&self) {
+        let dropped = self.dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            tracing::warn!(dropped, "serial input buffer was full, dropped bytes!");
+        }
+    }
+}
+
+// === impl KeyDecoder ===
+
+impl KeyDecoder {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            state: State::Ground,
+            after_cr: false,
+        }
+    }
+
+    /// Decodes the next byte received from the terminal, returning a key if
+    /// the byte completes one.
+    pub fn decode(&mut self, byte: u8) -> Option<DecodedKey> {
+        let after_cr = core::mem::replace(&mut self.after_cr, byte == b'\r');
+        match (&mut self.state, byte) {
+            (State::Ground, ESC) => {
+                self.state = State::Escape;
+                None
+            }
+            (State::Ground, b'\r') => Some(DecodedKey::Unicode('\n')),
+            (State::Ground, b'\n') if after_cr => None,
+            (State::Ground, 0x08 | 0x7f) => Some(DecodedKey::RawKey(KeyCode::Backspace)),
+            (State::Ground, b'\n' | b'\t' | 0x20..=0x7e) => Some(DecodedKey::Unicode(byte as char)),
+            (State::Ground, _) => {
+                tracing::debug!(byte = fmt::hex(&byte), "ignoring serial input byte");
+                None
+            }
+
+            (State::Escape, b'[' | b'O') => {
+                self.state = State::Csi(None);
+                None
+            }
+
+            (State::Csi(param), b'0'..=b'9') => {
+                let digit = byte - b'0';
+                *param = Some(param.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+                None
+            }
+            (State::Csi(param), _) => {
+                let key = match (*param, byte) {
+                    (None, b'A') => Some(KeyCode::ArrowUp),
+                    (None, b'B') => Some(KeyCode::ArrowDown),
+                    (None, b'C') => Some(KeyCode::ArrowRight),
+                    (None, b'D') => Some(KeyCode::ArrowLeft),
+                    (None, b'H') | (Some(1 | 7), b'~') => Some(KeyCode::Home),
+                    (None, b'F') | (Some(4 | 8), b'~') => Some(KeyCode::End),
+                    (Some(3), b'~') => Some(KeyCode::Delete),
+                    (param, _) => {
+                        tracing::debug!(?param, byte = fmt::hex(&byte), "ignoring escape sequence");
+                        None
+                    }
+                };
+                self.state = State::Ground;
+                key.map(DecodedKey::RawKey)
+            }
+
+            // a lone ESC, followed by something that doesn't start an escape
+            // sequence.
+            (State::Escape, _) => {
+                self.state = State::Ground;
+                self.decode(byte)
+            }
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! The kernel's initial RAM filesystem.
+//!
+//! This is a read-only `newc`-format cpio archive of WASM programs, which is
+//! packed by the build script from the `initramfs` directory and embedded in
+//! the kernel image.
+use core::str;
+
+static ARCHIVE: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/initramfs.cpio"));
+
+/// A file in the initramfs.
+#[derive(Copy, Clone, Debug)]
+pub struct Entry {
+    pub name: &'static str,
+    pub data: &'static [u8],
+}
+
+/// An iterator over the files in a cpio archive.
+#[derive(Clone, Debug)]
+pub struct Entries {
+    archive: &'static [u8],
+}
+
+const MAGIC: &[u8] = b"070701";
+const HEADER_LEN: usize = 110;
+const TRAILER: &str = "TRAILER!!!";
+
+/// Returns an iterator over all the files in the initramfs.
+pub fn entries() -> Entries {
+    Entries { archive: ARCHIVE }
+}
+
+/// Returns the contents of the file called `name`, if it exists.
+pub fn lookup(name: &str) -> Option<&'static [u8]> {
+    entries()
+        .find(|entry| entry.name == name)
+        .map(|entry| entry.data)
+}
+
+// === impl Entries ===
+
+impl Entries {
+    /// Reads the `idx`th hex-encoded field of an entry's header.
+    fn field(header: &[u8], idx: usize) -> Option<usize> {
+        let start = MAGIC.len() + idx * 8;
+        let field = str::from_utf8(header.get(start..start + 8)?).ok()?;
+        usize::from_str_radix(field, 16).ok()
+    }
+
+    fn align(offset: usize) -> usize {
+        (offset + 3) & !3
+    }
+}
+
+impl Iterator for Entries {
+    type Item = Entry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let header = self.archive.get(..HEADER_LEN)?;
+        if !header.starts_with(MAGIC) {
+            tracing::warn!("invalid initramfs entry header");
+            self.archive = &[];
+            return None;
+        }
+
+        let data_len = Self::field(header, 6)?;
+        let name_len = Self::field(header, 11)?;
+        // the name length includes the NUL terminator.
+        let name = self
+            .archive
+            .get(HEADER_LEN..HEADER_LEN + name_len.checked_sub(1)?)?;
+        let name = str::from_utf8(name).ok()?;
+        if name == TRAILER {
+            self.archive = &[];
+            return None;
+        }
+
+        let data_start = Self::align(HEADER_LEN + name_len);
+        let data_end = data_start + data_len;
+        let data = self.archive.get(data_start..data_end)?;
+        self.archive = self.archive.get(Self::align(data_end)..).unwrap_or(&[]);
+
+        Some(Entry { name, data })
+    }
+}
@@ -10,8 +10,11 @@ extern crate rlibc;
 pub mod allocator;
 pub mod arch;
 pub mod drivers;
+pub mod initramfs;
+pub mod rng;
 pub mod rt;
 pub mod shell;
+pub mod vm;
 pub mod wasm;
 
 #[cfg(test)]
@@ -162,7 +165,8 @@ pub fn kernel_start(bootinfo: impl BootInfo, archinfo: crate::arch::ArchInfo) ->
 }
 
 fn kernel_main(bootinfo: impl BootInfo) -> ! {
-    rt::spawn(keyboard_demo());
+    rt::spawn(keyboard_shell());
+    rt::spawn(serial_shell());
 
     let mut core = rt::Core::new();
     tracing::info!(
@@ -194,34 +198,35 @@ pub fn main() {
     /* no host-platform tests in this crate */
 }
 
-/// Keyboard handler demo task: logs each line typed by the user.
-// TODO(eliza): let's do something Actually Useful with keyboard input...
-async fn keyboard_demo() {
-    #[cfg(target_os = "none")]
-    use alloc::string::String;
-    use drivers::ps2_keyboard::{self, DecodedKey, KeyCode};
+/// Keyboard shell task: edits lines typed by the user, and evaluates them as
+/// shell commands.
+async fn keyboard_shell() {
+    use drivers::ps2_keyboard;
 
-    let mut line = String::new();
+    let mut editor = shell::LineEditor::new();
     tracing::info!("type `help` to list available commands");
     loop {
         let key = ps2_keyboard::next_key().await;
-        let mut newline = false;
-        match key {
-            DecodedKey::Unicode('\n') | DecodedKey::Unicode('\r') => {
-                newline = true;
-            }
-            // backspace
-            DecodedKey::RawKey(KeyCode::Backspace)
-            | DecodedKey::RawKey(KeyCode::Delete)
-            | DecodedKey::Unicode('\u{0008}') => {
-                line.pop();
-            }
-            DecodedKey::Unicode(c) => line.push(c),
-            DecodedKey::RawKey(key) => tracing::warn!(?key, "you typed something weird"),
+        if let Some(line) = editor.handle_key(key) {
+            shell::eval(&line);
         }
-        if newline {
+    }
+}
+
+/// Serial shell task: like the keyboard shell, but reads lines from the serial
+/// port, so that the kernel can be controlled when running headless.
+async fn serial_shell() {
+    use drivers::serial;
+
+    let mut decoder = serial::KeyDecoder::new();
+    let mut editor = shell::LineEditor::new();
+    loop {
+        let byte = serial::next_byte().await;
+        let Some(key) = decoder.decode(byte) else {
+            continue;
+        };
+        if let Some(line) = editor.handle_key(key) {
             shell::eval(&line);
-            line.clear();
         }
     }
 }
@@ -0,0 +1,30 @@
+;; Checks that `random_get` fills buffers with bytes that aren't constant.
+;;
+;; Traps if `random_get` fails, if either buffer is filled with a single
+;; repeated value, or if both calls return the same bytes.
+
+(module
+    ;; (*buf, buf_len) -> errno
+    (import "wasi_snapshot_preview1" "random_get" (func $random_get (param i32 i32) (result i32)))
+
+    (memory 1)
+    (export "memory" (memory 0))
+
+    ;; Fills the 16-byte buffer at `$buf` with random bytes, trapping if the
+    ;; call fails or both halves of the buffer are the same.
+    (func $fill (param $buf i32)
+        (if (call $random_get (local.get $buf) (i32.const 16))
+            (then unreachable))
+        (if (i64.eq
+                (i64.load (local.get $buf))
+                (i64.load offset=8 (local.get $buf)))
+            (then unreachable))
+    )
+
+    (func $main (export "_start")
+        (call $fill (i32.const 0))
+        (call $fill (i32.const 16))
+        (if (i64.eq (i64.load (i32.const 0)) (i64.load (i32.const 16)))
+            (then unreachable))
+    )
+)
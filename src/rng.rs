@@ -0,0 +1,23 @@
+//! The kernel's cryptographically secure random number generator.
+//!
+//! Unlike the per-core RNGs used by the scheduler for work-stealing, this RNG
+//! is suitable for generating secrets, such as the random bytes handed out to
+//! WASM guests by WASI's `random_get`.
+use crate::arch;
+use mycelium_util::sync::{blocking::Mutex, Lazy};
+use rand::RngCore;
+use rand_chacha::ChaCha20Rng;
+
+/// The kernel CSPRNG.
+///
+/// This is seeded from all the hardware entropy sources available on the
+/// current CPU (see [`arch::seed_rng`]) the first time it's used.
+static RNG: Lazy<Mutex<ChaCha20Rng>> = Lazy::new(|| {
+    tracing::debug!("seeding kernel CSPRNG");
+    Mutex::new(arch::seed_rng())
+});
+
+/// Fills `dest` with cryptographically secure random bytes.
+pub fn fill_bytes(dest: &mut [u8]) {
+    RNG.lock().fill_bytes(dest)
+}
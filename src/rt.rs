@@ -89,6 +89,12 @@ where
     })
 }
 
+/// Returns the ID of the task currently being polled on this core, or `None`
+/// if no task is running.
+pub fn current_task_id() -> Option<maitake::task::TaskId> {
+    SCHEDULER.with(|scheduler| scheduler.get()?.current_task().map(|task| task.id()))
+}
+
 /// Initialize the kernel runtime.
 pub fn init(clock: maitake::time::Clock) {
     tracing::info!(
@@ -102,6 +108,12 @@ pub fn init(clock: maitake::time::Clock) {
     tracing::info!("kernel runtime initialized");
 }
 
+/// Returns the kernel's global timer, or `None` if the runtime has not been
+/// initialized yet.
+pub fn timer() -> Option<&'static time::Timer> {
+    TIMER.try_get()
+}
+
 pub const DUMP_RT: crate::shell::Command = crate::shell::Command::new("rt")
     .with_help("print the kernel's async runtime")
     .with_fn(|_| {
@@ -5,6 +5,9 @@ use crate::rt;
 use core::str::FromStr;
 use mycelium_util::fmt::{self, Write};
 
+mod editor;
+pub use self::editor::LineEditor;
+
 /// Defines a shell command, including its name, help text, and how the command
 /// is executed.
 #[derive(Debug)]
@@ -49,16 +52,28 @@ enum RunKind<'a> {
     Runnable(&'a dyn Run),
 }
 
-pub fn eval(line: &str) {
-    static COMMANDS: &[Command] = &[
-        DUMP,
-        SLEEP,
-        PANIC,
-        FAULT,
-        VERSION,
-        crate::drivers::pci::LSPCI_CMD,
-    ];
+/// The commands which may be completed by [`complete`] and are evaluated by
+/// [`eval`].
+static COMMANDS: &[Command] = &[
+    DUMP,
+    SLEEP,
+    PANIC,
+    FAULT,
+    VERSION,
+    RUN,
+    crate::drivers::pci::LSPCI_CMD,
+];
+
+/// The names of the commands which could complete the last word of a line.
+///
+/// Returned by [`complete`].
+#[derive(Copy, Clone, Debug)]
+pub struct Completions<'line> {
+    partial: &'line str,
+    commands: &'static [Command<'static>],
+}
 
+pub fn eval(line: &str) {
     let _span = tracing::info_span!(target: "shell", "$", message = %line).entered();
     tracing::info!(target: "shell", "");
 
@@ -77,6 +92,31 @@ pub fn eval(line: &str) {
     tracing::info!(target: "shell", "");
 }
 
+/// Returns the commands which could complete the last word of `line`.
+///
+/// Each earlier word of `line` must name a command, or a subcommand of the
+/// previous word's command. Arguments to commands are never completed.
+pub fn complete(line: &str) -> Completions<'_> {
+    let (words, partial) = match line.rsplit_once(char::is_whitespace) {
+        Some((words, partial)) => (words, partial),
+        None => ("", line),
+    };
+
+    let mut commands = COMMANDS;
+    for word in words.split_whitespace() {
+        commands = commands
+            .iter()
+            .find(|cmd| cmd.name == word)
+            .and_then(|cmd| cmd.subcommands)
+            .unwrap_or(&[]);
+        if commands.is_empty() {
+            break;
+        }
+    }
+
+    Completions { partial, commands }
+}
+
 #[derive(Copy, Clone)]
 pub struct Context<'cmd> {
     line: &'cmd str,
@@ -113,11 +153,13 @@ const DUMP: Command = Command::new("dump")
         //         Ok(())
         //     }),
         rt::DUMP_RT,
+        crate::vm::DUMP_VM,
         crate::arch::shell::DUMP_ARCH,
         Command::new("heap")
             .with_help("print kernel heap statistics")
             .with_fn(|_| {
                 tracing::info!(target: "shell", heap = ?crate::ALLOC.state());
+                tracing::info!(target: "shell", wasm_cache = %crate::wasm::cache::stats());
                 Ok(())
             }),
     ]);
@@ -147,6 +189,52 @@ const SLEEP: Command = Command::new("sleep")
         Ok(())
     });
 
+const RUN: Command = Command::new("run")
+    .with_help("run a WASM program from the initramfs, or list the programs if NAME is omitted")
+    .with_usage("<NAME> [ARGS]...")
+    .with_fn(|ctx| {
+        use crate::{initramfs, wasm};
+        use alloc::vec::Vec;
+
+        let line = ctx.command();
+        let args = line.split_whitespace().collect::<Vec<_>>();
+        let Some(&name) = args.first() else {
+            tracing::info!(target: "shell", "programs:");
+            for entry in initramfs::entries() {
+                tracing::info!(target: "shell", "  {} ({} bytes)", entry.name, entry.data.len());
+            }
+            return Ok(());
+        };
+
+        let binary = initramfs::lookup(name)
+            .or_else(|| initramfs::lookup(&alloc::format!("{name}.wasm")))
+            .ok_or_else(|| ctx.invalid_argument("no such program in the initramfs"))?;
+        let task = wasm::WasmTask::new(binary)
+            .map_err(|error| {
+                tracing::error!(target: "shell", %error, "invalid WASM module");
+                ctx.other_error("invalid WASM module")
+            })?
+            .with_args(args.iter().copied());
+
+        let name = alloc::string::String::from(name);
+        tracing::info!(target: "shell", %name, "running WASM program");
+        rt::spawn(async move {
+            match task.await {
+                Ok(wasm::ExitStatus::Exited(code)) => {
+                    tracing::info!(target: "shell", %name, "exited with code {code}")
+                }
+                Ok(wasm::ExitStatus::Trapped(trap)) => {
+                    tracing::error!(target: "shell", %name, "trapped: {trap}")
+                }
+                Err(error) => {
+                    tracing::error!(target: "shell", %name, %error, "WASM program failed")
+                }
+            }
+        });
+
+        Ok(())
+    });
+
 const PANIC: Command = Command::new("panic")
     .with_usage("<MESSAGE>")
     .with_help("cause a kernel panic with the given message. use with caution.")
@@ -404,6 +492,28 @@ impl fmt::Display for Command<'_> {
     }
 }
 
+// === impl Completions ===
+
+impl<'line> Completions<'line> {
+    /// Returns the partial word being completed.
+    pub fn partial(&self) -> &'line str {
+        self.partial
+    }
+
+    /// Returns an iterator over the names of the commands which start with
+    /// [the partial word](Self::partial).
+    pub fn iter(&self) -> impl Iterator<Item = &'static str> + 'line {
+        let partial = self.partial;
+        // `help` is accepted everywhere there are commands.
+        let help = (!self.commands.is_empty()).then_some("help");
+        self.commands
+            .iter()
+            .map(|cmd| cmd.name)
+            .chain(help)
+            .filter(move |name| name.starts_with(partial))
+    }
+}
+
 // === impl Error ===
 
 impl Error<'_> {
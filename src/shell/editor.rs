@@ -0,0 +1,201 @@
+//! Line editing for the kernel shell.
+use alloc::{collections::VecDeque, string::String, vec::Vec};
+use pc_keyboard::{DecodedKey, KeyCode};
+
+/// Edits a line of shell input one key at a time.
+///
+/// The editor supports moving the cursor with the arrow keys, Home and End,
+/// recalling previous lines from a [history ring](Self::HISTORY_LEN) with the
+/// Up and Down keys, and completing command names with Tab.
+#[derive(Debug, Default)]
+pub struct LineEditor {
+    line: String,
+    /// The cursor's position in `line`, as a byte index.
+    cursor: usize,
+    /// Previously entered lines, oldest first.
+    history: VecDeque<String>,
+    /// The index in `history` of the line being edited, if the user has
+    /// walked back through the history with the Up key.
+    recalled: Option<usize>,
+    /// The line that was being edited before walking back through the
+    /// history, which is restored by walking forward past the newest line.
+    draft: String,
+}
+
+impl LineEditor {
+    /// The number of lines kept in the history ring.
+    pub const HISTORY_LEN: usize = 32;
+
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            line: String::new(),
+            cursor: 0,
+            history: VecDeque::new(),
+            recalled: None,
+            draft: String::new(),
+        }
+    }
+
+    /// Returns the line being edited.
+    pub fn line(&self) -> &str {
+        &self.line
+    }
+
+    /// Returns the cursor's position in [the line](Self::line), as a byte
+    /// index.
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Returns the history ring, oldest line first.
+    pub fn history(&self) -> impl DoubleEndedIterator<Item = &str> + '_ {
+        self.history.iter().map(String::as_str)
+    }
+
+    /// Handles a key press.
+    ///
+    /// If the key was Enter, the line is added to the history and returned,
+    /// and the editor starts a new line.
+    pub fn handle_key(&mut self, key: DecodedKey) -> Option<String> {
+        match key {
+            DecodedKey::Unicode('\n' | '\r') => return Some(self.submit()),
+            DecodedKey::Unicode('\u{0008}') | DecodedKey::RawKey(KeyCode::Backspace) => {
+                if let Some(c) = self.line[..self.cursor].chars().next_back() {
+                    self.cursor -= c.len_utf8();
+                    self.line.remove(self.cursor);
+                }
+            }
+            DecodedKey::Unicode('\u{007f}') | DecodedKey::RawKey(KeyCode::Delete) => {
+                if self.cursor < self.line.len() {
+                    self.line.remove(self.cursor);
+                }
+            }
+            DecodedKey::Unicode('\t') | DecodedKey::RawKey(KeyCode::Tab) => self.complete(),
+            DecodedKey::Unicode(c) if !c.is_control() => {
+                self.line.insert(self.cursor, c);
+                self.cursor += c.len_utf8();
+            }
+            DecodedKey::RawKey(KeyCode::ArrowLeft) => {
+                if let Some(c) = self.line[..self.cursor].chars().next_back() {
+                    self.cursor -= c.len_utf8();
+                }
+            }
+            DecodedKey::RawKey(KeyCode::ArrowRight) => {
+                if let Some(c) = self.line[self.cursor..].chars().next() {
+                    self.cursor += c.len_utf8();
+                }
+            }
+            DecodedKey::RawKey(KeyCode::Home) => self.cursor = 0,
+            DecodedKey::RawKey(KeyCode::End) => self.cursor = self.line.len(),
+            DecodedKey::RawKey(KeyCode::ArrowUp) => self.history_prev(),
+            DecodedKey::RawKey(KeyCode::ArrowDown) => self.history_next(),
+            key => tracing::debug!(target: "shell", ?key, "ignoring key"),
+        }
+        None
+    }
+
+    /// Adds the current line to the history ring and starts a new one,
+    /// returning the submitted line.
+    fn submit(&mut self) -> String {
+        self.cursor = 0;
+        self.recalled = None;
+        self.draft.clear();
+        let line = core::mem::take(&mut self.line);
+
+        // don't fill the history with blank lines, or with the same command
+        // repeated several times.
+        let is_repeat = self.history.back().map(String::as_str) == Some(line.as_str());
+        if !line.trim().is_empty() && !is_repeat {
+            if self.history.len() == Self::HISTORY_LEN {
+                self.history.pop_front();
+            }
+            self.history.push_back(line.clone());
+        }
+        line
+    }
+
+    /// Replaces the line with the previous line in the history.
+    fn history_prev(&mut self) {
+        let idx = match self.recalled {
+            Some(0) => return,
+            Some(idx) => idx - 1,
+            None if self.history.is_empty() => return,
+            None => {
+                self.draft = core::mem::take(&mut self.line);
+                self.history.len() - 1
+            }
+        };
+        self.recall(Some(idx));
+    }
+
+    /// Replaces the line with the next line in the history, or the line that
+    /// was being edited before walking back through the history.
+    fn history_next(&mut self) {
+        let Some(idx) = self.recalled else {
+            return;
+        };
+        let next = idx + 1;
+        self.recall((next < self.history.len()).then_some(next));
+    }
+
+    fn recall(&mut self, idx: Option<usize>) {
+        self.recalled = idx;
+        self.line = match idx {
+            Some(idx) => self.history[idx].clone(),
+            None => core::mem::take(&mut self.draft),
+        };
+        self.cursor = self.line.len();
+        self.echo();
+    }
+
+    /// Completes the command name before the cursor.
+    ///
+    /// If only one command matches, its name is completed. If several
+    /// commands match, the longest prefix they share is completed, and if
+    /// that doesn't add anything, the matching commands are listed.
+    fn complete(&mut self) {
+        let completions = super::complete(&self.line[..self.cursor]);
+        let candidates = completions.iter().collect::<Vec<_>>();
+        let (first, rest) = match candidates.split_first() {
+            Some(split) => split,
+            None => return,
+        };
+
+        let partial = completions.partial();
+        let insert = if rest.is_empty() {
+            let mut name = String::from(&first[partial.len()..]);
+            name.push(' ');
+            name
+        } else {
+            let shared = rest.iter().fold(first.len(), |len, name| {
+                first
+                    .bytes()
+                    .zip(name.bytes())
+                    .take(len)
+                    .take_while(|(a, b)| a == b)
+                    .count()
+            });
+            String::from(&first[partial.len()..shared])
+        };
+
+        if insert.is_empty() {
+            let mut names = String::new();
+            for name in &candidates {
+                names.push_str(name);
+                names.push_str("  ");
+            }
+            tracing::info!(target: "shell", "{}", names.trim_end());
+            return;
+        }
+
+        self.line.insert_str(self.cursor, &insert);
+        self.cursor += insert.len();
+        self.echo();
+    }
+
+    /// Prints the line, since it was changed by something other than typing.
+    fn echo(&self) {
+        tracing::info!(target: "shell", "> {}", self.line);
+    }
+}
@@ -1,9 +1,138 @@
 use super::*;
 
 mycotest::decl_test! {
-    fn wasm_hello_world() -> Result<(), wasmi::Error> {
+    fn wasm_hello_world() -> mycotest::TestResult {
         const HELLOWORLD_WASM: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/helloworld.wasm"));
-        wasm::run_wasm(HELLOWORLD_WASM)
+        let status = wasm::run_wasm(HELLOWORLD_WASM);
+        tracing::info!(?status);
+        mycotest::assert!(matches!(status, Ok(status) if status.success()));
+        Ok(())
+    }
+}
+
+mycotest::decl_test! {
+    fn wasm_random_get() -> mycotest::TestResult {
+        const RANDOM_WASM: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/random.wasm"));
+        let status = wasm::run_wasm(RANDOM_WASM);
+        tracing::info!(?status);
+        mycotest::assert!(matches!(status, Ok(status) if status.success()));
+        Ok(())
+    }
+}
+
+mycotest::decl_test! {
+    fn wasm_module_cache() -> mycotest::TestResult {
+        const HELLOWORLD_WASM: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/helloworld.wasm"));
+        let limits = wasm::WasmLimits::DEFAULT;
+        let first = wasm::cache::compile(HELLOWORLD_WASM, limits);
+        mycotest::assert!(first.is_ok());
+        let hits = wasm::cache::stats().hits;
+        let second = wasm::cache::compile(HELLOWORLD_WASM, limits);
+        mycotest::assert!(second.is_ok());
+        let stats = wasm::cache::stats();
+        tracing::info!(%stats);
+        mycotest::assert_eq!(stats.hits, hits + 1);
+        mycotest::assert!(stats.bytes > 0);
+        Ok(())
+    }
+}
+
+mycotest::decl_test! {
+    fn shell_line_editor() -> mycotest::TestResult {
+        use drivers::ps2_keyboard::{DecodedKey, KeyCode};
+
+        fn type_str(editor: &mut shell::LineEditor, s: &str) -> Option<::alloc::string::String> {
+            s.chars()
+                .filter_map(|c| editor.handle_key(DecodedKey::Unicode(c)))
+                .last()
+        }
+
+        let mut editor = shell::LineEditor::new();
+        // `arch` and `archinfo` share a prefix, so only that is completed.
+        type_str(&mut editor, "dump ar\t");
+        mycotest::assert_eq!(editor.line(), "dump arch");
+        type_str(&mut editor, " ms\t");
+        mycotest::assert_eq!(editor.line(), "dump arch msr ");
+
+        editor.handle_key(DecodedKey::RawKey(KeyCode::Home));
+        editor.handle_key(DecodedKey::RawKey(KeyCode::Delete));
+        mycotest::assert_eq!(editor.line(), "ump arch msr ");
+        let line = type_str(&mut editor, "d\n");
+        mycotest::assert_eq!(line.as_deref(), Some("dump arch msr "));
+
+        type_str(&mut editor, "lspci c\t\n");
+        type_str(&mut editor, "vers");
+        editor.handle_key(DecodedKey::RawKey(KeyCode::ArrowUp));
+        mycotest::assert_eq!(editor.line(), "lspci class ");
+        editor.handle_key(DecodedKey::RawKey(KeyCode::ArrowUp));
+        mycotest::assert_eq!(editor.line(), "dump arch msr ");
+        editor.handle_key(DecodedKey::RawKey(KeyCode::ArrowDown));
+        editor.handle_key(DecodedKey::RawKey(KeyCode::ArrowDown));
+        mycotest::assert_eq!(editor.line(), "vers");
+
+        Ok(())
+    }
+}
+
+mycotest::decl_test! {
+    fn serial_key_decoder() -> mycotest::TestResult {
+        use drivers::serial::KeyDecoder;
+
+        // what a terminal sends for typing `lspcix`, Backspace (as DEL), Home,
+        // Delete, `l`, End, and Enter (as CRLF).
+        const INPUT: &[u8] = b"lspcix\x7f\x1b[H\x1b[3~l\x1b[F\r\n";
+
+        let mut decoder = KeyDecoder::new();
+        let mut editor = shell::LineEditor::new();
+        let lines = INPUT
+            .iter()
+            .filter_map(|&byte| decoder.decode(byte))
+            .filter_map(|key| editor.handle_key(key))
+            .collect::<::alloc::vec::Vec<_>>();
+        mycotest::assert_eq!(lines, ["lspci"]);
+
+        Ok(())
+    }
+}
+
+mycotest::decl_test! {
+    fn vm_alloc_and_free() -> mycotest::TestResult {
+        use hal_core::{
+            mem::page::{Page, TranslatePage},
+            VAddr,
+        };
+
+        fn is_mapped(addr: VAddr) -> bool {
+            let page = Page::<_, arch::MinPageSize>::containing_fixed(addr);
+            arch::mm::PageCtrl::current().translate_page(page).is_ok()
+        }
+
+        let Ok(heap) = vm::alloc_heap(3 * 4096 + 1) else {
+            mycotest::fail!("allocating a heap VMA failed");
+        };
+        let Ok(stack) = vm::alloc_stack(4096) else {
+            mycotest::fail!("allocating a stack VMA failed");
+        };
+        tracing::info!(?heap, ?stack);
+        mycotest::assert_eq!(heap.size(), 4 * 4096);
+        mycotest::assert!(is_mapped(heap.base_addr()));
+        unsafe {
+            heap.base_addr().as_mut_ptr::<u64>().write_volatile(0xfeed_face);
+            stack.base_addr().as_mut_ptr::<u64>().write_volatile(0xdead_beef);
+        }
+
+        // the stack's guard page is reserved, but not mapped.
+        let guard = stack.base_addr().offset(-4096);
+        mycotest::assert!(!is_mapped(guard));
+
+        unsafe {
+            mycotest::assert!(vm::free(heap.base_addr()).is_ok());
+            mycotest::assert!(vm::free(stack.base_addr()).is_ok());
+            mycotest::assert!(vm::free(stack.base_addr()).is_err());
+        }
+        mycotest::assert!(!is_mapped(heap.base_addr()));
+        mycotest::assert!(!is_mapped(stack.base_addr()));
+        Ok(())
     }
 }
 
@@ -0,0 +1,313 @@
+//! The kernel's virtual address space manager.
+//!
+//! Most of the kernel's memory is reached through the physical memory map, but
+//! some things need virtual addresses of their own: MMIO registers that
+//! shouldn't share the physical memory map's page flags, stacks that need
+//! unmapped guard pages, and heaps that are backed by whichever page frames
+//! happen to be free. These are handed out from a window of the kernel's
+//! address space that nothing else maps ([`arch::KERNEL_VM_BASE`]), and each
+//! range handed out is tracked as a virtual memory area ([`Vma`]).
+use crate::{arch, shell::Command};
+use alloc::collections::BTreeMap;
+use core::fmt;
+use hal_core::{
+    mem::{
+        page::{Alloc, Map, Page, StaticSize},
+        Region, RegionKind,
+    },
+    Address, PAddr, VAddr,
+};
+use mycelium_util::sync::{blocking::Mutex, Lazy};
+
+type PageSize = arch::mm::size::Size4Kb;
+
+/// A range of the kernel's virtual address space handed out by the virtual
+/// memory manager.
+#[derive(Clone, Debug)]
+pub struct Vma {
+    region: Region<VAddr>,
+    kind: Kind,
+}
+
+/// What a [`Vma`] is used for, which determines how its pages are mapped.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Kind {
+    /// Memory-mapped I/O registers, mapped to the physical address of the
+    /// first register.
+    Mmio(PAddr),
+    /// A stack, which has an unmapped guard page below it so that overflowing
+    /// it faults rather than corrupting whatever is mapped below.
+    Stack,
+    /// Heap memory, backed by newly allocated page frames.
+    Heap,
+}
+
+/// Errors returned by the virtual memory manager.
+#[derive(Debug)]
+pub enum Error {
+    /// There's no free range of virtual addresses large enough.
+    OutOfVirtualMemory { size: usize },
+    /// There aren't enough free page frames to back a stack or heap.
+    OutOfPhysicalMemory { size: usize },
+    /// The address isn't the base address of a [`Vma`].
+    NotAllocated(VAddr),
+}
+
+struct AddressSpace {
+    base: VAddr,
+    end: VAddr,
+    /// The VMAs in the address space, by their base address.
+    vmas: BTreeMap<VAddr, Vma>,
+}
+
+static VM: Lazy<Mutex<AddressSpace>> = Lazy::new(|| {
+    let base = VAddr::from_u64(arch::KERNEL_VM_BASE);
+    let end = base.offset(arch::KERNEL_VM_SIZE as isize);
+    tracing::debug!(?base, ?end, "initializing kernel virtual memory");
+    Mutex::new(AddressSpace {
+        base,
+        end,
+        vmas: BTreeMap::new(),
+    })
+});
+
+pub const DUMP_VM: Command = Command::new("vm")
+    .with_help("print the kernel's virtual memory areas")
+    .with_fn(|_| {
+        let vm = VM.lock();
+        let used = vm
+            .vmas
+            .values()
+            .map(|vma| vma.reserved_size())
+            .sum::<usize>();
+        tracing::info!(
+            target: "shell",
+            base = ?vm.base,
+            end = ?vm.end,
+            areas = vm.vmas.len(),
+            used,
+            "kernel virtual memory",
+        );
+        for vma in vm.vmas.values() {
+            tracing::info!(target: "shell", "{vma}");
+        }
+        Ok(())
+    });
+
+/// Maps `size` bytes of memory-mapped I/O registers at `paddr` into the
+/// kernel's address space, returning the region they were mapped at.
+///
+/// The pages containing the registers are mapped writable, non-executable, and
+/// uncached.
+/// The returned region starts at the virtual address of `paddr`, which has the
+/// same offset into its page as `paddr` does.
+///
+/// # Safety
+///
+/// `paddr` must be the address of MMIO registers, and not RAM that is used for
+/// something else.
+pub unsafe fn map_mmio(paddr: PAddr, size: usize) -> Result<Region<VAddr>, Error> {
+    let offset = paddr.as_usize() % PageSize::SIZE;
+    let phys = paddr.align_down(PageSize::SIZE);
+    let vma = VM.lock().map(Kind::Mmio(phys), offset + size)?;
+    Ok(Region::new(
+        vma.base_addr().offset(offset as isize),
+        size,
+        RegionKind::USED,
+    ))
+}
+
+/// Allocates a stack of at least `size` bytes, returning its region. The top
+/// of the stack is the region's [end address](Region::end_addr).
+///
+/// The stack is mapped writable and non-executable, with an unmapped guard
+/// page below it.
+pub fn alloc_stack(size: usize) -> Result<Region<VAddr>, Error> {
+    VM.lock().map(Kind::Stack, size)
+}
+
+/// Allocates `size` bytes of heap memory, rounded up to whole pages,
+/// returning its region.
+///
+/// The memory is mapped writable and non-executable.
+pub fn alloc_heap(size: usize) -> Result<Region<VAddr>, Error> {
+    VM.lock().map(Kind::Heap, size)
+}
+
+/// Unmaps the [`Vma`] starting at `addr`, which must be the base address of a
+/// region returned by [`map_mmio`], [`alloc_stack`], or [`alloc_heap`]. The
+/// page frames backing stacks and heaps are deallocated.
+///
+/// # Safety
+///
+/// Nothing may use the VMA's memory once it's freed.
+pub unsafe fn free(addr: VAddr) -> Result<(), Error> {
+    let base = addr.align_down(PageSize::SIZE);
+    let mut vm = VM.lock();
+    let vma = vm.vmas.remove(&base).ok_or(Error::NotAllocated(addr))?;
+    tracing::debug!(%vma, "freeing VMA");
+    unmap_pages(vma.region.base_addr(), vma.pages(), vma.kind);
+    Ok(())
+}
+
+/// Unmaps `pages` pages starting at `base`, deallocating the page frames
+/// backing them if `kind` has its own page frames.
+unsafe fn unmap_pages(base: VAddr, pages: usize, kind: Kind) {
+    let mut pagectrl = arch::mm::PageCtrl::current();
+    for page in 0..pages {
+        let virt = Page::<VAddr, PageSize>::containing_fixed(
+            base.offset((page * PageSize::SIZE) as isize),
+        );
+        let frame = Map::<PageSize, crate::allocator::Allocator>::unmap(&mut pagectrl, virt);
+        if matches!(kind, Kind::Mmio(_)) {
+            continue;
+        }
+        if let Err(error) = crate::ALLOC.dealloc(frame) {
+            tracing::warn!(?frame, ?error, "failed to deallocate VMA page frame");
+        }
+    }
+}
+
+// === impl AddressSpace ===
+
+impl AddressSpace {
+    /// Reserves a range for a new VMA of `kind` that's at least `size` bytes
+    /// long, and maps its pages.
+    fn map(&mut self, kind: Kind, size: usize) -> Result<Region<VAddr>, Error> {
+        let pages = size.div_ceil(PageSize::SIZE).max(1);
+        let guard_pages = kind.guard_pages();
+        let reserved = (pages + guard_pages) * PageSize::SIZE;
+        let base = self
+            .find_free(reserved)
+            .ok_or(Error::OutOfVirtualMemory { size })?
+            .offset((guard_pages * PageSize::SIZE) as isize);
+        let vma = Vma {
+            region: Region::new(base, pages * PageSize::SIZE, RegionKind::USED),
+            kind,
+        };
+        let _span = tracing::debug_span!("map_vma", %vma).entered();
+
+        let mut pagectrl = arch::mm::PageCtrl::current();
+        for page in 0..pages {
+            let offset = page * PageSize::SIZE;
+            let virt = Page::<VAddr, PageSize>::containing_fixed(base.offset(offset as isize));
+            let phys = match kind {
+                Kind::Mmio(paddr) => Page::containing_fixed(paddr.offset(offset as isize)),
+                Kind::Stack | Kind::Heap => match crate::ALLOC.alloc(PageSize::INSTANCE) {
+                    Ok(frame) => frame,
+                    Err(_) => {
+                        tracing::warn!(page, "failed to allocate page frame for VMA");
+                        // give back the pages that were already mapped.
+                        unsafe { unmap_pages(base, page, kind) };
+                        return Err(Error::OutOfPhysicalMemory { size });
+                    }
+                },
+            };
+            unsafe {
+                pagectrl
+                    .map_page(virt, phys, &crate::ALLOC)
+                    .set_writable(true)
+                    .set_executable(false)
+                    .set_cached(!matches!(kind, Kind::Mmio(_)))
+                    .commit();
+            }
+        }
+
+        tracing::debug!("mapped VMA");
+        self.vmas.insert(base, vma.clone());
+        Ok(vma.region)
+    }
+
+    /// Returns the base address of the first range of at least `size` bytes
+    /// that isn't reserved by a VMA.
+    fn find_free(&self, size: usize) -> Option<VAddr> {
+        let mut base = self.base;
+        for vma in self.vmas.values() {
+            if vma.reserved_base().as_usize() - base.as_usize() >= size {
+                return Some(base);
+            }
+            base = vma.region.end_addr();
+        }
+        (self.end.as_usize() - base.as_usize() >= size).then_some(base)
+    }
+}
+
+// === impl Vma ===
+
+impl Vma {
+    /// Returns the region of virtual memory mapped by this VMA.
+    #[must_use]
+    pub fn region(&self) -> &Region<VAddr> {
+        &self.region
+    }
+
+    #[must_use]
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    fn pages(&self) -> usize {
+        self.region.size() / PageSize::SIZE
+    }
+
+    /// Returns the base address of the range reserved for this VMA, including
+    /// its guard pages.
+    fn reserved_base(&self) -> VAddr {
+        let guard = self.kind.guard_pages() * PageSize::SIZE;
+        self.region.base_addr().offset(-(guard as isize))
+    }
+
+    fn reserved_size(&self) -> usize {
+        self.region.size() + self.kind.guard_pages() * PageSize::SIZE
+    }
+}
+
+impl fmt::Display for Vma {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?}..{:?} ({} KiB) {}",
+            self.region.base_addr(),
+            self.region.end_addr(),
+            self.region.size() / 1024,
+            self.kind,
+        )
+    }
+}
+
+// === impl Kind ===
+
+impl Kind {
+    fn guard_pages(&self) -> usize {
+        match self {
+            Kind::Stack => 1,
+            Kind::Mmio(_) | Kind::Heap => 0,
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Kind::Mmio(paddr) => write!(f, "MMIO at {paddr:?}"),
+            Kind::Stack => f.write_str("stack"),
+            Kind::Heap => f.write_str("heap"),
+        }
+    }
+}
+
+// === impl Error ===
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfVirtualMemory { size } => {
+                write!(f, "no free kernel virtual memory for {size} bytes")
+            }
+            Self::OutOfPhysicalMemory { size } => {
+                write!(f, "not enough page frames to back {size} bytes")
+            }
+            Self::NotAllocated(addr) => write!(f, "{addr:?} is not the base of a VMA"),
+        }
+    }
+}
@@ -1,59 +1,378 @@
-use alloc::borrow::ToOwned;
+use alloc::{
+    borrow::ToOwned,
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::cell::{Cell, RefCell};
 use core::convert::TryFrom;
 use core::fmt;
+use core::future::Future;
 
+pub mod cache;
+mod caps;
 mod convert;
+mod exit;
+pub mod fd;
+mod fuel;
+mod instrument;
+mod kernel;
+mod limits;
+pub mod linker;
+mod module;
+mod ptr;
+#[cfg(test)]
+mod spec;
+mod symbols;
+mod task;
+mod trace;
 mod wasi;
 
-use self::convert::WasmPrimitive;
+pub use self::{
+    caps::{Capabilities, Manifest, OnDenied, PermissionDenied},
+    exit::{ExitStatus, GuestTrap},
+    limits::{LimitExceeded, WasmLimits},
+    module::CompiledModule,
+    ptr::{GuestPtr, GuestSlice, GuestType},
+    symbols::{Frame, Location},
+    task::{spawn_wasm, WasmTask, WouldBlock},
+};
 
-macro_rules! option_helper {
-    (Some $rt:expr) => {
-        Some($rt)
+use self::{
+    convert::{WasmResult, WasmValues},
+    linker::Linker,
+    wasi::Errno,
+};
+
+/// The return type of a `host_funcs!` entry, which is `()` if it has none.
+macro_rules! result_type {
+    ($rt:ty) => {
+        $rt
     };
-    (Some) => {
-        None
+    () => {
+        ()
     };
 }
 
+/// Host state shared by a set of linked WASM instances.
 #[derive(Debug)]
 pub struct Host {
-    pub module: wasmi::ModuleRef,
-    // FIXME: The wasmi crate currently provides no way to determine the
-    // caller's module or memory from a host function. This effectively means
-    // that if a module imports and calls a function from another module, and
-    // that module calls a host function, the memory region of the first module
-    // will be used.
-    //
-    // We will need to either modify the wasmi interpreter and host function API
-    // to pass in function context when calling host methods, or use host
-    // function trampolines which change the `Host` value, to avoid this issue.
-    pub memory: wasmi::MemoryRef,
+    /// Every instance created by this host, indexed by [`InstanceId`].
+    instances: Vec<Instance>,
+    /// The instance whose call into the host is currently being handled, if
+    /// any.
+    ///
+    /// The wasmi host function API does not tell us which instance called a
+    /// host function. Instead, every host function import is resolved to a
+    /// separate trampoline for each importing instance, whose index encodes
+    /// the instance ID. `invoke_index` sets this to the instance ID of the
+    /// trampoline before calling the host function, so that host functions
+    /// access the linear memory of the instance that actually called them,
+    /// rather than that of the instance the program was started from.
+    caller: Option<InstanceId>,
+    /// Instances registered by name, whose exports may be imported by other
+    /// instances.
+    linker: Linker,
+    /// The WASI file descriptors open in this host. The table is shared by
+    /// every instance the host creates, so a descriptor opened by one linked
+    /// instance may be used by the others.
+    fds: fd::FdTable,
+    /// The command-line arguments passed to the guest, including the program
+    /// name.
+    args: Vec<String>,
+    /// The guest's environment variables, as `KEY=VALUE` strings.
+    env: Vec<String>,
+    /// The resource limits for instances created by this host.
+    limits: WasmLimits,
+    /// The fuel remaining before `limits.max_fuel` is exceeded, if there is a
+    /// fuel limit.
+    fuel_remaining: Option<u64>,
+    /// The fuel remaining in the current time slice.
+    fuel: u64,
+    /// Whether the guest may be suspended by host calls: either when `fuel`
+    /// runs out, or to wait for a future. This is only the case while
+    /// running a resumable invocation.
+    preemptible: bool,
+    /// The suspended host call that the guest is waiting on, if any.
+    pending: Option<task::PendingCall>,
+}
+
+/// Identifies a WASM instance within a [`Host`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct InstanceId(usize);
+
+#[derive(Debug)]
+struct Instance {
+    module: wasmi::ModuleRef,
+    /// The instance's linear memory, if it has one.
+    memory: Option<wasmi::MemoryRef>,
+    /// The module this instance was created from, which holds the debug
+    /// information for symbolizing its functions.
+    compiled: CompiledModule,
+    /// The capabilities granted to the instance.
+    manifest: Manifest,
+    /// The instance's shadow call stack, maintained by [`trace::enter`].
+    stack: Vec<u32>,
+    /// Whether any of the instance's imports were resolved to the exports of
+    /// instances registered with the [`Linker`].
+    linked: bool,
 }
 
 impl Host {
-    // Create a new host for the given instance.
-    // NOTE: The instance may not have been started yet.
-    pub fn new(instance: &wasmi::ModuleRef) -> Result<Self, wasmi::Error> {
-        let memory = match instance.export_by_name("memory") {
-            Some(wasmi::ExternVal::Memory(memory)) => memory,
-            _ => {
-                return Err(wasmi::Error::Instantiation(
-                    "required memory export".to_owned(),
-                ))
-            }
+    pub fn new() -> Self {
+        Self::with_limits(WasmLimits::DEFAULT)
+    }
+
+    /// Returns a new host whose instances are bound by `limits`.
+    pub fn with_limits(limits: WasmLimits) -> Self {
+        Host {
+            instances: Vec::new(),
+            caller: None,
+            linker: Linker::new(),
+            fds: fd::FdTable::new(),
+            args: Vec::new(),
+            env: Vec::new(),
+            limits,
+            fuel_remaining: limits.max_fuel,
+            fuel: 0,
+            preemptible: false,
+            pending: None,
+        }
+    }
+
+    /// Parses and validates `binary`, applying this host's [`WasmLimits`] to
+    /// it.
+    ///
+    /// Modules must be loaded by this method (or with the same limits) before
+    /// they are instantiated, so that they can be preempted and their limits
+    /// enforced. Compiled modules are shared through the kernel-wide
+    /// [`cache`], so loading the same binary again is cheap.
+    pub fn load(&self, binary: &[u8]) -> Result<CompiledModule, wasmi::Error> {
+        cache::compile(binary, self.limits)
+    }
+
+    /// Instantiates `module` with the [default `Manifest`](Manifest::default)
+    /// and runs its start function.
+    ///
+    /// Imports from modules registered with the [`Linker`] are resolved to
+    /// the registered instance's exports. Any other function imports are
+    /// resolved to host functions.
+    pub fn instantiate(
+        &mut self,
+        module: &CompiledModule,
+    ) -> Result<wasmi::ModuleRef, wasmi::Error> {
+        self.instantiate_with_manifest(module, Manifest::default())
+    }
+
+    /// Instantiates `module`, granting it the capabilities in `manifest`, and
+    /// runs its start function.
+    pub fn instantiate_with_manifest(
+        &mut self,
+        module: &CompiledModule,
+        manifest: Manifest,
+    ) -> Result<wasmi::ModuleRef, wasmi::Error> {
+        if module.limits() != &self.limits {
+            return Err(wasmi::Error::Instantiation(format!(
+                "module was compiled with {:?}, but this host has {:?}",
+                module.limits(),
+                self.limits
+            )));
+        }
+
+        let id = InstanceId(self.instances.len());
+        let resolver = HostResolver {
+            id,
+            linker: &self.linker,
+            manifest,
+            imported_memory: RefCell::new(None),
+            linked: Cell::new(false),
         };
+        let instance = wasmi::ModuleInstance::new(module.module(), &resolver)?;
+        let compiled = module.clone();
 
-        Ok(Host {
-            module: instance.clone(),
+        // NOTE: The instance may not have been started yet, but it must be
+        // registered before its start function can call into the host.
+        let module = instance.not_started_instance().clone();
+        let memory = match module.export_by_name("memory") {
+            Some(wasmi::ExternVal::Memory(memory)) => Some(memory),
+            // if the module doesn't export its memory, it may have imported
+            // it from another module instead.
+            _ => resolver.imported_memory.into_inner(),
+        };
+        self.instances.push(Instance {
+            module,
             memory,
-        })
+            compiled,
+            manifest,
+            stack: Vec::new(),
+            linked: resolver.linked.get(),
+        });
+
+        instance.run_start(self).map_err(Into::into)
+    }
+
+    /// Instantiates `module` and registers it with the [`Linker`] under
+    /// `name`, so that modules instantiated later can import its exports.
+    pub fn instantiate_named(
+        &mut self,
+        name: impl Into<String>,
+        module: &CompiledModule,
+    ) -> Result<wasmi::ModuleRef, wasmi::Error> {
+        self.instantiate_named_with_manifest(name, module, Manifest::default())
+    }
+
+    /// Instantiates `module`, granting it the capabilities in `manifest`, and
+    /// registers it with the [`Linker`] under `name`.
+    ///
+    /// Host functions called through the instance's exports are checked
+    /// against this instance's manifest, not the manifest of the instance
+    /// which imported them.
+    pub fn instantiate_named_with_manifest(
+        &mut self,
+        name: impl Into<String>,
+        module: &CompiledModule,
+        manifest: Manifest,
+    ) -> Result<wasmi::ModuleRef, wasmi::Error> {
+        let name = name.into();
+        if self.linker.get(&name).is_some() {
+            return Err(wasmi::Error::Instantiation(format!(
+                "a module named {name:?} is already registered"
+            )));
+        }
+
+        let instance = self.instantiate_with_manifest(module, manifest)?;
+        self.linker.register(name, instance.clone())?;
+        Ok(instance)
+    }
+
+    /// Returns the registry of named instances.
+    pub fn linker(&self) -> &Linker {
+        &self.linker
+    }
+
+    /// Returns the registry of named instances, so that instances created
+    /// elsewhere may be registered.
+    pub fn linker_mut(&mut self) -> &mut Linker {
+        &mut self.linker
+    }
+
+    /// Returns the module of the instance that called the host function
+    /// currently being handled.
+    pub fn caller(&self) -> Option<&wasmi::ModuleRef> {
+        self.caller_instance().map(|instance| &instance.module)
+    }
+
+    /// Returns the linear memory of the instance that called the host
+    /// function currently being handled.
+    ///
+    /// If the caller does not export a memory, any access to it is out of
+    /// bounds.
+    pub fn memory(&self) -> Result<&wasmi::MemoryRef, wasmi::Trap> {
+        self.caller_instance()
+            .and_then(|instance| instance.memory.as_ref())
+            .ok_or_else(|| wasmi::TrapKind::MemoryAccessOutOfBounds.into())
+    }
+
+    /// Returns this instance's file descriptor table.
+    ///
+    /// This can be used to open files or preopen directories for the guest
+    /// before it is started.
+    pub fn fds_mut(&mut self) -> &mut fd::FdTable {
+        &mut self.fds
+    }
+
+    /// Sets the command-line arguments returned by WASI's `args_get`.
+    ///
+    /// By convention, the first argument is the name of the program.
+    pub fn set_args<S: Into<String>>(&mut self, args: impl IntoIterator<Item = S>) {
+        self.args = args.into_iter().map(Into::into).collect();
+    }
+
+    /// Sets the environment variables returned by WASI's `environ_get`.
+    pub fn set_env<K: fmt::Display, V: fmt::Display>(
+        &mut self,
+        env: impl IntoIterator<Item = (K, V)>,
+    ) {
+        self.env = env
+            .into_iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect();
+    }
+
+    /// Suspends the guest until `future` completes, returning a trap which
+    /// the calling host function must return.
+    ///
+    /// When the future completes, its output is returned to the guest as the
+    /// host function's return value. Only guests running as a [`WasmTask`]
+    /// can be suspended; otherwise, the returned trap ends the guest with a
+    /// [`WouldBlock`] error.
+    fn suspend<T: WasmResult>(
+        &mut self,
+        future: impl Future<Output = Result<T, wasmi::Trap>> + 'static,
+    ) -> wasmi::Trap {
+        if !self.preemptible {
+            return wasmi::TrapKind::Host(Box::new(WouldBlock)).into();
+        }
+
+        debug_assert!(
+            self.pending.is_none(),
+            "a guest can only be suspended by one host call at a time"
+        );
+        self.pending = Some(task::PendingCall::new(future));
+        wasmi::TrapKind::Host(Box::new(task::Suspend)).into()
+    }
+
+    /// Returns the capabilities granted to the instance that called the host
+    /// function currently being handled.
+    fn caller_capabilities(&self) -> Capabilities {
+        self.caller_instance()
+            .map_or(Capabilities::NONE, |instance| {
+                instance.manifest.capabilities
+            })
+    }
+
+    fn caller_instance(&self) -> Option<&Instance> {
+        self.caller
+            .and_then(|InstanceId(id)| self.instances.get(id))
+    }
+
+    fn caller_instance_mut(&mut self) -> Option<&mut Instance> {
+        self.caller
+            .and_then(|InstanceId(id)| self.instances.get_mut(id))
+    }
+}
+
+impl Default for Host {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// === impl InstanceId ===
+
+impl InstanceId {
+    /// The number of low bits of a host function index which identify the
+    /// host function. The remaining bits identify the calling instance.
+    const FUNC_BITS: u32 = 16;
+    const FUNC_MASK: usize = (1 << Self::FUNC_BITS) - 1;
+
+    /// Returns the index of the trampoline for `func`, when it is called
+    /// from this instance.
+    fn func_index(self, func: HostFunc) -> usize {
+        (self.0 << Self::FUNC_BITS) | func as usize
+    }
+
+    /// Splits a host function index into the calling instance and the index
+    /// of the host function.
+    fn from_func_index(index: usize) -> (Self, usize) {
+        (Self(index >> Self::FUNC_BITS), index & Self::FUNC_MASK)
     }
 }
 
 macro_rules! host_funcs {
     ($(
-        fn $module:literal :: $name:literal ($($p:ident : $t:ident),*) $( -> $rt:ident)?
+        fn $module:literal :: $name:literal ($($p:ident : $t:ty),*) $( -> $rt:ty)?
             as $variant:ident impl $method:path;
     )*) => {
         #[repr(usize)]
@@ -77,16 +396,20 @@ macro_rules! host_funcs {
             fn signature(self) -> wasmi::Signature {
                 match self {
                     $(
-                        HostFunc::$variant => wasmi::Signature::new(
-                            &[$(<$t as WasmPrimitive>::TYPE),*][..],
-                            option_helper!(Some $(<$rt as WasmPrimitive>::TYPE)?),
-                        )
+                        HostFunc::$variant => {
+                            let params: &[&[wasmi::ValueType]] =
+                                &[$(<$t as WasmValues>::TYPES),*];
+                            wasmi::Signature::new(
+                                params.concat(),
+                                <result_type!($($rt)?) as WasmResult>::TYPE,
+                            )
+                        }
                     ),*
                 }
             }
 
-            fn func_ref(self) -> wasmi::FuncRef {
-                wasmi::FuncInstance::alloc_host(self.signature(), self as usize)
+            fn func_ref(self, caller: InstanceId) -> wasmi::FuncRef {
+                wasmi::FuncInstance::alloc_host(self.signature(), caller.func_index(self))
             }
 
             fn module_name(self) -> &'static str {
@@ -120,28 +443,31 @@ macro_rules! host_funcs {
             }
         }
 
-        impl wasmi::Externals for Host {
-            fn invoke_index(
+        impl Host {
+            fn invoke_host_func(
                 &mut self,
-                index: usize,
+                func: HostFunc,
                 args: wasmi::RuntimeArgs,
             ) -> Result<Option<wasmi::RuntimeValue>, wasmi::Trap> {
-                let span = tracing::trace_span!("invoke_index", index, ?args);
-                let _enter = span.enter();
-
-                match HostFunc::try_from(index)? {
+                match func {
                     $(
-                        HostFunc::$variant => match args.as_ref() {
-                            [$($p),*] => {
-                                let _result = $method(
-                                    self,
-                                    $(<$t as WasmPrimitive>::from_wasm_value(*$p)?),*
-                                )?;
-                                Ok(option_helper!(
-                                    Some $(<$rt as WasmPrimitive>::into_wasm_value(_result))?
-                                ))
+                        HostFunc::$variant => {
+                            // each parameter may be made up of several values.
+                            #[allow(unused_mut)] // if there are no params
+                            let mut values = args.as_ref();
+                            $(
+                                let (param, rest) = values
+                                    .split_at_checked(<$t as WasmValues>::TYPES.len())
+                                    .ok_or(wasmi::TrapKind::UnexpectedSignature)?;
+                                values = rest;
+                                let $p = <$t as WasmValues>::from_wasm_values(param)?;
+                            )*
+                            if !values.is_empty() {
+                                return Err(wasmi::TrapKind::UnexpectedSignature.into());
                             }
-                            _ => Err(wasmi::TrapKind::UnexpectedSignature.into()),
+
+                            let result: result_type!($($rt)?) = $method(self, $($p),*)?;
+                            Ok(WasmResult::into_wasm_result(result))
                         }
                     ),*
                 }
@@ -150,21 +476,107 @@ macro_rules! host_funcs {
     }
 }
 
-host_funcs! {
-    fn "wasi_unstable"::"fd_write"(fd: u32, iovs: u32, iovs_len: u32, nwritten: u32) -> u16
-        as FdWrite impl wasi::fd_write;
+impl wasmi::Externals for Host {
+    fn invoke_index(
+        &mut self,
+        index: usize,
+        args: wasmi::RuntimeArgs,
+    ) -> Result<Option<wasmi::RuntimeValue>, wasmi::Trap> {
+        let span = tracing::trace_span!("invoke_index", index, ?args);
+        let _enter = span.enter();
+
+        let (caller, index) = InstanceId::from_func_index(index);
+        let func = HostFunc::try_from(index)?;
+        if let Some(instance) = self.instances.get(caller.0) {
+            instance
+                .manifest
+                .check(func.module_name(), func.field_name())
+                .map_err(PermissionDenied::into_trap)?;
+        }
+
+        // host functions can't call back into WASM, but restore the previous
+        // caller anyway, just in case.
+        let prev_caller = self.caller.replace(caller);
+        let result = self.invoke_host_func(func, args);
+        self.caller = prev_caller;
+        result
+    }
 }
 
-struct HostResolver;
-impl wasmi::ImportResolver for HostResolver {
+include!(concat!(env!("OUT_DIR"), "/wasi_host_funcs.rs"));
+
+wasi_host_funcs! {
+    fn "mycelium"::"log"(level: u32, msg: GuestSlice<u8>) -> Errno as MyceliumLog impl kernel::log;
+    fn "mycelium"::"spawn"(name: GuestSlice<u8>, task_id: GuestPtr<u64>) -> Errno
+        as MyceliumSpawn impl kernel::spawn;
+    fn "mycelium"::"sleep_ms"(ms: u64) -> Errno as MyceliumSleepMs impl kernel::sleep_ms;
+    fn "mycelium"::"task_id"() -> u64 as MyceliumTaskId impl kernel::task_id;
+    fn "mycelium"::"pci_device_count"(count: GuestPtr<u32>) -> Errno
+        as MyceliumPciDeviceCount impl kernel::pci_device_count;
+    fn "mycelium"::"pci_device"(index: u32, device: GuestPtr<u64>) -> Errno
+        as MyceliumPciDevice impl kernel::pci_device;
+    fn "__mycelium_fuel"::"consume"(amount: u32) as FuelConsume impl fuel::consume;
+    fn "__mycelium_trace"::"enter"(depth: u32, func: u32) as TraceEnter impl trace::enter;
+    fn "__mycelium_limits"::"call_depth_exceeded"() as CallDepthExceeded
+        impl limits::call_depth_exceeded;
+}
+
+/// Resolves the imports of a single instance.
+struct HostResolver<'a> {
+    /// The ID of the instance being resolved.
+    id: InstanceId,
+    /// Previously instantiated modules that this instance may import from.
+    linker: &'a Linker,
+    /// The capabilities granted to this instance.
+    manifest: Manifest,
+    /// The linear memory imported by this instance, if it imports one.
+    imported_memory: RefCell<Option<wasmi::MemoryRef>>,
+    /// Set if any import is resolved by the [`Linker`].
+    linked: Cell<bool>,
+}
+
+impl HostResolver<'_> {
+    /// Resolves an import from a module registered with the [`Linker`], if
+    /// `module_name` is registered.
+    fn resolve_linked<T>(
+        &self,
+        module_name: &str,
+        field_name: &str,
+        kind: &str,
+        extract: impl FnOnce(wasmi::ExternVal) -> Option<T>,
+    ) -> Option<Result<T, wasmi::Error>> {
+        let resolved = self
+            .linker
+            .resolve(module_name, field_name, kind, extract)?;
+        self.linked.set(true);
+        Some(resolved)
+    }
+}
+
+impl wasmi::ImportResolver for HostResolver<'_> {
     fn resolve_func(
         &self,
         module_name: &str,
         field_name: &str,
         signature: &wasmi::Signature,
     ) -> Result<wasmi::FuncRef, wasmi::Error> {
+        if let Some(func) = self.resolve_linked(module_name, field_name, "func", |export| {
+            export.as_func().cloned()
+        }) {
+            return func;
+        }
+
         let host_fn = HostFunc::resolve_func(module_name, field_name, signature)?;
-        Ok(host_fn.func_ref())
+        if self.manifest.on_denied == OnDenied::FailLink {
+            if let Err(denied) = self
+                .manifest
+                .check(host_fn.module_name(), host_fn.field_name())
+            {
+                tracing::error!(%denied, "denied func import");
+                return Err(wasmi::Error::Instantiation(denied.to_string()));
+            }
+        }
+        Ok(host_fn.func_ref(self.id))
     }
 
     fn resolve_global(
@@ -173,6 +585,12 @@ impl wasmi::ImportResolver for HostResolver {
         field_name: &str,
         descriptor: &wasmi::GlobalDescriptor,
     ) -> Result<wasmi::GlobalRef, wasmi::Error> {
+        if let Some(global) = self.resolve_linked(module_name, field_name, "global", |export| {
+            export.as_global().cloned()
+        }) {
+            return global;
+        }
+
         tracing::error!(
             module_name,
             field_name,
@@ -190,6 +608,14 @@ impl wasmi::ImportResolver for HostResolver {
         field_name: &str,
         descriptor: &wasmi::MemoryDescriptor,
     ) -> Result<wasmi::MemoryRef, wasmi::Error> {
+        if let Some(memory) = self.resolve_linked(module_name, field_name, "memory", |export| {
+            export.as_memory().cloned()
+        }) {
+            let memory = memory?;
+            *self.imported_memory.borrow_mut() = Some(memory.clone());
+            return Ok(memory);
+        }
+
         tracing::error!(
             module_name,
             field_name,
@@ -207,6 +633,12 @@ impl wasmi::ImportResolver for HostResolver {
         field_name: &str,
         descriptor: &wasmi::TableDescriptor,
     ) -> Result<wasmi::TableRef, wasmi::Error> {
+        if let Some(table) = self.resolve_linked(module_name, field_name, "table", |export| {
+            export.as_table().cloned()
+        }) {
+            return table;
+        }
+
         tracing::error!(
             module_name,
             field_name,
@@ -219,14 +651,31 @@ impl wasmi::ImportResolver for HostResolver {
     }
 }
 
-pub fn run_wasm(binary: &[u8]) -> Result<(), wasmi::Error> {
-    let module = wasmi::Module::from_buffer(binary)?;
-    // Instantiate the module and it's corresponding `Host` instance.
-    let instance = wasmi::ModuleInstance::new(&module, &HostResolver)?;
-    let mut host = Host::new(instance.not_started_instance())?;
-    let instance = instance.run_start(&mut host)?;
+/// Runs `binary` to completion on the current core, returning its exit
+/// status.
+///
+/// Errors loading or instantiating the module are returned as errors, while
+/// traps in the guest are returned as [`ExitStatus::Trapped`].
+pub fn run_wasm(binary: &[u8]) -> Result<ExitStatus, wasmi::Error> {
+    run_wasm_with_args(binary, &[], &[])
+}
+
+/// Runs `binary` with the command-line arguments `argv` and the environment
+/// variables `env`.
+pub fn run_wasm_with_args(
+    binary: &[u8],
+    argv: &[&str],
+    env: &[(&str, &str)],
+) -> Result<ExitStatus, wasmi::Error> {
+    let mut host = Host::new();
+    host.set_args(argv.iter().copied());
+    host.set_env(env.iter().copied());
+    let module = host.load(binary)?;
+    let id = InstanceId(host.instances.len());
+    let instance = host.instantiate(&module)?;
 
-    // FIXME: We should probably use resumable calls here.
-    instance.invoke_export("_start", &[], &mut host)?;
-    Ok(())
+    // NOTE: This runs the module to completion on the current core. To run a
+    // module as a task that yields to the scheduler, use `spawn_wasm`.
+    let result = instance.invoke_export("_start", &[], &mut host);
+    exit::exit_status(&host, id, result.map(|_| ()))
 }
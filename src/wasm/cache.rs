@@ -0,0 +1,86 @@
+//! A kernel-wide cache of compiled WASM modules.
+//!
+//! Compiling a module parses, validates, and instruments it, which is much
+//! more expensive than instantiating it. Modules are cached by the SHA-256
+//! hash of their binary, so that programs which are run many times (such as
+//! those in the initramfs) are only compiled once.
+use super::{CompiledModule, WasmLimits};
+use alloc::collections::BTreeMap;
+use core::{
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use mycelium_util::sync::{blocking::Mutex, Lazy};
+use sha2::{Digest, Sha256};
+
+/// Statistics about the module cache.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CacheStats {
+    /// The number of cached modules.
+    pub modules: usize,
+    /// The approximate amount of memory used by cached modules, in bytes.
+    pub bytes: usize,
+    /// The number of times a module was found in the cache.
+    pub hits: usize,
+    /// The number of times a module had to be compiled.
+    pub misses: usize,
+}
+
+type Key = ([u8; 32], WasmLimits);
+
+static CACHE: Lazy<Mutex<BTreeMap<Key, CompiledModule>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+static HITS: AtomicUsize = AtomicUsize::new(0);
+static MISSES: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the compiled module for `binary` with `limits` applied to it,
+/// compiling it if it isn't already cached.
+pub fn compile(binary: &[u8], limits: WasmLimits) -> Result<CompiledModule, wasmi::Error> {
+    let key = (Sha256::digest(binary).into(), limits);
+    if let Some(module) = CACHE.lock().get(&key) {
+        HITS.fetch_add(1, Ordering::Relaxed);
+        return Ok(module.clone());
+    }
+
+    MISSES.fetch_add(1, Ordering::Relaxed);
+    // don't hold the lock while compiling. if another core compiles the
+    // same module at the same time, the first one to finish wins.
+    let module = CompiledModule::new(binary, limits)?;
+    let module = CACHE.lock().entry(key).or_insert(module).clone();
+    tracing::debug!(size = module.size(), "cached compiled WASM module");
+    Ok(module)
+}
+
+/// Removes every module from the cache.
+///
+/// Modules which are still in use are freed once they're no longer
+/// referenced.
+pub fn clear() {
+    CACHE.lock().clear();
+}
+
+/// Returns statistics about the module cache.
+pub fn stats() -> CacheStats {
+    let cache = CACHE.lock();
+    CacheStats {
+        modules: cache.len(),
+        bytes: cache.values().map(CompiledModule::size).sum(),
+        hits: HITS.load(Ordering::Relaxed),
+        misses: MISSES.load(Ordering::Relaxed),
+    }
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            modules,
+            bytes,
+            hits,
+            misses,
+        } = self;
+        write!(
+            f,
+            "{modules} modules, {bytes} B ({hits} hits, {misses} misses)"
+        )
+    }
+}
@@ -0,0 +1,249 @@
+//! Capability-based permissions for WASM instances.
+//!
+//! Every instance is created with a [`Manifest`], which lists the
+//! [`Capabilities`] it is granted. Imports of host functions which require a
+//! capability the instance wasn't granted either fail to link, or trap with
+//! [`PermissionDenied`] when they're called, depending on the manifest's
+//! [`OnDenied`] policy. This lets untrusted guests run alongside trusted
+//! ones in the same [`Host`](super::Host).
+//!
+//! Access to stdin, stdout, and stderr is checked when they're read or
+//! written rather than when `fd_read` and `fd_write` are imported, since
+//! those functions are also used for files the host opened for the guest.
+//! Reading or writing a stream the instance may not access returns `EPERM`.
+use alloc::boxed::Box;
+use core::{fmt, ops};
+
+/// A set of capabilities that may be granted to a WASM instance.
+#[derive(Copy, Clone, Default, Eq, PartialEq, Hash)]
+pub struct Capabilities(u32);
+
+/// The capabilities granted to a WASM instance when it's instantiated, and
+/// how imports that require other capabilities are handled.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Manifest {
+    /// The capabilities granted to the instance.
+    pub capabilities: Capabilities,
+    /// What happens when the instance imports a host function that requires
+    /// capabilities it wasn't granted.
+    pub on_denied: OnDenied,
+}
+
+/// How imports of host functions that an instance may not call are handled.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum OnDenied {
+    /// Instantiating the module fails.
+    FailLink,
+    /// The import is linked, but calling it traps with [`PermissionDenied`].
+    #[default]
+    Trap,
+}
+
+/// Returned as a host trap when an instance calls a host function that
+/// requires capabilities it wasn't granted.
+#[derive(Debug)]
+pub struct PermissionDenied {
+    module_name: &'static str,
+    field_name: &'static str,
+    missing: Capabilities,
+}
+
+// === impl Capabilities ===
+
+impl Capabilities {
+    /// No capabilities.
+    pub const NONE: Self = Self(0);
+    /// May read from stdin.
+    pub const STDIN: Self = Self(1 << 0);
+    /// May write to stdout and stderr.
+    pub const STDOUT: Self = Self(1 << 1);
+    /// May read the clocks, and wait for timeouts with `poll_oneoff`.
+    pub const CLOCK: Self = Self(1 << 2);
+    /// May read random bytes from the kernel's CSPRNG.
+    pub const RANDOM: Self = Self(1 << 3);
+    /// May read its command-line arguments and environment variables.
+    pub const ENV: Self = Self(1 << 4);
+    /// May list the devices in the PCI device registry with
+    /// `mycelium::pci_device_count` and `mycelium::pci_device`.
+    pub const PCI: Self = Self(1 << 5);
+    /// May write to the kernel log with `mycelium::log`.
+    pub const LOG: Self = Self(1 << 6);
+    /// May spawn new tasks with `mycelium::spawn`.
+    pub const SPAWN: Self = Self(1 << 7);
+
+    /// The capabilities needed by ordinary WASI programs.
+    pub const WASI: Self = Self::STDIN
+        .union(Self::STDOUT)
+        .union(Self::CLOCK)
+        .union(Self::RANDOM)
+        .union(Self::ENV);
+
+    /// Every capability.
+    pub const ALL: Self = Self::WASI
+        .union(Self::PCI)
+        .union(Self::LOG)
+        .union(Self::SPAWN);
+
+    const NAMES: [(Self, &'static str); 8] = [
+        (Self::STDIN, "STDIN"),
+        (Self::STDOUT, "STDOUT"),
+        (Self::CLOCK, "CLOCK"),
+        (Self::RANDOM, "RANDOM"),
+        (Self::ENV, "ENV"),
+        (Self::PCI, "PCI"),
+        (Self::LOG, "LOG"),
+        (Self::SPAWN, "SPAWN"),
+    ];
+
+    /// Returns the capabilities in either `self` or `other`.
+    #[must_use]
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    /// Returns the capabilities in `self` that aren't in `other`.
+    #[must_use]
+    pub const fn difference(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    /// Returns `true` if every capability in `other` is also in `self`.
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns the capabilities required to import the host function
+    /// `module_name::field_name`.
+    ///
+    /// Host functions which don't appear here, such as those used by the
+    /// kernel's instrumentation, may be imported by any instance.
+    pub(super) fn required(module_name: &str, field_name: &str) -> Self {
+        match (module_name, field_name) {
+            ("mycelium", "log") => Self::LOG,
+            ("mycelium", "spawn") => Self::SPAWN,
+            ("mycelium", "sleep_ms") => Self::CLOCK,
+            ("mycelium", "pci_device_count" | "pci_device") => Self::PCI,
+            (module, _) if !module.starts_with("wasi") => Self::NONE,
+            (_, "clock_res_get" | "clock_time_get" | "poll_oneoff") => Self::CLOCK,
+            (_, "random_get") => Self::RANDOM,
+            (_, "args_get" | "args_sizes_get" | "environ_get" | "environ_sizes_get") => Self::ENV,
+            _ => Self::NONE,
+        }
+    }
+}
+
+impl ops::BitOr for Capabilities {
+    type Output = Self;
+    fn bitor(self, rhs: Self) -> Self {
+        self.union(rhs)
+    }
+}
+
+impl ops::BitOrAssign for Capabilities {
+    fn bitor_assign(&mut self, rhs: Self) {
+        *self = self.union(rhs);
+    }
+}
+
+impl fmt::Debug for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("NONE");
+        }
+
+        let mut names = Self::NAMES
+            .iter()
+            .filter(|(cap, _)| self.contains(*cap))
+            .map(|(_, name)| name);
+        if let Some(name) = names.next() {
+            f.write_str(name)?;
+        }
+        for name in names {
+            write!(f, " | {name}")?;
+        }
+        Ok(())
+    }
+}
+
+// === impl Manifest ===
+
+impl Manifest {
+    /// A manifest granting every capability.
+    pub const TRUSTED: Self = Self::new(Capabilities::ALL);
+
+    /// Returns a manifest granting `capabilities`, whose instances trap when
+    /// they call host functions requiring other capabilities.
+    pub const fn new(capabilities: Capabilities) -> Self {
+        Self {
+            capabilities,
+            on_denied: OnDenied::Trap,
+        }
+    }
+
+    /// Returns a manifest whose instances fail to link if they import host
+    /// functions requiring capabilities they weren't granted.
+    #[must_use]
+    pub const fn fail_link(self) -> Self {
+        Self {
+            on_denied: OnDenied::FailLink,
+            ..self
+        }
+    }
+
+    /// Checks whether an instance with this manifest may call the host
+    /// function `module_name::field_name`.
+    pub(super) fn check(
+        &self,
+        module_name: &'static str,
+        field_name: &'static str,
+    ) -> Result<(), PermissionDenied> {
+        let missing = Capabilities::required(module_name, field_name).difference(self.capabilities);
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        Err(PermissionDenied {
+            module_name,
+            field_name,
+            missing,
+        })
+    }
+}
+
+impl Default for Manifest {
+    /// Returns a manifest granting the capabilities needed by ordinary WASI
+    /// programs.
+    fn default() -> Self {
+        Self::new(Capabilities::WASI)
+    }
+}
+
+// === impl PermissionDenied ===
+
+impl PermissionDenied {
+    /// Returns the capabilities the instance would need to call the host
+    /// function.
+    pub fn missing(&self) -> Capabilities {
+        self.missing
+    }
+
+    pub(super) fn into_trap(self) -> wasmi::Trap {
+        wasmi::TrapKind::Host(Box::new(self)).into()
+    }
+}
+
+impl fmt::Display for PermissionDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "EPERM: {}::{} requires capabilities {:?}",
+            self.module_name, self.field_name, self.missing
+        )
+    }
+}
+
+impl wasmi::HostError for PermissionDenied {}
@@ -23,6 +23,60 @@ pub trait WasmPrimitive: Sized {
     fn into_wasm_value(self) -> wasmi::RuntimeValue;
 }
 
+/// Trait describing a type which is passed to host functions as one or more
+/// WASM values.
+///
+/// Every [`WasmPrimitive`] is a single value. Types such as
+/// [`GuestSlice`](super::GuestSlice) are made up of several values, which
+/// are passed to the host function as consecutive parameters.
+pub trait WasmValues: Sized {
+    /// The types of the values, in the order they're passed.
+    const TYPES: &'static [wasmi::ValueType];
+
+    /// Converts `values`, which has exactly one value for each of
+    /// [`Self::TYPES`].
+    fn from_wasm_values(values: &[wasmi::RuntimeValue]) -> Result<Self, ConvertError>;
+}
+
+/// Trait describing a type which can be returned by host functions.
+///
+/// The interpreter predates the multi-value proposal, so host functions may
+/// only return nothing, or a single [`WasmPrimitive`]. Host functions with
+/// more results return them through pointers into the guest's memory, like
+/// WASI does.
+pub trait WasmResult {
+    const TYPE: Option<wasmi::ValueType>;
+
+    fn into_wasm_result(self) -> Option<wasmi::RuntimeValue>;
+}
+
+impl<T: WasmPrimitive> WasmValues for T {
+    const TYPES: &'static [wasmi::ValueType] = &[T::TYPE];
+
+    fn from_wasm_values(values: &[wasmi::RuntimeValue]) -> Result<Self, ConvertError> {
+        match *values {
+            [value] => T::from_wasm_value(value),
+            _ => Err(ConvertError),
+        }
+    }
+}
+
+impl<T: WasmPrimitive> WasmResult for T {
+    const TYPE: Option<wasmi::ValueType> = Some(T::TYPE);
+
+    fn into_wasm_result(self) -> Option<wasmi::RuntimeValue> {
+        Some(self.into_wasm_value())
+    }
+}
+
+impl WasmResult for () {
+    const TYPE: Option<wasmi::ValueType> = None;
+
+    fn into_wasm_result(self) -> Option<wasmi::RuntimeValue> {
+        None
+    }
+}
+
 macro_rules! impl_wasm_primitive {
     ($($rust:ty = $wasm:ident;)*) => {
         $(
@@ -53,3 +107,40 @@ impl_wasm_primitive! {
     wasmi::nan_preserving_float::F32 = F32;
     wasmi::nan_preserving_float::F64 = F64;
 }
+
+impl WasmPrimitive for f32 {
+    const TYPE: wasmi::ValueType = wasmi::ValueType::F32;
+
+    fn from_wasm_value(value: wasmi::RuntimeValue) -> Result<Self, ConvertError> {
+        wasmi::nan_preserving_float::F32::from_wasm_value(value).map(|value| value.to_float())
+    }
+
+    fn into_wasm_value(self) -> wasmi::RuntimeValue {
+        wasmi::nan_preserving_float::F32::from_float(self).into_wasm_value()
+    }
+}
+
+impl WasmPrimitive for f64 {
+    const TYPE: wasmi::ValueType = wasmi::ValueType::F64;
+
+    fn from_wasm_value(value: wasmi::RuntimeValue) -> Result<Self, ConvertError> {
+        wasmi::nan_preserving_float::F64::from_wasm_value(value).map(|value| value.to_float())
+    }
+
+    fn into_wasm_value(self) -> wasmi::RuntimeValue {
+        wasmi::nan_preserving_float::F64::from_float(self).into_wasm_value()
+    }
+}
+
+impl WasmPrimitive for super::wasi::Errno {
+    const TYPE: wasmi::ValueType = wasmi::ValueType::I32;
+
+    fn from_wasm_value(value: wasmi::RuntimeValue) -> Result<Self, ConvertError> {
+        let errno = u16::from_wasm_value(value)?;
+        Self::try_from(errno).map_err(|_| ConvertError)
+    }
+
+    fn into_wasm_value(self) -> wasmi::RuntimeValue {
+        u16::from(self).into_wasm_value()
+    }
+}
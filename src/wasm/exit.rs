@@ -0,0 +1,153 @@
+//! How WASM programs finish running.
+//!
+//! A guest may finish by returning from `_start`, by calling WASI's
+//! `proc_exit`, or by trapping. `proc_exit` is implemented as a host trap
+//! carrying the exit code, which unwinds the interpreter's stack back to the
+//! host.
+use super::{symbols::Frame, trace, Host, InstanceId};
+use alloc::vec::Vec;
+use core::fmt;
+
+/// The outcome of running a WASM program.
+#[derive(Debug)]
+pub enum ExitStatus {
+    /// The program exited normally with the given exit code, either by
+    /// returning from `_start` (with exit code 0) or by calling `proc_exit`.
+    Exited(u32),
+    /// The program was terminated by a trap.
+    Trapped(GuestTrap),
+}
+
+/// A trap that terminated a WASM program.
+#[derive(Debug)]
+pub struct GuestTrap {
+    trap: wasmi::Trap,
+    /// The guest's call stack when it trapped, innermost frame first.
+    frames: Vec<Frame>,
+}
+
+/// Returned as a host trap by `proc_exit`, with the guest's exit code.
+#[derive(Debug)]
+pub(super) struct ProcExit(pub(super) u32);
+
+/// Returns the exit status of the guest instance `id`, given the result of
+/// running its `_start` function.
+///
+/// Errors other than traps are returned as errors. If the guest trapped, its
+/// symbolized call stack is logged.
+pub(super) fn exit_status(
+    host: &Host,
+    id: InstanceId,
+    result: Result<(), wasmi::Error>,
+) -> Result<ExitStatus, wasmi::Error> {
+    let trap = match result {
+        Ok(()) => return Ok(ExitStatus::Exited(0)),
+        Err(wasmi::Error::Trap(trap)) => trap,
+        Err(error) => return Err(error),
+    };
+
+    if let wasmi::TrapKind::Host(error) = trap.kind() {
+        if let Some(&ProcExit(code)) = error.downcast_ref::<ProcExit>() {
+            return Ok(ExitStatus::Exited(code));
+        }
+    }
+
+    let frames = host
+        .instances
+        .get(id.0)
+        .map(|instance| {
+            let stack = trace::backtrace(&instance.module, &instance.stack);
+            let frames = instance.compiled.symbols().symbolize(stack);
+            // the backtrace has been captured, so the instance can be called
+            // again.
+            trace::reset_call_depth(&instance.module)?;
+            Ok::<_, wasmi::Error>(frames)
+        })
+        .transpose()?
+        .unwrap_or_default();
+    let trap = GuestTrap { trap, frames };
+    tracing::error!(target: "wasm", "guest trapped: {}", trap.kind_name());
+    for (idx, frame) in trap.frames.iter().enumerate() {
+        tracing::error!(target: "wasm", "  {idx}: {frame}");
+    }
+    Ok(ExitStatus::Trapped(trap))
+}
+
+// === impl ExitStatus ===
+
+impl ExitStatus {
+    /// Returns `true` if the program exited with exit code 0.
+    pub fn success(&self) -> bool {
+        matches!(self, Self::Exited(0))
+    }
+
+    /// Returns the program's exit code, or `None` if it trapped.
+    pub fn code(&self) -> Option<u32> {
+        match self {
+            Self::Exited(code) => Some(*code),
+            Self::Trapped(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Exited(code) => write!(f, "exited with code {code}"),
+            Self::Trapped(trap) => write!(f, "trapped: {trap}"),
+        }
+    }
+}
+
+// === impl GuestTrap ===
+
+impl GuestTrap {
+    /// Returns the kind of trap.
+    pub fn kind(&self) -> &wasmi::TrapKind {
+        self.trap.kind()
+    }
+
+    /// Returns the index of the function that was executing when the guest
+    /// trapped, if it is known.
+    pub fn func(&self) -> Option<u32> {
+        self.frames.first().map(Frame::func)
+    }
+
+    /// Returns the guest's call stack when it trapped, innermost frame first.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    fn kind_name(&self) -> impl fmt::Display + '_ {
+        struct KindName<'a>(&'a wasmi::TrapKind);
+        impl fmt::Display for KindName<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self.0 {
+                    wasmi::TrapKind::Host(error) => fmt::Display::fmt(error, f),
+                    kind => write!(f, "{kind:?}"),
+                }
+            }
+        }
+        KindName(self.kind())
+    }
+}
+
+impl fmt::Display for GuestTrap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.kind_name(), f)?;
+        if let Some(frame) = self.frames.first() {
+            write!(f, " in {frame}")?;
+        }
+        Ok(())
+    }
+}
+
+// === impl ProcExit ===
+
+impl fmt::Display for ProcExit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "guest exited with code {}", self.0)
+    }
+}
+
+impl wasmi::HostError for ProcExit {}
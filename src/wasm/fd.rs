@@ -0,0 +1,206 @@
+//! WASI file descriptor tables, shared by the instances of a `wasm::Host`.
+use super::wasi::{self, Errno, Filetype, Rights, Whence};
+use alloc::{borrow::Cow, string::String, vec::Vec};
+use core::cmp;
+
+/// A WASI file descriptor number.
+pub type Fd = u32;
+
+/// A table of the file descriptors open in a [`Host`](super::Host).
+///
+/// Every instance the host creates uses the same table, so that a descriptor
+/// opened through one linked instance's exports is valid in the others.
+///
+/// Descriptors 0, 1, and 2 are always stdin, stdout, and stderr when the
+/// table is created. New descriptors are allocated at the lowest free index,
+/// like POSIX file descriptors.
+#[derive(Debug)]
+pub struct FdTable {
+    entries: Vec<Option<Entry>>,
+}
+
+/// An open file descriptor.
+#[derive(Debug)]
+pub struct Entry {
+    pub(super) handle: Handle,
+    /// The `fdflags` for this descriptor.
+    pub(super) flags: u16,
+    /// Rights that apply to this descriptor.
+    pub(super) rights_base: Rights,
+    /// Maximum rights for descriptors opened through this one.
+    pub(super) rights_inheriting: Rights,
+}
+
+/// The kernel object referenced by a file descriptor.
+#[derive(Debug)]
+pub enum Handle {
+    Stdin,
+    Stdout,
+    Stderr,
+    File(File),
+    Dir(Dir),
+}
+
+/// A read-only, in-memory file.
+#[derive(Debug)]
+pub struct File {
+    data: Cow<'static, [u8]>,
+    pos: u64,
+}
+
+/// A preopened directory.
+#[derive(Debug)]
+pub struct Dir {
+    name: String,
+}
+
+// === impl FdTable ===
+
+impl FdTable {
+    pub fn new() -> Self {
+        let mut table = Self {
+            entries: Vec::with_capacity(3),
+        };
+        table.insert(Entry::new(Handle::Stdin, wasi::RIGHTS_STDIN));
+        table.insert(Entry::new(Handle::Stdout, wasi::RIGHTS_STDOUT));
+        table.insert(Entry::new(Handle::Stderr, wasi::RIGHTS_STDOUT));
+        table
+    }
+
+    /// Inserts a new entry at the lowest free descriptor, returning that
+    /// descriptor.
+    pub fn insert(&mut self, entry: Entry) -> Fd {
+        let idx = match self.entries.iter().position(Option::is_none) {
+            Some(idx) => {
+                self.entries[idx] = Some(entry);
+                idx
+            }
+            None => {
+                self.entries.push(Some(entry));
+                self.entries.len() - 1
+            }
+        };
+        idx as Fd
+    }
+
+    /// Opens a read-only file containing `data`.
+    pub fn open_file(&mut self, data: impl Into<Cow<'static, [u8]>>) -> Fd {
+        let file = File {
+            data: data.into(),
+            pos: 0,
+        };
+        self.insert(Entry::new(Handle::File(file), wasi::RIGHTS_FILE))
+    }
+
+    /// Preopens a directory, so that it is visible to the guest through
+    /// `fd_prestat_get`.
+    pub fn preopen_dir(&mut self, name: impl Into<String>) -> Fd {
+        let dir = Dir { name: name.into() };
+        self.insert(Entry::new(Handle::Dir(dir), wasi::RIGHTS_DIR))
+    }
+
+    pub fn get(&self, fd: Fd) -> Result<&Entry, Errno> {
+        self.entries
+            .get(fd as usize)
+            .and_then(Option::as_ref)
+            .ok_or(Errno::Badf)
+    }
+
+    pub fn get_mut(&mut self, fd: Fd) -> Result<&mut Entry, Errno> {
+        self.entries
+            .get_mut(fd as usize)
+            .and_then(Option::as_mut)
+            .ok_or(Errno::Badf)
+    }
+
+    /// Closes `fd`, returning the entry that was open there.
+    pub fn close(&mut self, fd: Fd) -> Result<Entry, Errno> {
+        let entry = self
+            .entries
+            .get_mut(fd as usize)
+            .and_then(Option::take)
+            .ok_or(Errno::Badf)?;
+
+        // don't let the table grow forever if the guest opens and closes a
+        // lot of descriptors.
+        while let Some(None) = self.entries.last() {
+            self.entries.pop();
+        }
+
+        Ok(entry)
+    }
+}
+
+impl Default for FdTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// === impl Entry ===
+
+impl Entry {
+    pub fn new(handle: Handle, rights: Rights) -> Self {
+        Self {
+            handle,
+            flags: 0,
+            rights_base: rights,
+            rights_inheriting: rights,
+        }
+    }
+
+    /// Returns an error if this descriptor does not have all of the provided
+    /// `rights`.
+    pub fn check_rights(&self, rights: Rights) -> Result<(), Errno> {
+        if self.rights_base & rights != rights {
+            return Err(Errno::Notcapable);
+        }
+        Ok(())
+    }
+
+    pub fn filetype(&self) -> Filetype {
+        match self.handle {
+            Handle::Stdin | Handle::Stdout | Handle::Stderr => Filetype::CharacterDevice,
+            Handle::File(_) => Filetype::RegularFile,
+            Handle::Dir(_) => Filetype::Directory,
+        }
+    }
+}
+
+// === impl File ===
+
+impl File {
+    /// Reads from the current position into `buf`, returning the number of
+    /// bytes read.
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        let remaining = self.data.get(self.pos as usize..).unwrap_or(&[]);
+        let len = cmp::min(remaining.len(), buf.len());
+        buf[..len].copy_from_slice(&remaining[..len]);
+        self.pos += len as u64;
+        len
+    }
+
+    /// Moves the current position, returning the new position.
+    pub fn seek(&mut self, delta: i64, whence: Whence) -> Result<u64, Errno> {
+        let base = match whence {
+            Whence::Set => 0,
+            Whence::Cur => self.pos,
+            Whence::End => self.data.len() as u64,
+        };
+        let pos = base.checked_add_signed(delta).ok_or(Errno::Inval)?;
+        self.pos = pos;
+        Ok(pos)
+    }
+
+    pub fn tell(&self) -> u64 {
+        self.pos
+    }
+}
+
+// === impl Dir ===
+
+impl Dir {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
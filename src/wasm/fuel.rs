@@ -0,0 +1,109 @@
+//! Instruction fuel metering.
+//!
+//! The wasmi interpreter has no way to interrupt a guest that never calls into
+//! the host. To let WASM tasks be preempted, modules are instrumented before
+//! they are instantiated: every function entry and every loop header calls a
+//! host function, passing the number of instructions executed until the next
+//! metering point. When the current slice of fuel runs out, that host
+//! function returns a [`Yield`] trap, which suspends the resumable invocation
+//! so that the task can yield to the scheduler.
+use super::{instrument, limits::LimitExceeded, Host};
+use alloc::{boxed::Box, vec, vec::Vec};
+use core::{cmp, fmt};
+use parity_wasm::elements::{Instruction, Module, ValueType};
+
+/// The import module of the fuel metering host function.
+///
+/// This is not a valid module name in most source languages, so it won't
+/// collide with any of the guest's own imports.
+pub(super) const MODULE: &str = "__mycelium_fuel";
+
+/// The import name of the fuel metering host function.
+pub(super) const FUNC: &str = "consume";
+
+/// The number of instructions a WASM task may execute before it yields to the
+/// scheduler.
+pub const FUEL_PER_SLICE: u64 = 100_000;
+
+/// Returned as a host trap by the fuel metering function when the current
+/// slice of fuel has been used up.
+#[derive(Debug)]
+pub(super) struct Yield;
+
+/// Rewrites `module` so that it calls the fuel metering host function.
+pub(super) fn instrument(module: &mut Module) -> Result<(), wasmi::Error> {
+    let consume = instrument::add_func_import(module, MODULE, FUNC, vec![ValueType::I32])?;
+    if let Some(code) = module.code_section_mut() {
+        for body in code.bodies_mut() {
+            let instrs = body.code_mut().elements_mut();
+            *instrs = meter(instrs, consume);
+        }
+    }
+    Ok(())
+}
+
+/// Consumes `amount` fuel, yielding if the current slice has been used up.
+///
+/// If the instance's total fuel limit has been used up, this traps instead.
+#[tracing::instrument(level = "trace", skip(host))]
+pub(super) fn consume(host: &mut Host, amount: u32) -> Result<(), wasmi::Trap> {
+    let amount = amount as u64;
+    if let Some(ref mut remaining) = host.fuel_remaining {
+        if *remaining < amount {
+            *remaining = 0;
+            return Err(wasmi::TrapKind::Host(Box::new(LimitExceeded::Fuel)).into());
+        }
+        *remaining -= amount;
+    }
+
+    host.fuel = host.fuel.saturating_sub(amount);
+    // the start function can't be resumed, so it always runs to completion.
+    if host.fuel == 0 && host.preemptible {
+        return Err(wasmi::TrapKind::Host(Box::new(Yield)).into());
+    }
+    Ok(())
+}
+
+/// Inserts a call to `consume` at the start of a function body and after
+/// every loop header, charging the number of instructions up to the next
+/// metering point.
+///
+/// This overcharges for segments that branch out early, but it guarantees
+/// that a guest can't run for long without calling into the host, since
+/// every backwards branch targets a loop header.
+fn meter(instrs: &[Instruction], consume: u32) -> Vec<Instruction> {
+    fn charge(out: &mut [Instruction], at: usize, cost: u32) {
+        out[at] = Instruction::I32Const(cmp::min(cost, i32::MAX as u32) as i32);
+    }
+
+    let mut out = Vec::with_capacity(instrs.len() + 2);
+    let mut segment = out.len();
+    let mut cost = 0u32;
+    out.push(Instruction::I32Const(0));
+    out.push(Instruction::Call(consume));
+
+    for instr in instrs {
+        out.push(instr.clone());
+        cost = cost.saturating_add(1);
+        if let Instruction::Loop(_) = instr {
+            charge(&mut out, segment, cost);
+            segment = out.len();
+            cost = 0;
+            out.push(Instruction::I32Const(0));
+            out.push(Instruction::Call(consume));
+        }
+    }
+
+    charge(&mut out, segment, cost);
+    out
+}
+
+// === impl Yield ===
+
+impl fmt::Display for Yield {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("fuel exhausted, yielding to the scheduler")
+    }
+}
+
+impl wasmi::HostError for Yield {}
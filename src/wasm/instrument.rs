@@ -0,0 +1,133 @@
+//! Rewriting WASM modules before they are instantiated.
+//!
+//! Some things the kernel needs from guests, such as preemption and call
+//! depth limits, can't be done by the wasmi interpreter itself. Instead,
+//! modules are rewritten to call into the host at the points where the kernel
+//! needs to intervene.
+use super::{fuel, symbols::Symbols, trace, WasmLimits};
+use alloc::{format, vec::Vec};
+use parity_wasm::elements::{
+    External, FunctionType, GlobalEntry, GlobalSection, GlobalType, ImportCountType, ImportEntry,
+    ImportSection, InitExpr, Instruction, Internal, Module, Section, Type, TypeSection, ValueType,
+};
+
+/// Parses `binary`, instruments it, and applies `limits` to it, returning its
+/// debug information and the instrumented binary.
+pub(super) fn load(binary: &[u8], limits: &WasmLimits) -> Result<(Symbols, Vec<u8>), wasmi::Error> {
+    let module = Module::from_bytes(binary)
+        .map_err(|error| wasmi::Error::Validation(format!("invalid module: {error}")))?;
+    let (symbols, mut module) = Symbols::extract(binary, module);
+
+    trace::instrument(&mut module)?;
+    fuel::instrument(&mut module)?;
+    limits.apply(&mut module)?;
+
+    let binary = module.into_bytes().map_err(error)?;
+    Ok((symbols, binary))
+}
+
+/// Appends an import of the host function `module_name::field_name` to
+/// `module`, returning its function index.
+///
+/// Because imported functions come first in the function index space, every
+/// reference to a function defined by the module is shifted by one.
+pub(super) fn add_func_import(
+    module: &mut Module,
+    module_name: &str,
+    field_name: &str,
+    params: Vec<ValueType>,
+) -> Result<u32, wasmi::Error> {
+    let sig = Type::Function(FunctionType::new(params, Vec::new()));
+    if module.type_section().is_none() {
+        module
+            .insert_section(Section::Type(TypeSection::default()))
+            .map_err(error)?;
+    }
+    let types = module
+        .type_section_mut()
+        .expect("type section was inserted");
+    let type_idx = match types.types().iter().position(|ty| ty == &sig) {
+        Some(idx) => idx,
+        None => {
+            types.types_mut().push(sig);
+            types.types().len() - 1
+        }
+    } as u32;
+
+    let func = module.import_count(ImportCountType::Function) as u32;
+    if module.import_section().is_none() {
+        module
+            .insert_section(Section::Import(ImportSection::default()))
+            .map_err(error)?;
+    }
+    module
+        .import_section_mut()
+        .expect("import section was inserted")
+        .entries_mut()
+        .push(ImportEntry::new(
+            module_name.into(),
+            field_name.into(),
+            External::Function(type_idx),
+        ));
+
+    let shift = |idx: &mut u32| {
+        if *idx >= func {
+            *idx += 1;
+        }
+    };
+
+    if let Some(exports) = module.export_section_mut() {
+        for export in exports.entries_mut() {
+            if let Internal::Function(idx) = export.internal_mut() {
+                shift(idx);
+            }
+        }
+    }
+
+    if let Some(elements) = module.elements_section_mut() {
+        for segment in elements.entries_mut() {
+            segment.members_mut().iter_mut().for_each(shift);
+        }
+    }
+
+    if let Some(mut start) = module.start_section() {
+        shift(&mut start);
+        module.set_start_section(start);
+    }
+
+    if let Some(code) = module.code_section_mut() {
+        for body in code.bodies_mut() {
+            for instr in body.code_mut().elements_mut() {
+                if let Instruction::Call(idx) = instr {
+                    shift(idx);
+                }
+            }
+        }
+    }
+
+    Ok(func)
+}
+
+/// Adds a new mutable `i32` global to `module`, initialized to zero,
+/// returning its index.
+pub(super) fn add_global(module: &mut Module) -> Result<u32, wasmi::Error> {
+    let idx = module.globals_space() as u32;
+    if module.global_section().is_none() {
+        module
+            .insert_section(Section::Global(GlobalSection::default()))
+            .map_err(error)?;
+    }
+    module
+        .global_section_mut()
+        .expect("global section was inserted")
+        .entries_mut()
+        .push(GlobalEntry::new(
+            GlobalType::new(ValueType::I32, true),
+            InitExpr::new(alloc::vec![Instruction::I32Const(0), Instruction::End]),
+        ));
+    Ok(idx)
+}
+
+pub(super) fn error(error: parity_wasm::elements::Error) -> wasmi::Error {
+    wasmi::Error::Validation(format!("failed to instrument module: {error}"))
+}
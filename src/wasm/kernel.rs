@@ -0,0 +1,151 @@
+//! The `mycelium` host module, which exposes kernel services to WASM guests.
+//!
+//! Unlike WASI, these functions are specific to Mycelium, and let guest
+//! services take part in the kernel's logging and task system directly.
+use super::{
+    wasi::{self, Errno},
+    GuestPtr, GuestSlice, Host, WasmTask,
+};
+use crate::{drivers::pci, rt};
+use alloc::string::String;
+use core::time::Duration;
+
+/// Logs the UTF-8 message `msg` with the `tracing` level `level`.
+///
+/// Levels are numbered like those of the `log` crate: 1 is `ERROR`, 2 is
+/// `WARN`, 3 is `INFO`, 4 is `DEBUG`, and 5 is `TRACE`. Other levels return
+/// `EINVAL`.
+#[tracing::instrument(skip(host))]
+pub(super) fn log(host: &mut Host, level: u32, msg: GuestSlice<u8>) -> Result<Errno, wasmi::Trap> {
+    host.memory()?.with_direct_access(|mem| {
+        let msg = String::from_utf8_lossy(msg.as_bytes(mem)?);
+        match level {
+            1 => tracing::error!(target: "wasm", "{msg}"),
+            2 => tracing::warn!(target: "wasm", "{msg}"),
+            3 => tracing::info!(target: "wasm", "{msg}"),
+            4 => tracing::debug!(target: "wasm", "{msg}"),
+            5 => tracing::trace!(target: "wasm", "{msg}"),
+            _ => return Ok(Errno::Inval),
+        }
+        Ok(Errno::Success)
+    })
+}
+
+/// Spawns a new task running the exported function named `name`, in a new
+/// instance of the caller's module, and writes the task's ID to `task_id`.
+///
+/// The new instance is granted the same capabilities as the caller, but has
+/// no arguments or environment variables. The function must take no
+/// arguments and return no values, or `EINVAL` is returned. If the module
+/// has no such export, `ENOENT` is returned.
+///
+/// The new instance runs in a host of its own, so instances registered with
+/// the caller's [`Linker`](super::Linker) aren't available to it. If the
+/// caller imports anything from a registered instance, `ENOTSUP` is returned
+/// rather than spawning a task that would fail to link.
+#[tracing::instrument(skip(host))]
+pub(super) fn spawn(
+    host: &mut Host,
+    name: GuestSlice<u8>,
+    task_id: GuestPtr<u64>,
+) -> Result<Errno, wasmi::Trap> {
+    let memory = host.memory()?.clone();
+    let entry = memory.with_direct_access(|mem| {
+        name.as_bytes(mem)
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+    })?;
+    let Some(caller) = host.caller_instance() else {
+        return Ok(Errno::Fault);
+    };
+    if caller.linked {
+        tracing::warn!(
+            target: "wasm",
+            "can't spawn a module that imports from registered instances"
+        );
+        return Ok(Errno::Notsup);
+    }
+
+    let Some(func) = caller
+        .module
+        .export_by_name(&entry)
+        .and_then(|export| export.as_func().cloned())
+    else {
+        return Ok(Errno::Noent);
+    };
+    let signature = func.signature();
+    if !signature.params().is_empty() || signature.return_type().is_some() {
+        return Ok(Errno::Inval);
+    }
+
+    let task = WasmTask::from_module(caller.compiled.clone())
+        .with_manifest(caller.manifest)
+        .with_entry(entry);
+    let handle = rt::spawn(async move {
+        match task.await {
+            Ok(status) => tracing::info!(target: "wasm", %status, "spawned guest task finished"),
+            Err(error) => tracing::error!(target: "wasm", %error, "spawned guest task failed"),
+        }
+    });
+
+    memory.with_direct_access_mut(|mem| task_id.write(mem, handle.id().as_u64()))?;
+    Ok(Errno::Success)
+}
+
+/// Writes the number of devices in the kernel's PCI device registry to
+/// `count`.
+#[tracing::instrument(skip(host))]
+pub(super) fn pci_device_count(
+    host: &mut Host,
+    count: GuestPtr<u32>,
+) -> Result<Errno, wasmi::Trap> {
+    let len = pci::DEVICES.try_get().map_or(0, pci::DeviceRegistry::len);
+    host.memory()?
+        .with_direct_access_mut(|mem| count.write(mem, len as u32))?;
+    Ok(Errno::Success)
+}
+
+/// Writes the address and IDs of the device at `index` in the kernel's PCI
+/// device registry to `device`, or returns `ENOENT` if there's no such device.
+///
+/// The low 32 bits hold the device's address, with the function number in
+/// bits 0-2, the device number in bits 3-7, the bus in bits 8-15, and the
+/// segment group in bits 16-31. The vendor ID is in bits 32-47, and the
+/// device ID in bits 48-63.
+#[tracing::instrument(skip(host))]
+pub(super) fn pci_device(
+    host: &mut Host,
+    index: u32,
+    device: GuestPtr<u64>,
+) -> Result<Errno, wasmi::Trap> {
+    let Some((addr, _, id)) = pci::DEVICES
+        .try_get()
+        .and_then(|devices| devices.iter().nth(index as usize))
+    else {
+        return Ok(Errno::Noent);
+    };
+    let group = addr.group().map_or(0, |group| group.get());
+    let value = u64::from(addr.function())
+        | u64::from(addr.device()) << 3
+        | u64::from(addr.bus()) << 8
+        | u64::from(group) << 16
+        | u64::from(id.vendor_id()) << 32
+        | u64::from(id.device_id()) << 48;
+    host.memory()?
+        .with_direct_access_mut(|mem| device.write(mem, value))?;
+    Ok(Errno::Success)
+}
+
+/// Suspends the guest for `ms` milliseconds.
+#[tracing::instrument(skip(host))]
+pub(super) fn sleep_ms(host: &mut Host, ms: u64) -> Result<Errno, wasmi::Trap> {
+    Err(host.suspend(async move {
+        wasi::sleep(Duration::from_millis(ms)).await;
+        Ok(Errno::Success)
+    }))
+}
+
+/// Returns the ID of the kernel task running the guest, or 0 if it isn't
+/// running as a task.
+pub(super) fn task_id(_host: &mut Host) -> Result<u64, wasmi::Trap> {
+    Ok(rt::current_task_id().map_or(0, |id| id.as_u64()))
+}
@@ -0,0 +1,189 @@
+//! Resource limits for WASM instances.
+use super::{instrument, trace, Host};
+use alloc::{boxed::Box, format, vec::Vec};
+use core::{cmp, fmt};
+use parity_wasm::elements::{
+    BlockType, External, ImportCountType, Instruction, MemoryType, Module, TableType,
+};
+
+/// Limits on the resources a WASM instance may consume.
+///
+/// Memory and table limits are enforced when a module is loaded: modules
+/// whose initial memory or table sizes exceed the limits are rejected, and
+/// the maximum size of each memory and table is lowered to the limit, so
+/// that `memory.grow` and `table.grow` fail (returning -1 to the guest) past
+/// it. Call depth and fuel limits are enforced while the guest runs, and
+/// trap with a [`LimitExceeded`] error when they are exceeded.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct WasmLimits {
+    /// The maximum size of each linear memory, in 64 KiB WASM pages.
+    pub max_memory_pages: u32,
+    /// The maximum number of elements in each table.
+    pub max_table_elements: u32,
+    /// The maximum depth of nested calls between the guest's functions.
+    pub max_call_depth: u32,
+    /// The total number of instructions the guest may execute, or `None` if
+    /// it may run forever.
+    pub max_fuel: Option<u64>,
+}
+
+/// A [`WasmLimits`] limit exceeded by a running guest.
+///
+/// This is returned as a host trap.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LimitExceeded {
+    /// The guest used up all of its fuel.
+    Fuel,
+    /// The guest exceeded the maximum call depth.
+    CallDepth,
+}
+
+/// The import module of the limit enforcement host functions.
+pub(super) const MODULE: &str = "__mycelium_limits";
+
+/// Traps with [`LimitExceeded::CallDepth`].
+#[tracing::instrument(level = "debug", skip(_host))]
+pub(super) fn call_depth_exceeded(_host: &mut Host) -> Result<(), wasmi::Trap> {
+    Err(wasmi::TrapKind::Host(Box::new(LimitExceeded::CallDepth)).into())
+}
+
+// === impl WasmLimits ===
+
+impl WasmLimits {
+    pub const DEFAULT: Self = Self {
+        // 16 MiB
+        max_memory_pages: 256,
+        max_table_elements: 10_000,
+        max_call_depth: 4096,
+        max_fuel: None,
+    };
+
+    /// Checks `module`'s memories and tables against these limits, lowers
+    /// their maximum sizes to the limits, and instruments it to enforce the
+    /// call depth limit.
+    pub(super) fn apply(&self, module: &mut Module) -> Result<(), wasmi::Error> {
+        if let Some(imports) = module.import_section() {
+            for import in imports.entries() {
+                match import.external() {
+                    External::Memory(memory) => {
+                        self.check_memory(memory.limits().initial())?;
+                    }
+                    External::Table(table) => {
+                        self.check_table(table.limits().initial())?;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        if let Some(memories) = module.memory_section_mut() {
+            for memory in memories.entries_mut() {
+                let initial = memory.limits().initial();
+                self.check_memory(initial)?;
+                let max = memory.limits().maximum().unwrap_or(u32::MAX);
+                *memory = MemoryType::new(initial, Some(cmp::min(max, self.max_memory_pages)));
+            }
+        }
+
+        if let Some(tables) = module.table_section_mut() {
+            for table in tables.entries_mut() {
+                let initial = table.limits().initial();
+                self.check_table(initial)?;
+                let max = table.limits().maximum().unwrap_or(u32::MAX);
+                *table = TableType::new(initial, Some(cmp::min(max, self.max_table_elements)));
+            }
+        }
+
+        self.limit_call_depth(module)
+    }
+
+    fn check_memory(&self, pages: u32) -> Result<(), wasmi::Error> {
+        if pages > self.max_memory_pages {
+            return Err(wasmi::Error::Instantiation(format!(
+                "memory of {pages} pages exceeds the limit of {} pages",
+                self.max_memory_pages
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_table(&self, elements: u32) -> Result<(), wasmi::Error> {
+        if elements > self.max_table_elements {
+            return Err(wasmi::Error::Instantiation(format!(
+                "table of {elements} elements exceeds the limit of {} elements",
+                self.max_table_elements
+            )));
+        }
+        Ok(())
+    }
+
+    /// Precedes every call to a function defined by the module (or through a
+    /// table) with code that calls the `call_depth_exceeded` host function if
+    /// the call depth exceeds the limit.
+    ///
+    /// The call depth is the one tracked by [`trace::instrument`], so the
+    /// module must already have been instrumented for tracing. Calls to
+    /// imported functions are not counted, as host functions never call back
+    /// into the guest, and other modules count their own calls.
+    fn limit_call_depth(&self, module: &mut Module) -> Result<(), wasmi::Error> {
+        let depth = trace::depth_global(module).ok_or_else(|| {
+            wasmi::Error::Validation("module must be instrumented for tracing".into())
+        })?;
+        let exceeded =
+            instrument::add_func_import(module, MODULE, "call_depth_exceeded", Vec::new())?;
+        let imported_funcs = module.import_count(ImportCountType::Function) as u32;
+        let max_depth = cmp::min(self.max_call_depth, i32::MAX as u32) as i32;
+
+        let Some(code) = module.code_section_mut() else {
+            return Ok(());
+        };
+        for body in code.bodies_mut() {
+            let instrs = body.code_mut().elements_mut();
+            let mut out = Vec::with_capacity(instrs.len());
+            for instr in instrs.drain(..) {
+                let counted = match instr {
+                    Instruction::Call(idx) => idx >= imported_funcs,
+                    Instruction::CallIndirect(..) => true,
+                    _ => false,
+                };
+                if !counted {
+                    out.push(instr);
+                    continue;
+                }
+
+                // the call depth has already been incremented for this call.
+                out.extend([
+                    Instruction::GetGlobal(depth),
+                    Instruction::I32Const(max_depth),
+                    Instruction::I32GtU,
+                    Instruction::If(BlockType::NoResult),
+                    Instruction::Call(exceeded),
+                    Instruction::End,
+                    instr,
+                ]);
+            }
+            *instrs = out;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for WasmLimits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+// === impl LimitExceeded ===
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fuel => f.write_str("fuel limit exceeded"),
+            Self::CallDepth => f.write_str("call depth limit exceeded"),
+        }
+    }
+}
+
+impl wasmi::HostError for LimitExceeded {}
@@ -0,0 +1,82 @@
+//! A registry of named WASM instances, used to link modules together.
+use alloc::{collections::BTreeMap, format, string::String};
+
+/// A registry of instantiated WASM modules, by name.
+///
+/// When a module is instantiated by a [`Host`](super::Host), its imports from
+/// a module name that is registered here are resolved to the exports of the
+/// registered instance. This allows kernel services to be split into WASM
+/// libraries which are instantiated once and imported by other modules.
+#[derive(Debug, Default)]
+pub struct Linker {
+    modules: BTreeMap<String, wasmi::ModuleRef>,
+}
+
+impl Linker {
+    pub fn new() -> Self {
+        Self {
+            modules: BTreeMap::new(),
+        }
+    }
+
+    /// Registers `instance` under `name`, so that modules instantiated later
+    /// can import its exports.
+    ///
+    /// Registered modules take precedence over host modules with the same
+    /// name, so a WASM library may be used to provide its own implementation
+    /// of a host module (such as WASI) to other modules.
+    ///
+    /// # Errors
+    ///
+    /// If a module is already registered under `name`.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        instance: wasmi::ModuleRef,
+    ) -> Result<(), wasmi::Error> {
+        use alloc::collections::btree_map::Entry;
+        match self.modules.entry(name.into()) {
+            Entry::Occupied(entry) => Err(wasmi::Error::Instantiation(format!(
+                "a module named {:?} is already registered",
+                entry.key()
+            ))),
+            Entry::Vacant(entry) => {
+                tracing::debug!(name = %entry.key(), "registered WASM module");
+                entry.insert(instance);
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the instance registered under `name`, if there is one.
+    pub fn get(&self, name: &str) -> Option<&wasmi::ModuleRef> {
+        self.modules.get(name)
+    }
+
+    /// Returns an iterator over the names of all registered modules.
+    pub fn names(&self) -> impl Iterator<Item = &str> + '_ {
+        self.modules.keys().map(String::as_str)
+    }
+
+    /// Resolves an import of `field_name` from the registered module
+    /// `module_name`.
+    ///
+    /// Returns `None` if no module named `module_name` is registered, so that
+    /// the import may be resolved elsewhere. If the module is registered, but
+    /// it has no export named `field_name` of the expected kind, an error is
+    /// returned.
+    pub(super) fn resolve<T>(
+        &self,
+        module_name: &str,
+        field_name: &str,
+        kind: &str,
+        extract: impl FnOnce(wasmi::ExternVal) -> Option<T>,
+    ) -> Option<Result<T, wasmi::Error>> {
+        let module = self.modules.get(module_name)?;
+        let resolved = module.export_by_name(field_name).and_then(extract);
+        Some(resolved.ok_or_else(|| {
+            tracing::error!(module_name, field_name, kind, "unresolved import");
+            wasmi::Error::Instantiation(format!("unresolved {kind} import"))
+        }))
+    }
+}
@@ -0,0 +1,72 @@
+//! Compiled WASM modules.
+use super::{instrument, symbols::Symbols, WasmLimits};
+use alloc::sync::Arc;
+use core::fmt;
+
+/// A WASM module that has been validated and instrumented, and may be
+/// instantiated any number of times.
+///
+/// Cloning a `CompiledModule` is cheap, as it's reference-counted. Modules
+/// compiled with [`cache::compile`](super::cache::compile) are shared
+/// kernel-wide.
+#[derive(Clone)]
+pub struct CompiledModule(Arc<Inner>);
+
+struct Inner {
+    module: wasmi::Module,
+    /// Debug information for symbolizing the module's functions.
+    symbols: Symbols,
+    /// The limits that were applied when the module was instrumented.
+    limits: WasmLimits,
+    /// The approximate amount of memory used by the module, in bytes.
+    size: usize,
+}
+
+impl CompiledModule {
+    /// Parses, validates, and instruments `binary`, applying `limits` to it.
+    ///
+    /// The returned module may only be instantiated by a [`Host`](super::Host)
+    /// with the same limits.
+    pub fn new(binary: &[u8], limits: WasmLimits) -> Result<Self, wasmi::Error> {
+        let (symbols, instrumented) = instrument::load(binary, &limits)?;
+        let module = wasmi::Module::from_buffer(&instrumented)?;
+        // the interpreter's representation of the module is roughly
+        // proportional to the size of the instrumented binary.
+        let size = instrumented.len() + symbols.size();
+        Ok(Self(Arc::new(Inner {
+            module,
+            symbols,
+            limits,
+            size,
+        })))
+    }
+
+    /// Returns the limits that were applied to this module.
+    pub fn limits(&self) -> &WasmLimits {
+        &self.0.limits
+    }
+
+    /// Returns the approximate amount of memory used by this module, in
+    /// bytes.
+    pub fn size(&self) -> usize {
+        self.0.size
+    }
+
+    pub(super) fn module(&self) -> &wasmi::Module {
+        &self.0.module
+    }
+
+    pub(super) fn symbols(&self) -> &Symbols {
+        &self.0.symbols
+    }
+}
+
+impl fmt::Debug for CompiledModule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompiledModule")
+            .field("symbols", &self.0.symbols)
+            .field("limits", &self.0.limits)
+            .field("size", &self.0.size)
+            .finish_non_exhaustive()
+    }
+}
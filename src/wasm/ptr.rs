@@ -0,0 +1,277 @@
+//! Typed pointers into a guest's linear memory.
+//!
+//! Host functions receive pointers into the calling instance's memory as
+//! plain `i32`s. [`GuestPtr`] and [`GuestSlice`] give those pointers a type,
+//! so that they can be passed directly to host functions by `host_funcs!`,
+//! and check every access against the bounds of the memory they're used
+//! with, trapping with [`MemoryAccessOutOfBounds`] if it's out of bounds.
+//!
+//! [`MemoryAccessOutOfBounds`]: wasmi::TrapKind::MemoryAccessOutOfBounds
+use super::convert::{ConvertError, WasmPrimitive, WasmValues};
+use core::{fmt, marker::PhantomData};
+
+/// A pointer to a `T` in a guest's linear memory.
+pub struct GuestPtr<T> {
+    addr: u32,
+    _ty: PhantomData<fn() -> T>,
+}
+
+/// A pointer to `len` contiguous `T`s in a guest's linear memory.
+///
+/// When passed to a host function, a `GuestSlice` is two WASM values: the
+/// pointer, followed by the length. In memory, it's laid out like WASI's
+/// `iovec` and `ciovec`, so a `GuestSlice<GuestSlice<u8>>` is an array of
+/// `iovec`s.
+pub struct GuestSlice<T> {
+    ptr: GuestPtr<T>,
+    len: u32,
+}
+
+/// A type that can be stored in a guest's linear memory.
+pub trait GuestType: Sized {
+    /// The size of the type in guest memory, in bytes.
+    const SIZE: u32;
+
+    /// Reads a value from the start of `bytes`, which is exactly
+    /// [`Self::SIZE`] bytes long.
+    fn read_from(bytes: &[u8]) -> Self;
+
+    /// Writes the value to the start of `bytes`, which is exactly
+    /// [`Self::SIZE`] bytes long.
+    fn write_to(self, bytes: &mut [u8]);
+}
+
+fn out_of_bounds() -> wasmi::Trap {
+    wasmi::TrapKind::MemoryAccessOutOfBounds.into()
+}
+
+/// Returns `len` bytes of `mem` starting at `addr`.
+fn bytes(mem: &[u8], addr: u32, len: u32) -> Result<&[u8], wasmi::Trap> {
+    let end = addr.checked_add(len).ok_or_else(out_of_bounds)?;
+    mem.get(addr as usize..end as usize)
+        .ok_or_else(out_of_bounds)
+}
+
+/// Returns `len` bytes of `mem` starting at `addr`, mutably.
+fn bytes_mut(mem: &mut [u8], addr: u32, len: u32) -> Result<&mut [u8], wasmi::Trap> {
+    let end = addr.checked_add(len).ok_or_else(out_of_bounds)?;
+    mem.get_mut(addr as usize..end as usize)
+        .ok_or_else(out_of_bounds)
+}
+
+// === impl GuestPtr ===
+
+impl<T> GuestPtr<T> {
+    pub const fn new(addr: u32) -> Self {
+        Self {
+            addr,
+            _ty: PhantomData,
+        }
+    }
+
+    /// Returns the address this pointer points to.
+    pub const fn addr(self) -> u32 {
+        self.addr
+    }
+
+    /// Returns a pointer to the same address, as a different type.
+    pub const fn cast<U>(self) -> GuestPtr<U> {
+        GuestPtr::new(self.addr)
+    }
+
+    /// Returns a pointer to the `U` at `offset` bytes from this pointer, such
+    /// as a field of a struct.
+    pub fn field<U>(self, offset: u32) -> Result<GuestPtr<U>, wasmi::Trap> {
+        let addr = self.addr.checked_add(offset).ok_or_else(out_of_bounds)?;
+        Ok(GuestPtr::new(addr))
+    }
+}
+
+impl<T: GuestType> GuestPtr<T> {
+    /// Returns a pointer to the `count`th `T` after this one.
+    pub fn offset(self, count: u32) -> Result<Self, wasmi::Trap> {
+        let offset = count.checked_mul(T::SIZE).ok_or_else(out_of_bounds)?;
+        self.field(offset)
+    }
+
+    /// Reads the value this pointer points to from `mem`.
+    pub fn read(self, mem: &[u8]) -> Result<T, wasmi::Trap> {
+        bytes(mem, self.addr, T::SIZE).map(T::read_from)
+    }
+
+    /// Writes `value` to the location in `mem` this pointer points to.
+    pub fn write(self, mem: &mut [u8], value: T) -> Result<(), wasmi::Trap> {
+        value.write_to(bytes_mut(mem, self.addr, T::SIZE)?);
+        Ok(())
+    }
+}
+
+impl<T> Copy for GuestPtr<T> {}
+
+impl<T> Clone for GuestPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> PartialEq for GuestPtr<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.addr == other.addr
+    }
+}
+
+impl<T> Eq for GuestPtr<T> {}
+
+impl<T> fmt::Debug for GuestPtr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#x}", self.addr)
+    }
+}
+
+impl<T> WasmPrimitive for GuestPtr<T> {
+    const TYPE: wasmi::ValueType = wasmi::ValueType::I32;
+
+    fn from_wasm_value(value: wasmi::RuntimeValue) -> Result<Self, ConvertError> {
+        u32::from_wasm_value(value).map(Self::new)
+    }
+
+    fn into_wasm_value(self) -> wasmi::RuntimeValue {
+        self.addr.into_wasm_value()
+    }
+}
+
+impl<T> GuestType for GuestPtr<T> {
+    const SIZE: u32 = u32::SIZE;
+
+    fn read_from(bytes: &[u8]) -> Self {
+        Self::new(u32::read_from(bytes))
+    }
+
+    fn write_to(self, bytes: &mut [u8]) {
+        self.addr.write_to(bytes)
+    }
+}
+
+// === impl GuestSlice ===
+
+impl<T> GuestSlice<T> {
+    pub const fn new(ptr: GuestPtr<T>, len: u32) -> Self {
+        Self { ptr, len }
+    }
+
+    /// Returns a pointer to the first element of the slice.
+    pub const fn ptr(self) -> GuestPtr<T> {
+        self.ptr
+    }
+
+    /// Returns the number of elements in the slice.
+    pub const fn len(self) -> u32 {
+        self.len
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<T: GuestType> GuestSlice<T> {
+    /// Returns a pointer to the `idx`th element of the slice, or `None` if
+    /// `idx` is out of bounds.
+    pub fn get(self, idx: u32) -> Option<GuestPtr<T>> {
+        if idx >= self.len {
+            return None;
+        }
+        self.ptr.offset(idx).ok()
+    }
+
+    /// Returns an iterator over pointers to the slice's elements.
+    pub fn iter(self) -> impl Iterator<Item = GuestPtr<T>> {
+        (0..self.len).map_while(move |idx| self.get(idx))
+    }
+
+    /// Returns the first `len` elements of the slice, or the whole slice if
+    /// it's shorter than that.
+    #[must_use]
+    pub fn truncate(self, len: u32) -> Self {
+        Self {
+            len: self.len.min(len),
+            ..self
+        }
+    }
+}
+
+impl GuestSlice<u8> {
+    /// Returns the bytes of `mem` that this slice points to.
+    pub fn as_bytes(self, mem: &[u8]) -> Result<&[u8], wasmi::Trap> {
+        bytes(mem, self.ptr.addr, self.len)
+    }
+
+    /// Returns the bytes of `mem` that this slice points to, mutably.
+    pub fn as_bytes_mut(self, mem: &mut [u8]) -> Result<&mut [u8], wasmi::Trap> {
+        bytes_mut(mem, self.ptr.addr, self.len)
+    }
+}
+
+impl<T> Copy for GuestSlice<T> {}
+
+impl<T> Clone for GuestSlice<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> fmt::Debug for GuestSlice<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}[..{}]", self.ptr, self.len)
+    }
+}
+
+impl<T> WasmValues for GuestSlice<T> {
+    const TYPES: &'static [wasmi::ValueType] = &[wasmi::ValueType::I32, wasmi::ValueType::I32];
+
+    fn from_wasm_values(values: &[wasmi::RuntimeValue]) -> Result<Self, ConvertError> {
+        match *values {
+            [ptr, len] => Ok(Self::new(
+                GuestPtr::from_wasm_value(ptr)?,
+                u32::from_wasm_value(len)?,
+            )),
+            _ => Err(ConvertError),
+        }
+    }
+}
+
+impl<T> GuestType for GuestSlice<T> {
+    const SIZE: u32 = 2 * u32::SIZE;
+
+    fn read_from(bytes: &[u8]) -> Self {
+        let (ptr, len) = bytes.split_at(u32::SIZE as usize);
+        Self::new(GuestPtr::read_from(ptr), u32::read_from(len))
+    }
+
+    fn write_to(self, bytes: &mut [u8]) {
+        let (ptr, len) = bytes.split_at_mut(u32::SIZE as usize);
+        self.ptr.write_to(ptr);
+        self.len.write_to(len);
+    }
+}
+
+macro_rules! impl_guest_type {
+    ($($ty:ty),*) => {
+        $(
+            impl GuestType for $ty {
+                const SIZE: u32 = core::mem::size_of::<$ty>() as u32;
+
+                fn read_from(bytes: &[u8]) -> Self {
+                    let bytes = bytes.try_into().expect("slice must be exactly `SIZE` bytes");
+                    <$ty>::from_le_bytes(bytes)
+                }
+
+                fn write_to(self, bytes: &mut [u8]) {
+                    bytes.copy_from_slice(&self.to_le_bytes())
+                }
+            }
+        )*
+    }
+}
+
+impl_guest_type!(u8, i8, u16, i16, u32, i32, u64, i64, f32, f64);
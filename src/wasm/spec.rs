@@ -0,0 +1,413 @@
+//! Runs WebAssembly spec test scripts in the kernel.
+//!
+//! The scripts in `src/wasm/spec` are curated subsets of the WebAssembly spec
+//! test suite's scripts, not copies of them. `build.rs` turns every `.wast`
+//! script into a `mycotest` test, which runs the script's commands against a
+//! [`Host`] using a [`Script`]. This exercises the interpreter, the kernel's
+//! module instrumentation, and the [`Host`]'s import resolution on the real
+//! kernel target, rather than only in host-side unit tests.
+//!
+//! Failed assertions are reported at their location in the script, rather
+//! than in the generated test. Commands that are skipped are logged with the
+//! reason they were skipped, so that the test output lists them.
+use super::{trace, Host, LimitExceeded};
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+};
+use mycotest::{assert::Failed, TestResult};
+use wasmi::{RuntimeValue, TrapKind};
+
+/// The state of a spec script that's being run.
+struct Script {
+    path: &'static str,
+    host: Host,
+    /// The most recently instantiated module, which commands that don't name
+    /// a module refer to.
+    current: Option<wasmi::ModuleRef>,
+    /// Modules that were given a `$name` in the script.
+    named: BTreeMap<String, wasmi::ModuleRef>,
+}
+
+/// An expected result of an `assert_return`.
+enum Expect {
+    Value(RuntimeValue),
+    /// Any NaN with the canonical payload, of either float type.
+    CanonicalNan,
+    /// Any NaN with the most significant bit of the payload set, of either
+    /// float type.
+    ArithmeticNan,
+}
+
+impl Script {
+    fn new(path: &'static str) -> Self {
+        Self {
+            path,
+            host: Host::new(),
+            current: None,
+            named: BTreeMap::new(),
+        }
+    }
+
+    /// `(module ...)`: instantiates a module, which later commands refer to.
+    fn module(&mut self, line: u32, name: Option<&str>, binary: &[u8]) -> TestResult {
+        let instance = self
+            .host
+            .load(binary)
+            .and_then(|module| self.host.instantiate(&module))
+            .map_err(|error| {
+                tracing::error!(%error, "instantiating module failed");
+                self.failed(line, "module")
+            })?;
+        if let Some(name) = name {
+            self.named.insert(name.into(), instance.clone());
+        }
+        self.current = Some(instance);
+        Ok(())
+    }
+
+    /// `(register "name" $module)`: registers a module with the host's
+    /// linker, so that later modules can import its exports.
+    fn register(&mut self, line: u32, as_name: &str, module: Option<&str>) -> TestResult {
+        let instance = self.instance(line, module)?.clone();
+        self.host
+            .linker_mut()
+            .register(as_name, instance)
+            .map_err(|error| {
+                tracing::error!(%error, "registering module failed");
+                self.failed(line, "register")
+            })
+    }
+
+    /// `(invoke ...)`: calls an export, which must not trap.
+    fn invoke(
+        &mut self,
+        line: u32,
+        module: Option<&str>,
+        func: &str,
+        args: &[RuntimeValue],
+    ) -> TestResult {
+        self.call(line, module, func, args).map_err(|error| {
+            tracing::error!(%error, func, "invocation failed");
+            self.failed(line, "invoke")
+        })?;
+        Ok(())
+    }
+
+    /// `(assert_return (invoke ...) ...)`: calls an export, which must return
+    /// `expected`.
+    fn assert_return(
+        &mut self,
+        line: u32,
+        module: Option<&str>,
+        func: &str,
+        args: &[RuntimeValue],
+        expected: &[Expect],
+    ) -> TestResult {
+        let result = self.call(line, module, func, args).map_err(|error| {
+            tracing::error!(%error, func, ?args, "invocation failed");
+            self.failed(line, "assert_return")
+        })?;
+        self.check_results(line, result, expected)
+    }
+
+    /// `(assert_return (get ...) ...)`: reads an exported global, which must
+    /// have the value `expected`.
+    fn assert_global(
+        &mut self,
+        line: u32,
+        module: Option<&str>,
+        global: &str,
+        expected: &[Expect],
+    ) -> TestResult {
+        let value = self
+            .instance(line, module)?
+            .export_by_name(global)
+            .and_then(|export| export.as_global().map(|global| global.get()));
+        if value.is_none() {
+            tracing::error!(global, "no such global export");
+            return Err(self.failed(line, "assert_return"));
+        }
+        self.check_results(line, value, expected)
+    }
+
+    /// `(assert_trap (invoke ...) "message")` and `(assert_exhaustion ...)`:
+    /// calls an export, which must trap.
+    fn assert_trap(
+        &mut self,
+        line: u32,
+        module: Option<&str>,
+        func: &str,
+        args: &[RuntimeValue],
+        message: &str,
+    ) -> TestResult {
+        let result = self.call(line, module, func, args);
+        self.check_trap(line, result.map(|_| ()), message)
+    }
+
+    /// `(assert_trap (module ...) "message")`: instantiates a module whose
+    /// start function must trap.
+    fn assert_start_trap(&mut self, line: u32, binary: &[u8], message: &str) -> TestResult {
+        let result = self
+            .host
+            .load(binary)
+            .and_then(|module| self.host.instantiate(&module));
+        self.check_trap(line, result.map(|_| ()), message)
+    }
+
+    /// `(assert_invalid ...)` and `(assert_malformed ...)`: the module must
+    /// fail to load.
+    fn assert_invalid(&mut self, line: u32, binary: &[u8], message: &str) -> TestResult {
+        match self.host.load(binary) {
+            Ok(_) => {
+                tracing::error!(expected = message, "module loaded successfully");
+                Err(self.failed(line, "assert_invalid"))
+            }
+            Err(error) if error_matches(&error, message) => {
+                tracing::trace!(%error, expected = message, "module is invalid");
+                Ok(())
+            }
+            Err(error) => {
+                tracing::error!(%error, expected = message, "unexpected error");
+                Err(self.failed(line, "assert_invalid"))
+            }
+        }
+    }
+
+    /// `(assert_unlinkable ...)`: the module must load, but fail to
+    /// instantiate.
+    fn assert_unlinkable(&mut self, line: u32, binary: &[u8], message: &str) -> TestResult {
+        let module = self.host.load(binary).map_err(|error| {
+            tracing::error!(%error, "loading module failed");
+            self.failed(line, "assert_unlinkable")
+        })?;
+        match self.host.instantiate(&module) {
+            Ok(_) => {
+                tracing::error!(expected = message, "module linked successfully");
+                Err(self.failed(line, "assert_unlinkable"))
+            }
+            Err(error) if error_matches(&error, message) => {
+                tracing::trace!(%error, expected = message, "module is unlinkable");
+                Ok(())
+            }
+            Err(error) => {
+                tracing::error!(%error, expected = message, "unexpected error");
+                Err(self.failed(line, "assert_unlinkable"))
+            }
+        }
+    }
+
+    /// A command that can't be run by the kernel.
+    // only called by the generated tests for scripts with skipped commands.
+    #[allow(dead_code)]
+    fn skip(&self, line: u32, reason: &str) {
+        tracing::info!("skipped {}:{line}: {reason}", self.path);
+    }
+
+    fn call(
+        &mut self,
+        line: u32,
+        module: Option<&str>,
+        func: &str,
+        args: &[RuntimeValue],
+    ) -> Result<Option<RuntimeValue>, wasmi::Error> {
+        let instance = self
+            .instance(line, module)
+            .map_err(|_| wasmi::Error::Function("no module to invoke".into()))?
+            .clone();
+        let result = instance.invoke_export(func, args, &mut self.host);
+        if let Err(wasmi::Error::Trap(_)) = result {
+            // later commands may call the instance again.
+            trace::reset_call_depth(&instance)?;
+        }
+        result
+    }
+
+    fn instance(&self, line: u32, module: Option<&str>) -> Result<&wasmi::ModuleRef, Failed> {
+        let instance = match module {
+            Some(name) => self.named.get(name),
+            None => self.current.as_ref(),
+        };
+        instance.ok_or_else(|| {
+            tracing::error!(?module, "no such module");
+            self.failed(line, "module")
+        })
+    }
+
+    fn check_results(
+        &self,
+        line: u32,
+        actual: Option<RuntimeValue>,
+        expected: &[Expect],
+    ) -> TestResult {
+        let matches = match (actual, expected) {
+            (None, []) => true,
+            (Some(actual), [expected]) => expected.matches(actual),
+            _ => false,
+        };
+        if !matches {
+            tracing::error!(?actual, ?expected, "unexpected result");
+            return Err(self.failed(line, "assert_return"));
+        }
+        Ok(())
+    }
+
+    fn check_trap(&self, line: u32, result: Result<(), wasmi::Error>, message: &str) -> TestResult {
+        let error = match result {
+            Ok(()) => {
+                tracing::error!(expected = message, "did not trap");
+                return Err(self.failed(line, "assert_trap"));
+            }
+            Err(error) => error,
+        };
+        match error {
+            wasmi::Error::Trap(ref trap) if trap_matches(trap.kind(), message) => Ok(()),
+            error => {
+                tracing::error!(%error, expected = message, "unexpected error");
+                Err(self.failed(line, "assert_trap"))
+            }
+        }
+    }
+
+    fn failed(&self, line: u32, command: &'static str) -> Failed {
+        tracing::error!("{command} failed at {}:{line}", self.path);
+        Failed {
+            expr: command,
+            file: self.path,
+            line,
+            col: 0,
+        }
+    }
+}
+
+/// Returns `true` if a trap of `kind` is the trap described by `message` in
+/// a spec script.
+fn trap_matches(kind: &TrapKind, message: &str) -> bool {
+    match kind {
+        TrapKind::Unreachable => message.starts_with("unreachable"),
+        TrapKind::MemoryAccessOutOfBounds => message.starts_with("out of bounds memory access"),
+        TrapKind::TableAccessOutOfBounds => {
+            message.starts_with("undefined element") || message.starts_with("out of bounds table")
+        }
+        TrapKind::ElemUninitialized => message.starts_with("uninitialized element"),
+        TrapKind::DivisionByZero => message.starts_with("integer divide by zero"),
+        // the interpreter reports signed division overflow as an invalid
+        // conversion, too.
+        TrapKind::InvalidConversionToInt => {
+            message.starts_with("integer overflow")
+                || message.starts_with("invalid conversion to integer")
+        }
+        TrapKind::StackOverflow => message.starts_with("call stack exhausted"),
+        TrapKind::UnexpectedSignature => message.starts_with("indirect call type mismatch"),
+        // the kernel's call depth limit is usually hit before the
+        // interpreter's own stack limit.
+        TrapKind::Host(error) => {
+            matches!(
+                error.downcast_ref::<LimitExceeded>(),
+                Some(LimitExceeded::CallDepth)
+            ) && message.starts_with("call stack exhausted")
+        }
+    }
+}
+
+/// Returns `true` if `error`, returned when loading or instantiating a
+/// module, is the error described by `message` in a spec script.
+///
+/// The interpreter's error messages are worded differently from the spec's,
+/// so each message is mapped to the parts of the errors that mean the same
+/// thing. Messages that aren't listed here never match, so a new kind of
+/// `assert_invalid` or `assert_unlinkable` fails until it's added.
+fn error_matches(error: &wasmi::Error, message: &str) -> bool {
+    const ERRORS: &[(&str, &[&str])] = &[
+        (
+            "type mismatch",
+            &[
+                "Expected value of type",
+                "Unexpected stack height",
+                "non-empty stack expected",
+                "Trying to access parent frame stack values",
+                "Trying to update local",
+                "Trying to update global",
+                "Labels in br_table points to block of different types",
+                "If block without else",
+            ],
+        ),
+        ("unknown function", &["Function at index"]),
+        ("unknown table", &["Table at index"]),
+        ("unknown memory", &["Memory at index"]),
+        ("unknown global", &["Global at index"]),
+        ("unknown type", &["Type at index"]),
+        ("unknown local", &["Trying to access local"]),
+        (
+            "alignment must not be larger than natural",
+            &["Too large memory alignment"],
+        ),
+        (
+            "size minimum must not be greater than maximum",
+            &["is less than minimum"],
+        ),
+        ("magic header not detected", &["Invalid magic number"]),
+        ("unknown binary version", &["Unsupported wasm version"]),
+        // import resolution fails in `HostResolver`, or in the `Linker`.
+        ("unknown import", &["unresolved"]),
+        (
+            "incompatible import type",
+            &[
+                "Expected function with type",
+                "Expect global with",
+                "trying to import with limits",
+                "extern_val",
+            ],
+        ),
+    ];
+
+    let error = error.to_string();
+    ERRORS
+        .iter()
+        .filter(|(expected, _)| message.starts_with(expected))
+        .flat_map(|(_, errors)| errors.iter())
+        .any(|part| error.contains(part))
+}
+
+// === impl Expect ===
+
+impl Expect {
+    fn matches(&self, actual: RuntimeValue) -> bool {
+        match (self, actual) {
+            // floats are compared bitwise, so that NaN payloads and the signs
+            // of zeroes are checked.
+            (Self::Value(RuntimeValue::F32(expected)), RuntimeValue::F32(actual)) => {
+                expected.to_bits() == actual.to_bits()
+            }
+            (Self::Value(RuntimeValue::F64(expected)), RuntimeValue::F64(actual)) => {
+                expected.to_bits() == actual.to_bits()
+            }
+            (Self::Value(expected), actual) => *expected == actual,
+            (Self::CanonicalNan, RuntimeValue::F32(actual)) => {
+                actual.to_bits() & 0x7fff_ffff == 0x7fc0_0000
+            }
+            (Self::CanonicalNan, RuntimeValue::F64(actual)) => {
+                actual.to_bits() & 0x7fff_ffff_ffff_ffff == 0x7ff8_0000_0000_0000
+            }
+            (Self::ArithmeticNan, RuntimeValue::F32(actual)) => {
+                actual.to_bits() & 0x7fc0_0000 == 0x7fc0_0000
+            }
+            (Self::ArithmeticNan, RuntimeValue::F64(actual)) => {
+                actual.to_bits() & 0x7ff8_0000_0000_0000 == 0x7ff8_0000_0000_0000
+            }
+            _ => false,
+        }
+    }
+}
+
+impl core::fmt::Debug for Expect {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Value(value) => value.fmt(f),
+            Self::CanonicalNan => f.write_str("nan:canonical"),
+            Self::ArithmeticNan => f.write_str("nan:arithmetic"),
+        }
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/wasm_spec_tests.rs"));
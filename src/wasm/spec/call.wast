@@ -0,0 +1,89 @@
+;; Direct and indirect calls: a curated subset of the WebAssembly spec test
+;; suite's `call.wast`, `call_indirect.wast`, and `start.wast`.
+
+(module
+  (type $proc (func))
+  (type $out-i32 (func (result i32)))
+  (type $over-i64 (func (param i64) (result i64)))
+
+  (table funcref (elem $const-i32 $id-i64 $fac))
+
+  (func $const-i32 (type $out-i32) (i32.const 0x132))
+  (func $id-i64 (type $over-i64) (local.get 0))
+  (func $fac (export "fac") (type $over-i64)
+    (if (result i64) (i64.eqz (local.get 0))
+      (then (i64.const 1))
+      (else (i64.mul (local.get 0) (call $fac (i64.sub (local.get 0) (i64.const 1)))))
+    )
+  )
+  (func $even (export "even") (param i64) (result i32)
+    (if (result i32) (i64.eqz (local.get 0))
+      (then (i32.const 44))
+      (else (call $odd (i64.sub (local.get 0) (i64.const 1))))
+    )
+  )
+  (func $odd (export "odd") (param i64) (result i32)
+    (if (result i32) (i64.eqz (local.get 0))
+      (then (i32.const 99))
+      (else (call $even (i64.sub (local.get 0) (i64.const 1))))
+    )
+  )
+  (func $runaway (export "runaway") (call $runaway))
+  (func $mutual-runaway1 (export "mutual-runaway") (call $mutual-runaway2))
+  (func $mutual-runaway2 (call $mutual-runaway1))
+
+  (func (export "dispatch") (param i32 i64) (result i64)
+    (call_indirect (type $over-i64) (local.get 1) (local.get 0))
+  )
+  (func (export "dispatch-i32") (param i32) (result i32)
+    (call_indirect (type $out-i32) (local.get 0))
+  )
+  (func (export "unreachable") (unreachable))
+)
+
+(assert_return (invoke "fac" (i64.const 0)) (i64.const 1))
+(assert_return (invoke "fac" (i64.const 5)) (i64.const 120))
+(assert_return (invoke "fac" (i64.const 25)) (i64.const 7034535277573963776))
+(assert_return (invoke "even" (i64.const 77)) (i32.const 99))
+(assert_return (invoke "odd" (i64.const 200)) (i32.const 99))
+
+(assert_exhaustion (invoke "runaway") "call stack exhausted")
+(assert_exhaustion (invoke "mutual-runaway") "call stack exhausted")
+
+(assert_return (invoke "dispatch" (i32.const 1) (i64.const 2)) (i64.const 2))
+(assert_return (invoke "dispatch" (i32.const 2) (i64.const 5)) (i64.const 120))
+(assert_return (invoke "dispatch-i32" (i32.const 0)) (i32.const 0x132))
+(assert_trap (invoke "dispatch" (i32.const 0) (i64.const 2)) "indirect call type mismatch")
+(assert_trap (invoke "dispatch" (i32.const 3) (i64.const 2)) "undefined element")
+(assert_trap (invoke "dispatch" (i32.const -1) (i64.const 2)) "undefined element")
+
+(assert_trap (invoke "unreachable") "unreachable")
+
+;; a trap in the start function fails instantiation.
+(assert_trap
+  (module (func $main (unreachable)) (start $main))
+  "unreachable"
+)
+
+(module
+  (memory (data "A"))
+  (func $inc
+    (i32.store8 (i32.const 0) (i32.add (i32.load8_u (i32.const 0)) (i32.const 1)))
+  )
+  (func (export "get") (result i32) (i32.load8_u (i32.const 0)))
+  (start $inc)
+)
+(assert_return (invoke "get") (i32.const 66))
+
+(assert_invalid
+  (module (func $type-void-vs-num (i32.eqz (call 1))) (func))
+  "type mismatch"
+)
+(assert_invalid
+  (module (func $unbound-func (call 1)))
+  "unknown function"
+)
+(assert_invalid
+  (module (func (result i32) (call_indirect (i32.const 0))))
+  "unknown table"
+)
@@ -0,0 +1,61 @@
+;; f32 and f64 operations and conversions: a curated subset of the
+;; WebAssembly spec test suite's `f32.wast`, `f64.wast`, and `conversions.wast`.
+
+(module
+  (func (export "f32.add") (param $x f32) (param $y f32) (result f32) (f32.add (local.get $x) (local.get $y)))
+  (func (export "f32.div") (param $x f32) (param $y f32) (result f32) (f32.div (local.get $x) (local.get $y)))
+  (func (export "f32.sqrt") (param $x f32) (result f32) (f32.sqrt (local.get $x)))
+  (func (export "f32.neg") (param $x f32) (result f32) (f32.neg (local.get $x)))
+  (func (export "f32.min") (param $x f32) (param $y f32) (result f32) (f32.min (local.get $x) (local.get $y)))
+  (func (export "f32.nearest") (param $x f32) (result f32) (f32.nearest (local.get $x)))
+  (func (export "f64.add") (param $x f64) (param $y f64) (result f64) (f64.add (local.get $x) (local.get $y)))
+  (func (export "f64.mul") (param $x f64) (param $y f64) (result f64) (f64.mul (local.get $x) (local.get $y)))
+  (func (export "f64.copysign") (param $x f64) (param $y f64) (result f64) (f64.copysign (local.get $x) (local.get $y)))
+  (func (export "f64.eq") (param $x f64) (param $y f64) (result i32) (f64.eq (local.get $x) (local.get $y)))
+  (func (export "i32.trunc_f32_s") (param $x f32) (result i32) (i32.trunc_f32_s (local.get $x)))
+  (func (export "i32.trunc_f64_u") (param $x f64) (result i32) (i32.trunc_f64_u (local.get $x)))
+  (func (export "i64.trunc_f64_s") (param $x f64) (result i64) (i64.trunc_f64_s (local.get $x)))
+  (func (export "f32.convert_i32_u") (param $x i32) (result f32) (f32.convert_i32_u (local.get $x)))
+  (func (export "f64.promote_f32") (param $x f32) (result f64) (f64.promote_f32 (local.get $x)))
+  (func (export "i32.reinterpret_f32") (param $x f32) (result i32) (i32.reinterpret_f32 (local.get $x)))
+  (func (export "f64.reinterpret_i64") (param $x i64) (result f64) (f64.reinterpret_i64 (local.get $x)))
+)
+
+(assert_return (invoke "f32.add" (f32.const 0x1p+0) (f32.const 0x1p+0)) (f32.const 0x1p+1))
+(assert_return (invoke "f32.add" (f32.const -0x0p+0) (f32.const -0x0p+0)) (f32.const -0x0p+0))
+(assert_return (invoke "f32.add" (f32.const inf) (f32.const -inf)) (f32.const nan:canonical))
+(assert_return (invoke "f32.add" (f32.const nan) (f32.const 0x1p+0)) (f32.const nan:canonical))
+(assert_return (invoke "f32.div" (f32.const 0x1p+0) (f32.const 0x0p+0)) (f32.const inf))
+(assert_return (invoke "f32.div" (f32.const 0x0p+0) (f32.const 0x0p+0)) (f32.const nan:canonical))
+(assert_return (invoke "f32.sqrt" (f32.const 0x1p+2)) (f32.const 0x1p+1))
+(assert_return (invoke "f32.sqrt" (f32.const -0x1p+0)) (f32.const nan:canonical))
+(assert_return (invoke "f32.neg" (f32.const 0x0p+0)) (f32.const -0x0p+0))
+(assert_return (invoke "f32.neg" (f32.const nan:0x200000)) (f32.const -nan:0x200000))
+(assert_return (invoke "f32.min" (f32.const -0x0p+0) (f32.const 0x0p+0)) (f32.const -0x0p+0))
+(assert_return (invoke "f32.nearest" (f32.const 0x1.8p+0)) (f32.const 0x1p+1))
+(assert_return (invoke "f32.nearest" (f32.const 0x1.4p+1)) (f32.const 0x1p+1))
+
+(assert_return (invoke "f64.add" (f64.const 0x1p-1074) (f64.const 0x1p-1074)) (f64.const 0x1p-1073))
+(assert_return (invoke "f64.add" (f64.const inf) (f64.const 0x1p+0)) (f64.const inf))
+(assert_return (invoke "f64.mul" (f64.const inf) (f64.const 0x0p+0)) (f64.const nan:canonical))
+(assert_return (invoke "f64.mul" (f64.const nan:0x4000000000000) (f64.const 0x1p+0)) (f64.const nan:arithmetic))
+(assert_return (invoke "f64.copysign" (f64.const 0x1p+0) (f64.const -0x0p+0)) (f64.const -0x1p+0))
+(assert_return (invoke "f64.eq" (f64.const nan) (f64.const nan)) (i32.const 0))
+(assert_return (invoke "f64.eq" (f64.const 0x0p+0) (f64.const -0x0p+0)) (i32.const 1))
+
+(assert_return (invoke "i32.trunc_f32_s" (f32.const -0x1.19999ap+0)) (i32.const -1))
+(assert_return (invoke "i32.trunc_f32_s" (f32.const 0x1.fffffep+30)) (i32.const 2147483520))
+(assert_trap (invoke "i32.trunc_f32_s" (f32.const 0x1p+31)) "integer overflow")
+(assert_trap (invoke "i32.trunc_f32_s" (f32.const nan)) "invalid conversion to integer")
+(assert_return (invoke "i32.trunc_f64_u" (f64.const 0x1.fffffffep+31)) (i32.const -1))
+(assert_return (invoke "i32.trunc_f64_u" (f64.const 0x1.fffffffcp+31)) (i32.const -2))
+(assert_trap (invoke "i32.trunc_f64_u" (f64.const -0x1p+0)) "integer overflow")
+(assert_return (invoke "i64.trunc_f64_s" (f64.const -0x1p+63)) (i64.const 0x8000000000000000))
+(assert_trap (invoke "i64.trunc_f64_s" (f64.const 0x1p+63)) "integer overflow")
+
+(assert_return (invoke "f32.convert_i32_u" (i32.const 0xffffffff)) (f32.const 4294967296))
+(assert_return (invoke "f64.promote_f32" (f32.const 0x1.fffffep+127)) (f64.const 0x1.fffffep+127))
+(assert_return (invoke "f64.promote_f32" (f32.const nan)) (f64.const nan:canonical))
+(assert_return (invoke "i32.reinterpret_f32" (f32.const -0x0p+0)) (i32.const 0x80000000))
+(assert_return (invoke "i32.reinterpret_f32" (f32.const nan:0x200000)) (i32.const 0x7fa00000))
+(assert_return (invoke "f64.reinterpret_i64" (i64.const 0x7ff4000000000000)) (f64.const nan:0x4000000000000))
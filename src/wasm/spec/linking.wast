@@ -0,0 +1,111 @@
+;; Linking modules to each other and to the host: a curated subset of the
+;; WebAssembly spec test suite's `linking.wast` and `imports.wast`.
+
+(module $Mf
+  (func (export "call") (result i32) (call $g))
+  (func $g (result i32) (i32.const 2))
+)
+(register "Mf" $Mf)
+
+(module $Nf
+  (func $f (import "Mf" "call") (result i32))
+  (export "Mf.call" (func $f))
+  (func (export "call Mf.call") (result i32) (call $f))
+  (func (export "call") (result i32) (call $g))
+  (func $g (result i32) (i32.const 3))
+)
+
+(assert_return (invoke $Mf "call") (i32.const 2))
+(assert_return (invoke $Nf "Mf.call") (i32.const 2))
+(assert_return (invoke $Nf "call") (i32.const 3))
+(assert_return (invoke $Nf "call Mf.call") (i32.const 2))
+
+(module $Mg
+  (global $glob (export "glob") i32 (i32.const 42))
+  (func (export "get") (result i32) (global.get $glob))
+  (global $mut_glob (export "mut_glob") (mut i32) (i32.const 142))
+  (func (export "get_mut") (result i32) (global.get $mut_glob))
+  (func (export "set_mut") (param i32) (global.set $mut_glob (local.get 0)))
+)
+(register "Mg" $Mg)
+
+(module $Ng
+  (global $x (import "Mg" "glob") i32)
+  (global $mut_glob (import "Mg" "mut_glob") (mut i32))
+  (func (export "Mg.get_mut") (result i32) (global.get $mut_glob))
+  (func (export "Mg.set_mut") (param i32) (global.set $mut_glob (local.get 0)))
+  (export "Mg.glob" (global $x))
+  (global $glob (export "glob") i32 (i32.const 43))
+)
+
+(assert_return (get $Mg "glob") (i32.const 42))
+(assert_return (get $Ng "Mg.glob") (i32.const 42))
+(assert_return (get $Ng "glob") (i32.const 43))
+(assert_return (invoke $Mg "get_mut") (i32.const 142))
+(invoke $Ng "Mg.set_mut" (i32.const 241))
+(assert_return (invoke $Mg "get_mut") (i32.const 241))
+(assert_return (invoke $Ng "Mg.get_mut") (i32.const 241))
+
+(module $Mm
+  (memory (export "mem") 1 5)
+  (data (i32.const 10) "\00\01\02\03\04\05\06\07\08\09")
+  (func (export "load") (param $a i32) (result i32) (i32.load8_u (local.get 0)))
+)
+(register "Mm" $Mm)
+
+(module $Nm
+  (func $loadM (import "Mm" "load") (param i32) (result i32))
+  (memory (import "Mm" "mem") 1)
+  (func (export "store") (param $a i32) (param $v i32) (i32.store8 (local.get 0) (local.get 1)))
+  (func (export "Mm.load") (param $a i32) (result i32) (call $loadM (local.get 0)))
+)
+
+(assert_return (invoke $Mm "load" (i32.const 12)) (i32.const 2))
+(assert_return (invoke $Nm "Mm.load" (i32.const 12)) (i32.const 2))
+(invoke $Nm "store" (i32.const 12) (i32.const 0xa7))
+(assert_return (invoke $Mm "load" (i32.const 12)) (i32.const 0xa7))
+
+;; imports which neither a registered module nor the host provide.
+(assert_unlinkable
+  (module (import "Mf" "unknown" (func)))
+  "unknown import"
+)
+(assert_unlinkable
+  (module (import "Mg" "call" (func)))
+  "unknown import"
+)
+(assert_unlinkable
+  (module (import "spectest" "print_i32" (func (param i32))))
+  "unknown import"
+)
+(assert_unlinkable
+  (module (import "wasi_snapshot_preview1" "not_a_function" (func)))
+  "unknown import"
+)
+(assert_unlinkable
+  (module (global (import "spectest" "global_i32") i32))
+  "unknown import"
+)
+(assert_unlinkable
+  (module (memory (import "spectest" "memory") 1))
+  "unknown import"
+)
+(assert_unlinkable
+  (module (table (import "spectest" "table") 10 funcref))
+  "unknown import"
+)
+
+;; host functions are linked by name and signature.
+(module $host
+  (import "wasi_snapshot_preview1" "proc_exit" (func $exit (param i32)))
+  (import "mycelium" "task_id" (func $task_id (result i64)))
+  (func (export "exit") (call $exit (i32.const 0)))
+)
+(assert_unlinkable
+  (module (import "wasi_snapshot_preview1" "proc_exit" (func (param i64))))
+  "incompatible import type"
+)
+(assert_unlinkable
+  (module (import "mycelium" "task_id" (func (result i32))))
+  "incompatible import type"
+)
@@ -0,0 +1,77 @@
+;; Linear memory accesses, bounds checks, and growth: a curated subset of the
+;; WebAssembly spec test suite's `memory.wast`, `address.wast`, and
+;; `memory_grow.wast`.
+
+(module
+  (memory 1 4)
+  (data (i32.const 0) "abcdefghijklmnopqrstuvwxyz")
+
+  (func (export "load8_u") (param $a i32) (result i32) (i32.load8_u (local.get $a)))
+  (func (export "load8_s_offset") (param $a i32) (result i32) (i32.load8_s offset=1 (local.get $a)))
+  (func (export "load16_u") (param $a i32) (result i32) (i32.load16_u (local.get $a)))
+  (func (export "load") (param $a i32) (result i32) (i32.load (local.get $a)))
+  (func (export "load_offset") (param $a i32) (result i32) (i32.load offset=0xfffffffc (local.get $a)))
+  (func (export "i64.load") (param $a i32) (result i64) (i64.load (local.get $a)))
+  (func (export "f64.load") (param $a i32) (result f64) (f64.load (local.get $a)))
+  (func (export "store") (param $a i32) (param $v i32) (i32.store (local.get $a) (local.get $v)))
+  (func (export "f64.store") (param $a i32) (param $v f64) (f64.store (local.get $a) (local.get $v)))
+  (func (export "size") (result i32) (memory.size))
+  (func (export "grow") (param $pages i32) (result i32) (memory.grow (local.get $pages)))
+)
+
+(assert_return (invoke "load8_u" (i32.const 0)) (i32.const 97))
+(assert_return (invoke "load8_u" (i32.const 25)) (i32.const 122))
+(assert_return (invoke "load8_u" (i32.const 26)) (i32.const 0))
+(assert_return (invoke "load8_u" (i32.const 65535)) (i32.const 0))
+(assert_trap (invoke "load8_u" (i32.const 65536)) "out of bounds memory access")
+(assert_trap (invoke "load8_u" (i32.const -1)) "out of bounds memory access")
+(assert_return (invoke "load8_s_offset" (i32.const 0)) (i32.const 98))
+(assert_return (invoke "load16_u" (i32.const 0)) (i32.const 25185))
+(assert_return (invoke "load" (i32.const 0)) (i32.const 1684234849))
+(assert_return (invoke "load" (i32.const 65532)) (i32.const 0))
+(assert_trap (invoke "load" (i32.const 65533)) "out of bounds memory access")
+(assert_trap (invoke "load_offset" (i32.const 4)) "out of bounds memory access")
+(assert_return (invoke "i64.load" (i32.const 0)) (i64.const 0x6867666564636261))
+
+(invoke "store" (i32.const 32) (i32.const 0xdeadbeef))
+(assert_return (invoke "load" (i32.const 32)) (i32.const 0xdeadbeef))
+(assert_return (invoke "load16_u" (i32.const 34)) (i32.const 0xdead))
+(assert_trap (invoke "store" (i32.const 65534) (i32.const 0)) "out of bounds memory access")
+(invoke "f64.store" (i32.const 40) (f64.const -nan:0x8000000000001))
+(assert_return (invoke "f64.load" (i32.const 40)) (f64.const -nan:0x8000000000001))
+
+(assert_return (invoke "size") (i32.const 1))
+(assert_return (invoke "grow" (i32.const 1)) (i32.const 1))
+(assert_return (invoke "size") (i32.const 2))
+(assert_return (invoke "load" (i32.const 65536)) (i32.const 0))
+(assert_return (invoke "grow" (i32.const 2)) (i32.const 2))
+(assert_return (invoke "grow" (i32.const 1)) (i32.const -1))
+(assert_return (invoke "size") (i32.const 4))
+(assert_trap (invoke "load8_u" (i32.const 262144)) "out of bounds memory access")
+
+;; a memory without a maximum size is capped by the host's limits.
+(module
+  (memory 0)
+  (func (export "grow") (param $pages i32) (result i32) (memory.grow (local.get $pages)))
+)
+
+(assert_return (invoke "grow" (i32.const 0)) (i32.const 0))
+(assert_return (invoke "grow" (i32.const 1)) (i32.const 0))
+(assert_return (invoke "grow" (i32.const 0x10000)) (i32.const -1))
+
+(assert_invalid
+  (module (memory 1) (func (drop (i32.load align=8 (i32.const 0)))))
+  "alignment must not be larger than natural"
+)
+(assert_invalid
+  (module (func (drop (i32.load (i32.const 0)))))
+  "unknown memory"
+)
+(assert_invalid
+  (module (memory 1 0))
+  "size minimum must not be greater than maximum"
+)
+(assert_malformed
+  (module binary "\00asm" "\02\00\00\00")
+  "unknown binary version"
+)
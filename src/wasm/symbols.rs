@@ -0,0 +1,277 @@
+//! Symbolizing WASM guest functions, for trap reports.
+//!
+//! Function names are taken from the module's `name` custom section, and
+//! source locations from its DWARF debug sections, if it has them. Both are
+//! extracted when the module is loaded, and stripped from the module that is
+//! actually instantiated.
+use addr2line::gimli;
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt;
+use parity_wasm::elements::{ImportCountType, Module, Section};
+
+/// Debug information for a WASM module.
+#[derive(Default)]
+pub(super) struct Symbols {
+    /// Function names from the `name` section, by function index.
+    names: BTreeMap<u32, String>,
+    /// The number of functions imported by the module, which come before the
+    /// functions it defines in the function index space.
+    imported_funcs: u32,
+    /// The offset of the first instruction of each function defined by the
+    /// module, relative to the start of the code section. This is how DWARF
+    /// addresses code in WASM modules.
+    code_offsets: Vec<u64>,
+    /// The contents of the module's DWARF sections, by section name.
+    dwarf: BTreeMap<String, Vec<u8>>,
+}
+
+/// A symbolized frame of a guest's call stack.
+#[derive(Clone, Debug)]
+pub struct Frame {
+    func: u32,
+    name: Option<String>,
+    location: Option<Location>,
+}
+
+/// A location in a guest's source code.
+#[derive(Clone, Debug)]
+pub struct Location {
+    pub file: String,
+    pub line: Option<u32>,
+}
+
+// === impl Symbols ===
+
+impl Symbols {
+    /// Extracts debug information from `module`, which was parsed from
+    /// `binary`, and strips it from the module.
+    pub(super) fn extract(binary: &[u8], module: Module) -> (Self, Module) {
+        let mut module = match module.parse_names() {
+            Ok(module) => module,
+            Err((errors, module)) => {
+                for (_, error) in errors {
+                    tracing::debug!(%error, "invalid WASM name section");
+                }
+                module
+            }
+        };
+
+        let mut symbols = Self {
+            imported_funcs: module.import_count(ImportCountType::Function) as u32,
+            ..Self::default()
+        };
+
+        if let Some(names) = module.names_section().and_then(|names| names.functions()) {
+            symbols.names = names
+                .names()
+                .iter()
+                .map(|(idx, name)| (idx, name.clone()))
+                .collect();
+        }
+
+        for section in module.custom_sections() {
+            if section.name().starts_with(".debug_") {
+                symbols
+                    .dwarf
+                    .insert(section.name().to_string(), section.payload().to_vec());
+            }
+        }
+        if !symbols.dwarf.is_empty() {
+            symbols.code_offsets = code_offsets(binary).unwrap_or_default();
+        }
+
+        // the interpreter keeps the whole module around, so don't make it
+        // hold on to the debug info as well.
+        module
+            .sections_mut()
+            .retain(|section| !matches!(section, Section::Custom(_) | Section::Name(_)));
+
+        (symbols, module)
+    }
+
+    /// Returns the approximate amount of memory used by the debug
+    /// information, in bytes.
+    pub(super) fn size(&self) -> usize {
+        let names: usize = self.names.values().map(String::len).sum();
+        let dwarf: usize = self.dwarf.values().map(Vec::len).sum();
+        names + dwarf + self.code_offsets.len() * core::mem::size_of::<u64>()
+    }
+
+    /// Symbolizes the functions at `funcs`.
+    pub(super) fn symbolize(&self, funcs: impl IntoIterator<Item = u32>) -> Vec<Frame> {
+        let context = self.dwarf_context();
+        funcs
+            .into_iter()
+            .map(|func| {
+                let mut frame = Frame {
+                    func,
+                    name: self.names.get(&func).map(|name| demangle(name)),
+                    location: None,
+                };
+                if let Some(ref context) = context {
+                    self.find_frame(context, &mut frame);
+                }
+                frame
+            })
+            .collect()
+    }
+
+    fn dwarf_context(
+        &self,
+    ) -> Option<addr2line::Context<gimli::EndianSlice<'_, gimli::LittleEndian>>> {
+        if self.dwarf.is_empty() {
+            return None;
+        }
+
+        let dwarf = gimli::Dwarf::load(|id| {
+            let data = self.dwarf.get(id.name()).map(Vec::as_slice).unwrap_or(&[]);
+            Ok::<_, gimli::Error>(gimli::EndianSlice::new(data, gimli::LittleEndian))
+        })
+        .ok()?;
+        addr2line::Context::from_dwarf(dwarf)
+            .map_err(|error| tracing::debug!(%error, "invalid WASM DWARF sections"))
+            .ok()
+    }
+
+    /// Looks up `frame`'s function in the DWARF debug info.
+    ///
+    /// The interpreter doesn't tell us where in a function a trap occurred, so
+    /// this finds the location of the function's first instruction.
+    fn find_frame(
+        &self,
+        context: &addr2line::Context<gimli::EndianSlice<'_, gimli::LittleEndian>>,
+        frame: &mut Frame,
+    ) -> Option<()> {
+        let defined = frame.func.checked_sub(self.imported_funcs)?;
+        let addr = *self.code_offsets.get(defined as usize)?;
+        let mut frames = context.find_frames(addr).skip_all_loads().ok()?;
+        let found = frames.next().ok()??;
+
+        if let Some(name) = found
+            .function
+            .and_then(|func| func.demangle().ok().map(|name| name.into_owned()))
+        {
+            frame.name = Some(name);
+        }
+        frame.location = found.location.and_then(|location| {
+            Some(Location {
+                file: location.file?.to_string(),
+                line: location.line,
+            })
+        });
+        Some(())
+    }
+}
+
+impl fmt::Debug for Symbols {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Symbols")
+            .field("names", &self.names.len())
+            .field("imported_funcs", &self.imported_funcs)
+            .field("dwarf", &self.dwarf.keys())
+            .finish()
+    }
+}
+
+/// Demangles `name`, if it's a mangled Rust symbol.
+fn demangle(name: &str) -> String {
+    addr2line::demangle(name, gimli::DW_LANG_Rust).unwrap_or_else(|| name.to_string())
+}
+
+/// Returns the offset of the first instruction of each function body in the
+/// code section of `binary`, relative to the start of the section's contents.
+fn code_offsets(binary: &[u8]) -> Option<Vec<u64>> {
+    let mut reader = Reader { binary, pos: 8 };
+    let code = loop {
+        let id = reader.byte()?;
+        let len = reader.leb128()? as usize;
+        let contents = binary.get(reader.pos..reader.pos + len)?;
+        reader.pos += len;
+        if id == 10 {
+            break contents;
+        }
+    };
+
+    let mut reader = Reader {
+        binary: code,
+        pos: 0,
+    };
+    let count = reader.leb128()?;
+    let mut offsets = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let len = reader.leb128()? as usize;
+        let end = reader.pos + len;
+        // skip the local declarations.
+        for _ in 0..reader.leb128()? {
+            reader.leb128()?;
+            reader.byte()?;
+        }
+        offsets.push(reader.pos as u64);
+        reader.pos = end;
+    }
+    Some(offsets)
+}
+
+struct Reader<'a> {
+    binary: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Option<u8> {
+        let byte = *self.binary.get(self.pos)?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn leb128(&mut self) -> Option<u32> {
+        let mut value = 0u32;
+        for shift in (0..35).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u32) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+// === impl Frame ===
+
+impl Frame {
+    /// Returns the index of the frame's function.
+    pub fn func(&self) -> u32 {
+        self.func
+    }
+
+    /// Returns the name of the frame's function, if it's known.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the source location of the frame's function, if it's known.
+    pub fn location(&self) -> Option<&Location> {
+        self.location.as_ref()
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "func[{}]", self.func)?;
+        if let Some(ref name) = self.name {
+            write!(f, " {name}")?;
+        }
+        if let Some(ref location) = self.location {
+            write!(f, " at {}", location.file)?;
+            if let Some(line) = location.line {
+                write!(f, ":{line}")?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,338 @@
+//! WASM programs as preemptible kernel tasks.
+use super::{
+    cache, convert::WasmResult, exit, fuel, CompiledModule, ExitStatus, Host, InstanceId, Manifest,
+    WasmLimits,
+};
+use crate::rt;
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+use core::{
+    fmt,
+    future::Future,
+    mem,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+/// A future that runs a WASM module's `_start` function to completion,
+/// returning its [`ExitStatus`].
+///
+/// The module is instrumented to consume fuel as it runs. Each time the task
+/// is polled, it executes the guest until [`FUEL_PER_SLICE`] instructions'
+/// worth of fuel has been used up, and then yields, so that many guests can
+/// share a core with each other and with other kernel tasks.
+///
+/// Host functions called by the guest may also suspend it until a future
+/// completes (such as a timer or keyboard input), using [`Host::suspend`].
+/// The task waits for that future without blocking the core, and resumes the
+/// guest with the future's output as the host function's return value.
+///
+/// [`FUEL_PER_SLICE`]: fuel::FUEL_PER_SLICE
+#[must_use = "futures do nothing unless polled"]
+pub struct WasmTask {
+    state: State,
+    limits: WasmLimits,
+    manifest: Manifest,
+    /// The name of the exported function the task runs.
+    entry: String,
+    args: Vec<String>,
+    env: Vec<String>,
+}
+
+enum State {
+    /// The module has not yet been instantiated.
+    Init(CompiledModule),
+    Running {
+        host: Host,
+        invocation: wasmi::FuncInvocation<'static>,
+    },
+    Done,
+}
+
+/// A host function call that is waiting for a future to complete.
+pub(super) struct PendingCall(
+    Pin<Box<dyn Future<Output = Result<Option<wasmi::RuntimeValue>, wasmi::Trap>>>>,
+);
+
+/// Returned as a host trap when a host function has suspended the guest by
+/// calling [`Host::suspend`].
+#[derive(Debug)]
+pub(super) struct Suspend;
+
+/// Returned as a host trap when a host function would suspend a guest that
+/// is not running as a [`WasmTask`], and therefore cannot be resumed.
+#[derive(Debug)]
+pub struct WouldBlock;
+
+/// Spawns `binary` as a WASM task on the kernel runtime.
+///
+/// The module is parsed and validated before it is spawned, so invalid
+/// modules are reported immediately. The returned [`JoinHandle`] completes
+/// with the guest's [`ExitStatus`] when it exits or traps.
+///
+/// [`JoinHandle`]: rt::JoinHandle
+pub fn spawn_wasm(
+    binary: &[u8],
+) -> Result<rt::JoinHandle<Result<ExitStatus, wasmi::Error>>, wasmi::Error> {
+    let task = WasmTask::new(binary)?;
+    Ok(rt::spawn(task))
+}
+
+// === impl WasmTask ===
+
+impl WasmTask {
+    pub fn new(binary: &[u8]) -> Result<Self, wasmi::Error> {
+        Self::with_limits(binary, WasmLimits::DEFAULT)
+    }
+
+    /// Returns a new task whose instance is bound by `limits`.
+    pub fn with_limits(binary: &[u8], limits: WasmLimits) -> Result<Self, wasmi::Error> {
+        let module = cache::compile(binary, limits)?;
+        Ok(Self::from_module(module))
+    }
+
+    /// Returns a new task running an already compiled `module`, bound by the
+    /// limits it was compiled with.
+    pub fn from_module(module: CompiledModule) -> Self {
+        Self {
+            limits: *module.limits(),
+            state: State::Init(module),
+            manifest: Manifest::default(),
+            entry: String::from("_start"),
+            args: Vec::new(),
+            env: Vec::new(),
+        }
+    }
+
+    /// Sets the capabilities granted to the guest.
+    ///
+    /// By default, guests are granted the capabilities needed by ordinary
+    /// WASI programs.
+    pub fn with_manifest(self, manifest: Manifest) -> Self {
+        Self { manifest, ..self }
+    }
+
+    /// Sets the exported function the task runs, instead of `_start`.
+    ///
+    /// The function must take no arguments and return no values.
+    pub fn with_entry(self, entry: impl Into<String>) -> Self {
+        Self {
+            entry: entry.into(),
+            ..self
+        }
+    }
+
+    /// Sets the command-line arguments passed to the guest.
+    ///
+    /// See [`Host::set_args`].
+    pub fn with_args<S: Into<String>>(self, args: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            args: args.into_iter().map(Into::into).collect(),
+            ..self
+        }
+    }
+
+    /// Sets the environment variables passed to the guest.
+    ///
+    /// See [`Host::set_env`].
+    pub fn with_env<K: fmt::Display, V: fmt::Display>(
+        self,
+        env: impl IntoIterator<Item = (K, V)>,
+    ) -> Self {
+        Self {
+            env: env
+                .into_iter()
+                .map(|(key, value)| format!("{key}={value}"))
+                .collect(),
+            ..self
+        }
+    }
+
+    fn start(
+        module: &CompiledModule,
+        limits: WasmLimits,
+        manifest: Manifest,
+        entry: &str,
+        args: Vec<String>,
+        env: Vec<String>,
+    ) -> Result<(Host, wasmi::FuncInvocation<'static>), wasmi::Error> {
+        let mut host = Host::with_limits(limits);
+        host.args = args;
+        host.env = env;
+        let instance = host.instantiate_with_manifest(module, manifest)?;
+        let start = instance
+            .export_by_name(entry)
+            .and_then(|export| export.as_func().cloned())
+            .ok_or_else(|| wasmi::Error::Function(format!("module has no `{entry}` function")))?;
+        let invocation = wasmi::FuncInstance::invoke_resumable(&start, &[][..])?;
+        Ok((host, invocation))
+    }
+}
+
+impl Future for WasmTask {
+    type Output = Result<ExitStatus, wasmi::Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let task = &mut *self;
+        let this = &mut task.state;
+        let mut result = match this {
+            State::Init(module) => {
+                // the guest's arguments and environment are moved into its
+                // host, so they're only needed until it starts.
+                let started = Self::start(
+                    module,
+                    task.limits,
+                    task.manifest,
+                    &task.entry,
+                    mem::take(&mut task.args),
+                    mem::take(&mut task.env),
+                );
+                match started {
+                    Ok((mut host, mut invocation)) => {
+                        host.fuel = fuel::FUEL_PER_SLICE;
+                        host.preemptible = true;
+                        let result = invocation.start_execution(&mut host);
+                        *this = State::Running { host, invocation };
+                        result
+                    }
+                    Err(error) => {
+                        *this = State::Done;
+                        return Poll::Ready(Err(error));
+                    }
+                }
+            }
+            State::Running { host, invocation } => {
+                host.fuel = fuel::FUEL_PER_SLICE;
+                ready!(Self::resume(host, invocation, cx))
+            }
+            State::Done => panic!("`WasmTask` polled after completion"),
+        };
+
+        loop {
+            match result {
+                Err(wasmi::ResumableError::Trap(ref trap))
+                    if is_host_error::<fuel::Yield>(trap) =>
+                {
+                    tracing::trace!("WASM task out of fuel, yielding");
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+                Err(wasmi::ResumableError::Trap(ref trap)) if is_host_error::<Suspend>(trap) => {
+                    let State::Running { host, invocation } = this else {
+                        unreachable!("only a running task can be suspended");
+                    };
+                    tracing::trace!("WASM task suspended by host call");
+                    // poll the host call's future immediately, in case it's
+                    // already ready.
+                    result = ready!(Self::resume(host, invocation, cx));
+                }
+                result => {
+                    // drop the instance as soon as the guest has finished.
+                    let State::Running { host, .. } = mem::replace(this, State::Done) else {
+                        unreachable!("only a running task can finish");
+                    };
+                    let result = result.map(|_| ()).map_err(|error| match error {
+                        wasmi::ResumableError::Trap(trap) => wasmi::Error::Trap(trap),
+                        error => wasmi::Error::Function(format!("{error:?}")),
+                    });
+                    return Poll::Ready(exit::exit_status(&host, InstanceId(0), result));
+                }
+            }
+        }
+    }
+}
+
+impl WasmTask {
+    /// Resumes a suspended guest, once the host call it's waiting on (if
+    /// any) has completed.
+    fn resume(
+        host: &mut Host,
+        invocation: &mut wasmi::FuncInvocation<'static>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<wasmi::RuntimeValue>, wasmi::ResumableError>> {
+        let return_val = match host.pending.as_mut() {
+            Some(PendingCall(call)) => {
+                let result = ready!(call.as_mut().poll(cx));
+                host.pending = None;
+                match result {
+                    Ok(value) => value,
+                    Err(trap) => return Poll::Ready(Err(wasmi::ResumableError::Trap(trap))),
+                }
+            }
+            None => None,
+        };
+        Poll::Ready(invocation.resume_execution(return_val, host))
+    }
+}
+
+impl fmt::Debug for WasmTask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self.state {
+            State::Init(_) => "Init",
+            State::Running { .. } => "Running",
+            State::Done => "Done",
+        };
+        let mut f = f.debug_struct("WasmTask");
+        f.field("state", &state)
+            .field("limits", &self.limits)
+            .field("manifest", &self.manifest)
+            .field("entry", &self.entry);
+        // once the guest has started, its arguments and environment belong
+        // to its host.
+        if let State::Init(_) = self.state {
+            f.field("args", &self.args).field("env", &self.env);
+        }
+        f.finish()
+    }
+}
+
+fn is_host_error<E: wasmi::HostError>(trap: &wasmi::Trap) -> bool {
+    matches!(trap.kind(), wasmi::TrapKind::Host(error) if error.is::<E>())
+}
+
+// === impl PendingCall ===
+
+impl PendingCall {
+    pub(super) fn new<T: WasmResult>(
+        future: impl Future<Output = Result<T, wasmi::Trap>> + 'static,
+    ) -> Self {
+        Self(Box::pin(async move {
+            future.await.map(WasmResult::into_wasm_result)
+        }))
+    }
+}
+
+impl fmt::Debug for PendingCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PendingCall(..)")
+    }
+}
+
+// === impl Suspend ===
+
+impl fmt::Display for Suspend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("host call suspended the guest")
+    }
+}
+
+impl wasmi::HostError for Suspend {}
+
+// === impl WouldBlock ===
+
+impl fmt::Display for WouldBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("host call would block, but the guest is not running as a task")
+    }
+}
+
+impl wasmi::HostError for WouldBlock {}
+
+// Safety: the `Host` and the `FuncInvocation` contain `Rc`s, which is why
+// they are not `Send`. However, every `Rc` that refers to the guest's
+// instances was created by this task when it instantiated the module, and
+// none of them are ever shared outside of the task. The same is true of the
+// futures of suspended host calls, which are created by host functions
+// called from this task, and may hold references to the caller's memory.
+// Therefore, it's okay to move the task to another core, as all of the
+// references to its instances move with it.
+unsafe impl Send for WasmTask {}
@@ -0,0 +1,135 @@
+//! Guest call stack tracing.
+//!
+//! The interpreter doesn't expose its call stack, so to report where a guest
+//! trapped, modules are instrumented to keep a shadow call stack in the host:
+//! every function calls the `enter` host function with its index and the
+//! current call depth, which is tracked in a global that is incremented
+//! around every call to another of the module's functions.
+//!
+//! The shadow stack is only truncated when a function is entered, so frames
+//! above the current call depth may be stale. The call depth global is
+//! exported, so that the live frames can be found when the guest traps.
+//! Since a trap unwinds the guest without decrementing the call depth, it
+//! must be reset with [`reset_call_depth`] before a trapped instance is
+//! called again.
+use super::{instrument, Host};
+use alloc::{vec, vec::Vec};
+use parity_wasm::elements::{
+    ExportEntry, ExportSection, ImportCountType, Instruction, Internal, Module, Section, ValueType,
+};
+use wasmi::RuntimeValue;
+
+/// The import module of the tracing host function.
+pub(super) const MODULE: &str = "__mycelium_trace";
+
+/// The name of the exported global holding the guest's current call depth.
+pub(super) const CALL_DEPTH: &str = "__mycelium_call_depth";
+
+/// Rewrites `module` so that it records its calls in the shadow call stack.
+///
+/// Function indices are those of the original module, so this must be done
+/// before any other instrumentation adds imports.
+pub(super) fn instrument(module: &mut Module) -> Result<(), wasmi::Error> {
+    let imported_funcs = module.import_count(ImportCountType::Function) as u32;
+    let enter = instrument::add_func_import(module, MODULE, "enter", vec![ValueType::I32; 2])?;
+    let depth = instrument::add_global(module)?;
+    if module.export_section().is_none() {
+        module
+            .insert_section(Section::Export(ExportSection::default()))
+            .map_err(instrument::error)?;
+    }
+    module
+        .export_section_mut()
+        .expect("export section was inserted")
+        .entries_mut()
+        .push(ExportEntry::new(CALL_DEPTH.into(), Internal::Global(depth)));
+
+    let Some(code) = module.code_section_mut() else {
+        return Ok(());
+    };
+    for (idx, body) in code.bodies_mut().iter_mut().enumerate() {
+        let func = imported_funcs + idx as u32;
+        let instrs = body.code_mut().elements_mut();
+        let mut out = Vec::with_capacity(instrs.len() + 3);
+        out.extend([
+            Instruction::GetGlobal(depth),
+            Instruction::I32Const(func as i32),
+            Instruction::Call(enter),
+        ]);
+        for instr in instrs.drain(..) {
+            let traced = match instr {
+                // `add_func_import` has already shifted the indices of the
+                // module's own functions up by one.
+                Instruction::Call(idx) => idx > imported_funcs,
+                Instruction::CallIndirect(..) => true,
+                _ => false,
+            };
+            if !traced {
+                out.push(instr);
+                continue;
+            }
+
+            out.extend([
+                Instruction::GetGlobal(depth),
+                Instruction::I32Const(1),
+                Instruction::I32Add,
+                Instruction::SetGlobal(depth),
+                instr,
+                Instruction::GetGlobal(depth),
+                Instruction::I32Const(1),
+                Instruction::I32Sub,
+                Instruction::SetGlobal(depth),
+            ]);
+        }
+        *instrs = out;
+    }
+
+    Ok(())
+}
+
+/// Returns the index of the call depth global added to `module` by
+/// [`instrument`], if it has been instrumented.
+pub(super) fn depth_global(module: &Module) -> Option<u32> {
+    module
+        .export_section()?
+        .entries()
+        .iter()
+        .find(|export| export.field() == CALL_DEPTH)
+        .and_then(|export| match *export.internal() {
+            Internal::Global(idx) => Some(idx),
+            _ => None,
+        })
+}
+
+/// Resets `instance`'s call depth after it trapped, so that it can be called
+/// again.
+pub(super) fn reset_call_depth(instance: &wasmi::ModuleRef) -> Result<(), wasmi::Error> {
+    match instance.export_by_name(CALL_DEPTH) {
+        Some(wasmi::ExternVal::Global(depth)) => depth.set(RuntimeValue::I32(0)),
+        _ => Ok(()),
+    }
+}
+
+/// Records that the calling instance has entered the function `func` at call
+/// depth `depth`.
+pub(super) fn enter(host: &mut Host, depth: u32, func: u32) -> Result<(), wasmi::Trap> {
+    if let Some(instance) = host.caller_instance_mut() {
+        instance.stack.truncate(depth as usize);
+        instance.stack.push(func);
+    }
+    Ok(())
+}
+
+/// Returns the live frames of `instance`'s shadow call stack, innermost
+/// first.
+pub(super) fn backtrace<'a>(
+    module: &wasmi::ModuleRef,
+    stack: &'a [u32],
+) -> impl Iterator<Item = u32> + 'a {
+    let depth = module
+        .export_by_name(CALL_DEPTH)
+        .and_then(|export| export.as_global().cloned())
+        .and_then(|global| global.get().try_into::<i32>())
+        .map_or(stack.len(), |depth| depth as usize + 1);
+    stack[..depth.min(stack.len())].iter().rev().copied()
+}
@@ -1,104 +1,711 @@
-use super::Host;
-
-// FIXME: These should both be `u16`, and probably generated from the wasi
-// snapshot_0 `witx` definitions.
-const __WASI_ESUCCESS: u16 = 0;
-const __WASI_EIO: u16 = 29; // Not sure I counted right. Might be some other error.
-
-const __WASI_STDOUT: u32 = 1;
-
-fn get_element_ptr(base: u32, offset: u32, scale: u32) -> Result<u32, wasmi::Trap> {
-    let byte_offset = offset
-        .checked_mul(scale)
-        .ok_or(wasmi::TrapKind::MemoryAccessOutOfBounds)?;
-    Ok(base
-        .checked_add(byte_offset)
-        .ok_or(wasmi::TrapKind::MemoryAccessOutOfBounds)?)
-}
-
-/// Loads the value stored at `base + (offset * scale)`.
-fn mem_read<T: wasmi::LittleEndianConvert>(
-    mem: &[u8],
-    base: u32,
-    offset: u32,
-    scale: u32,
-) -> Result<T, wasmi::Trap> {
-    let addr = get_element_ptr(base, offset, scale)?;
-    let slice = mem
-        .get(addr as usize..)
-        .ok_or(wasmi::TrapKind::MemoryAccessOutOfBounds)?;
-    Ok(T::from_little_endian(slice).map_err(|_| wasmi::TrapKind::MemoryAccessOutOfBounds)?)
-}
-
-/// Stores a value at `base + (offset * scale)`.
-fn mem_write<T: wasmi::LittleEndianConvert>(
-    mem: &mut [u8],
-    value: T,
-    base: u32,
-    offset: u32,
-    scale: u32,
-) -> Result<(), wasmi::Trap> {
-    let addr = get_element_ptr(base, offset, scale)?;
-
-    // NOTE: while `from_little_endian` will trim the input slice to be the
-    // correct length, `into_little_endian` does not, and requires that `slice`
-    // be the correct length for our data type.
-    //
-    // Assume that the size in wasm matches our host size for the given type.
-    let addr_after = get_element_ptr(addr, core::mem::size_of::<T>() as u32, 1)?;
-    let slice = mem
-        .get_mut(addr as usize..addr_after as usize)
-        .ok_or(wasmi::TrapKind::MemoryAccessOutOfBounds)?;
-    T::into_little_endian(value, slice);
-    Ok(())
-}
-
-/// Reference to a subslice of memory.
-fn mem_slice(mem: &[u8], addr: u32, len: u32) -> Result<&[u8], wasmi::Trap> {
-    let end = get_element_ptr(addr, len, 1)?;
-    Ok(mem
-        .get(addr as usize..end as usize)
-        .ok_or(wasmi::TrapKind::MemoryAccessOutOfBounds)?)
-}
-
-#[allow(dead_code)] // unused
-fn mem_slice_mut(mem: &mut [u8], addr: u32, len: u32) -> Result<&mut [u8], wasmi::Trap> {
-    let end = get_element_ptr(addr, len, 1)?;
-    Ok(mem
-        .get_mut(addr as usize..end as usize)
-        .ok_or(wasmi::TrapKind::MemoryAccessOutOfBounds)?)
+use super::{fd::Handle, Capabilities, GuestPtr, GuestSlice, Host};
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::time::Duration;
+use maitake::time;
+
+/// WASI ABI types, generated from the `witx` definitions.
+// not every type in the WASI ABI is used by the kernel (yet).
+#[allow(dead_code)]
+pub(super) mod types {
+    include!(concat!(env!("OUT_DIR"), "/wasi_types.rs"));
+}
+
+/// `ENOSYS` stubs for every WASI function, generated from the `witx`
+/// definitions.
+// stubs which are shadowed by a real implementation are never used, and some
+// WASI functions just have a lot of arguments.
+#[allow(dead_code, clippy::too_many_arguments)]
+mod unsupported {
+    include!(concat!(env!("OUT_DIR"), "/wasi_stubs.rs"));
+}
+
+/// `wasi_snapshot_preview1` host functions.
+pub(super) mod preview1 {
+    pub(in crate::wasm) use super::unsupported::preview1::*;
+    pub(in crate::wasm) use super::{
+        args_get, args_sizes_get, clock_res_get, clock_time_get, environ_get, environ_sizes_get,
+        fd_close, fd_fdstat_get, fd_prestat_dir_name, fd_prestat_get, fd_read, fd_seek, fd_tell,
+        fd_write, poll_oneoff, proc_exit, random_get,
+    };
+}
+
+/// `wasi_unstable` host functions.
+///
+/// These are the same as the `wasi_snapshot_preview1` functions, except where
+/// the ABI differs.
+pub(super) mod unstable {
+    pub(in crate::wasm) use super::unsupported::unstable::*;
+    pub(in crate::wasm) use super::{
+        args_get, args_sizes_get, clock_res_get, clock_time_get, environ_get, environ_sizes_get,
+        fd_close, fd_fdstat_get, fd_prestat_dir_name, fd_prestat_get, fd_read, fd_tell, fd_write,
+        random_get, unstable_fd_seek as fd_seek, unstable_poll_oneoff as poll_oneoff,
+    };
+}
+
+pub(super) use self::types::preview1::{
+    rights, Clockid, Errno, Filetype, Preopentype, Rights, Whence,
+};
+
+pub(super) const RIGHTS_STDIN: Rights =
+    rights::FD_READ | rights::FD_FILESTAT_GET | rights::POLL_FD_READWRITE;
+pub(super) const RIGHTS_STDOUT: Rights =
+    rights::FD_WRITE | rights::FD_FILESTAT_GET | rights::POLL_FD_READWRITE;
+pub(super) const RIGHTS_FILE: Rights = rights::FD_READ
+    | rights::FD_SEEK
+    | rights::FD_TELL
+    | rights::FD_FILESTAT_GET
+    | rights::POLL_FD_READWRITE;
+pub(super) const RIGHTS_DIR: Rights = rights::FD_FILESTAT_GET;
+
+/// Returns early with the errno if `$e` is an error.
+macro_rules! try_errno {
+    ($e:expr) => {
+        match $e {
+            Ok(val) => val,
+            Err(errno) => return Ok(errno),
+        }
+    };
 }
 
 #[tracing::instrument(skip(host))]
 pub fn fd_write(
     host: &mut Host,
     fd: u32,
-    iovs: u32,
-    iovs_len: u32,
-    nwritten: u32,
-) -> Result<u16, wasmi::Trap> {
-    if fd != __WASI_STDOUT {
-        return Ok(__WASI_EIO);
+    iovs: GuestSlice<GuestSlice<u8>>,
+    nwritten: GuestPtr<u32>,
+) -> Result<Errno, wasmi::Trap> {
+    let entry = try_errno!(host.fds.get(fd));
+    try_errno!(entry.check_rights(rights::FD_WRITE));
+    let stream = match entry.handle {
+        Handle::Stdout => "stdout",
+        Handle::Stderr => "stderr",
+        Handle::Dir(_) => return Ok(Errno::Isdir),
+        _ => return Ok(Errno::Badf),
+    };
+    if !host.caller_capabilities().contains(Capabilities::STDOUT) {
+        return Ok(Errno::Perm);
     }
 
-    host.memory.with_direct_access_mut(|mem| {
+    host.memory()?.with_direct_access_mut(|mem| {
         let mut bytes_written = 0u32;
-        for idx in 0..iovs_len {
-            // read iov
-            let iov = get_element_ptr(iovs, 8, idx)?;
-            let buf = mem_read::<u32>(mem, iov, 0, 4)?;
-            let buf_len = mem_read::<u32>(mem, iov, 1, 4)?;
-
-            // TODO: Actually write out our slice.
-            let buf_slice = mem_slice(mem, buf, buf_len)?;
-            let buf_str = core::str::from_utf8(buf_slice);
-            tracing::info!(?buf_slice, ?buf_str, "fd_write");
-
-            bytes_written = bytes_written.saturating_add(buf_len);
+        for iov in iovs.iter() {
+            let buf = iov.read(mem)?;
+            let text = String::from_utf8_lossy(buf.as_bytes(mem)?);
+            for line in text.lines() {
+                tracing::info!(target: "wasm", fd, stream, "{line}");
+            }
+
+            bytes_written = bytes_written.saturating_add(buf.len());
         }
 
         // Write number of bytes written to memory.
-        mem_write::<u32>(mem, bytes_written, nwritten, 0, 0)?;
-        Ok(__WASI_ESUCCESS)
+        nwritten.write(mem, bytes_written)?;
+        Ok(Errno::Success)
+    })
+}
+
+#[tracing::instrument(skip(host))]
+pub fn fd_read(
+    host: &mut Host,
+    fd: u32,
+    iovs: GuestSlice<GuestSlice<u8>>,
+    nread: GuestPtr<u32>,
+) -> Result<Errno, wasmi::Trap> {
+    let memory = host.memory()?.clone();
+    let caps = host.caller_capabilities();
+    let entry = try_errno!(host.fds.get_mut(fd));
+    try_errno!(entry.check_rights(rights::FD_READ));
+    if let Handle::Stdin = entry.handle {
+        if !caps.contains(Capabilities::STDIN) {
+            return Ok(Errno::Perm);
+        }
+        return read_stdin(host, memory, iovs, nread);
+    }
+
+    memory.with_direct_access_mut(|mem| {
+        let mut bytes_read = 0u32;
+        match entry.handle {
+            Handle::File(ref mut file) => {
+                for iov in iovs.iter() {
+                    let buf = iov.read(mem)?;
+                    let len = file.read(buf.as_bytes_mut(mem)?) as u32;
+                    bytes_read = bytes_read.saturating_add(len);
+                    if len < buf.len() {
+                        break;
+                    }
+                }
+            }
+            Handle::Dir(_) => return Ok(Errno::Isdir),
+            _ => return Ok(Errno::Badf),
+        }
+
+        nread.write(mem, bytes_read)?;
+        Ok(Errno::Success)
+    })
+}
+
+/// Reads a key from the keyboard into the first iovec, suspending the guest
+/// until a key is pressed.
+///
+/// Only one key is read at a time, so that the guest sees input as soon as
+/// it's typed. If the key's UTF-8 encoding doesn't fit in the buffer, it is
+/// truncated.
+fn read_stdin(
+    host: &mut Host,
+    memory: wasmi::MemoryRef,
+    iovs: GuestSlice<GuestSlice<u8>>,
+    nread: GuestPtr<u32>,
+) -> Result<Errno, wasmi::Trap> {
+    use crate::drivers::ps2_keyboard::{self, DecodedKey};
+
+    let Some(iov) = iovs.get(0) else {
+        memory.with_direct_access_mut(|mem| nread.write(mem, 0))?;
+        return Ok(Errno::Success);
+    };
+    let buf = memory.with_direct_access(|mem| iov.read(mem))?;
+
+    Err(host.suspend(async move {
+        let mut bytes = [0; 4];
+        let input = loop {
+            // raw keys (arrows, function keys, and so on) have no text to
+            // read, so wait for the next one.
+            if let DecodedKey::Unicode(c) = ps2_keyboard::next_key().await {
+                break c.encode_utf8(&mut bytes).as_bytes();
+            }
+        };
+
+        memory.with_direct_access_mut(|mem| {
+            let buf = buf.truncate(input.len() as u32);
+            buf.as_bytes_mut(mem)?
+                .copy_from_slice(&input[..buf.len() as usize]);
+            nread.write(mem, buf.len())?;
+            Ok(Errno::Success)
+        })
+    }))
+}
+
+#[tracing::instrument(skip(host))]
+pub fn fd_close(host: &mut Host, fd: u32) -> Result<Errno, wasmi::Trap> {
+    try_errno!(host.fds.close(fd));
+    Ok(Errno::Success)
+}
+
+#[tracing::instrument(skip(host))]
+pub fn fd_seek(
+    host: &mut Host,
+    fd: u32,
+    offset: i64,
+    whence: u32,
+    newoffset: GuestPtr<u64>,
+) -> Result<Errno, wasmi::Trap> {
+    let entry = try_errno!(host.fds.get_mut(fd));
+    try_errno!(entry.check_rights(rights::FD_SEEK));
+    let file = match entry.handle {
+        Handle::File(ref mut file) => file,
+        Handle::Dir(_) => return Ok(Errno::Isdir),
+        _ => return Ok(Errno::Spipe),
+    };
+    let whence = try_errno!(u8::try_from(whence)
+        .ok()
+        .and_then(|whence| Whence::try_from(whence).ok())
+        .ok_or(Errno::Inval));
+    let pos = try_errno!(file.seek(offset, whence));
+
+    host.memory()?
+        .with_direct_access_mut(|mem| newoffset.write(mem, pos))?;
+    Ok(Errno::Success)
+}
+
+/// `wasi_unstable` numbers its whence values differently from
+/// `wasi_snapshot_preview1`.
+#[tracing::instrument(skip(host))]
+pub fn unstable_fd_seek(
+    host: &mut Host,
+    fd: u32,
+    offset: i64,
+    whence: u32,
+    newoffset: GuestPtr<u64>,
+) -> Result<Errno, wasmi::Trap> {
+    use self::types::unstable;
+    let whence = match u8::try_from(whence).map(unstable::Whence::try_from) {
+        Ok(Ok(unstable::Whence::Cur)) => Whence::Cur,
+        Ok(Ok(unstable::Whence::End)) => Whence::End,
+        Ok(Ok(unstable::Whence::Set)) => Whence::Set,
+        _ => return Ok(Errno::Inval),
+    };
+    fd_seek(host, fd, offset, u8::from(whence) as u32, newoffset)
+}
+
+#[tracing::instrument(skip(host))]
+pub fn fd_tell(host: &mut Host, fd: u32, offset: GuestPtr<u64>) -> Result<Errno, wasmi::Trap> {
+    let entry = try_errno!(host.fds.get(fd));
+    try_errno!(entry.check_rights(rights::FD_TELL));
+    let pos = match entry.handle {
+        Handle::File(ref file) => file.tell(),
+        Handle::Dir(_) => return Ok(Errno::Isdir),
+        _ => return Ok(Errno::Spipe),
+    };
+
+    host.memory()?
+        .with_direct_access_mut(|mem| offset.write(mem, pos))?;
+    Ok(Errno::Success)
+}
+
+#[tracing::instrument(skip(host))]
+pub fn fd_fdstat_get(host: &mut Host, fd: u32, stat: u32) -> Result<Errno, wasmi::Trap> {
+    use self::types::preview1::fdstat;
+    let entry = try_errno!(host.fds.get(fd));
+
+    let stat = GuestPtr::<u8>::new(stat);
+    host.memory()?.with_direct_access_mut(|mem| {
+        GuestSlice::new(stat, fdstat::SIZE)
+            .as_bytes_mut(mem)?
+            .fill(0);
+        stat.field(fdstat::FS_FILETYPE_OFFSET)?
+            .write(mem, u8::from(entry.filetype()))?;
+        stat.field(fdstat::FS_FLAGS_OFFSET)?
+            .write(mem, entry.flags)?;
+        stat.field(fdstat::FS_RIGHTS_BASE_OFFSET)?
+            .write(mem, entry.rights_base)?;
+        stat.field(fdstat::FS_RIGHTS_INHERITING_OFFSET)?
+            .write(mem, entry.rights_inheriting)?;
+        Ok(Errno::Success)
+    })
+}
+
+#[tracing::instrument(skip(host))]
+pub fn fd_prestat_get(host: &mut Host, fd: u32, prestat: u32) -> Result<Errno, wasmi::Trap> {
+    use self::types::preview1::{prestat, prestat_dir};
+
+    // wasi-libc probes descriptors starting at 3 until this returns `EBADF`,
+    // so anything that isn't a preopened directory is `EBADF`.
+    let dir = match host.fds.get(fd).map(|entry| &entry.handle) {
+        Ok(Handle::Dir(dir)) => dir,
+        _ => return Ok(Errno::Badf),
+    };
+
+    let prestat = GuestPtr::<u8>::new(prestat);
+    host.memory()?.with_direct_access_mut(|mem| {
+        GuestSlice::new(prestat, prestat::SIZE)
+            .as_bytes_mut(mem)?
+            .fill(0);
+        prestat.write(mem, Preopentype::Dir.into())?;
+        prestat
+            .field::<u8>(prestat::PAYLOAD_OFFSET)?
+            .field(prestat_dir::PR_NAME_LEN_OFFSET)?
+            .write(mem, dir.name().len() as u32)?;
+        Ok(Errno::Success)
+    })
+}
+
+#[tracing::instrument(skip(host))]
+pub fn fd_prestat_dir_name(
+    host: &mut Host,
+    fd: u32,
+    path: GuestPtr<u8>,
+    path_len: u32,
+) -> Result<Errno, wasmi::Trap> {
+    let dir = match host.fds.get(fd).map(|entry| &entry.handle) {
+        Ok(Handle::Dir(dir)) => dir,
+        _ => return Ok(Errno::Badf),
+    };
+    let name = dir.name().as_bytes();
+    if (path_len as usize) < name.len() {
+        return Ok(Errno::Nametoolong);
+    }
+
+    host.memory()?.with_direct_access_mut(|mem| {
+        GuestSlice::new(path, name.len() as u32)
+            .as_bytes_mut(mem)?
+            .copy_from_slice(name);
+        Ok(Errno::Success)
+    })
+}
+
+/// Writes `strings` into the guest's memory, as an array of pointers at
+/// `ptrs` to NUL-terminated strings in the buffer at `buf`.
+fn write_strings(
+    host: &Host,
+    strings: &[String],
+    ptrs: GuestPtr<GuestPtr<u8>>,
+    buf: GuestPtr<u8>,
+) -> Result<Errno, wasmi::Trap> {
+    host.memory()?.with_direct_access_mut(|mem| {
+        let mut ptr = buf;
+        for (idx, string) in strings.iter().enumerate() {
+            ptrs.offset(idx as u32)?.write(mem, ptr)?;
+            let len = string.len() as u32 + 1;
+            let dst = GuestSlice::new(ptr, len).as_bytes_mut(mem)?;
+            dst[..string.len()].copy_from_slice(string.as_bytes());
+            dst[string.len()] = 0;
+            ptr = ptr.offset(len)?;
+        }
+        Ok(Errno::Success)
+    })
+}
+
+/// Writes the number of `strings` to `count`, and the size of the buffer
+/// needed to hold them (with a NUL terminator each) to `buf_size`.
+fn write_sizes(
+    host: &Host,
+    strings: &[String],
+    count: GuestPtr<u32>,
+    buf_size: GuestPtr<u32>,
+) -> Result<Errno, wasmi::Trap> {
+    let size = strings.iter().map(|string| string.len() + 1).sum::<usize>();
+    let size = try_errno!(u32::try_from(size).map_err(|_| Errno::Overflow));
+    host.memory()?.with_direct_access_mut(|mem| {
+        count.write(mem, strings.len() as u32)?;
+        buf_size.write(mem, size)?;
+        Ok(Errno::Success)
     })
 }
+
+#[tracing::instrument(skip(host))]
+pub fn args_get(
+    host: &mut Host,
+    argv: GuestPtr<GuestPtr<u8>>,
+    argv_buf: GuestPtr<u8>,
+) -> Result<Errno, wasmi::Trap> {
+    write_strings(host, &host.args, argv, argv_buf)
+}
+
+#[tracing::instrument(skip(host))]
+pub fn args_sizes_get(
+    host: &mut Host,
+    argc: GuestPtr<u32>,
+    argv_buf_size: GuestPtr<u32>,
+) -> Result<Errno, wasmi::Trap> {
+    write_sizes(host, &host.args, argc, argv_buf_size)
+}
+
+#[tracing::instrument(skip(host))]
+pub fn environ_get(
+    host: &mut Host,
+    environ: GuestPtr<GuestPtr<u8>>,
+    environ_buf: GuestPtr<u8>,
+) -> Result<Errno, wasmi::Trap> {
+    write_strings(host, &host.env, environ, environ_buf)
+}
+
+#[tracing::instrument(skip(host))]
+pub fn environ_sizes_get(
+    host: &mut Host,
+    environc: GuestPtr<u32>,
+    environ_buf_size: GuestPtr<u32>,
+) -> Result<Errno, wasmi::Trap> {
+    write_sizes(host, &host.env, environc, environ_buf_size)
+}
+
+/// Exits the guest with the exit code `rval`, by trapping with
+/// [`ProcExit`](super::exit::ProcExit).
+#[tracing::instrument(skip(_host))]
+pub fn proc_exit(_host: &mut Host, rval: u32) -> Result<(), wasmi::Trap> {
+    Err(wasmi::TrapKind::Host(Box::new(super::exit::ProcExit(rval))).into())
+}
+
+#[tracing::instrument(skip(host))]
+pub fn random_get(host: &mut Host, buf: GuestPtr<u8>, buf_len: u32) -> Result<Errno, wasmi::Trap> {
+    host.memory()?.with_direct_access_mut(|mem| {
+        crate::rng::fill_bytes(GuestSlice::new(buf, buf_len).as_bytes_mut(mem)?);
+        Ok(Errno::Success)
+    })
+}
+
+/// Returns the current time of `clock`, as a duration since the clock's
+/// epoch.
+///
+/// Both the monotonic and realtime clocks count from when the kernel's timer
+/// was started at boot, as there's no RTC driver to tell the wall-clock time
+/// yet.
+fn now(clock: u32) -> Result<Duration, Errno> {
+    match Clockid::try_from(clock).map_err(|_| Errno::Inval)? {
+        Clockid::Realtime | Clockid::Monotonic => time::Instant::try_now()
+            .map(|now| now.elapsed())
+            .map_err(|_| Errno::Notsup),
+        // TODO(eliza): track how long each guest has actually been running.
+        Clockid::ProcessCputimeId | Clockid::ThreadCputimeId => Err(Errno::Notsup),
+    }
+}
+
+fn duration_to_timestamp(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+}
+
+#[tracing::instrument(skip(host))]
+pub fn clock_res_get(
+    host: &mut Host,
+    id: u32,
+    resolution: GuestPtr<u64>,
+) -> Result<Errno, wasmi::Trap> {
+    // make sure the clock is one we can actually read.
+    try_errno!(now(id));
+    let timer = try_errno!(crate::rt::timer().ok_or(Errno::Notsup));
+    let res = duration_to_timestamp(timer.clock().tick_duration());
+
+    host.memory()?
+        .with_direct_access_mut(|mem| resolution.write(mem, res))?;
+    Ok(Errno::Success)
+}
+
+/// The timer is always read at its full resolution, so `precision` is
+/// ignored.
+#[tracing::instrument(skip(host))]
+pub fn clock_time_get(
+    host: &mut Host,
+    id: u32,
+    precision: u64,
+    time: GuestPtr<u64>,
+) -> Result<Errno, wasmi::Trap> {
+    let now = duration_to_timestamp(try_errno!(now(id)));
+
+    host.memory()?
+        .with_direct_access_mut(|mem| time.write(mem, now))?;
+    Ok(Errno::Success)
+}
+
+/// The layout of a `subscription`, which differs between WASI versions.
+struct SubscriptionLayout {
+    size: u32,
+    userdata_offset: u32,
+    tag_offset: u32,
+    clock_id_offset: u32,
+    clock_timeout_offset: u32,
+    clock_flags_offset: u32,
+    fd_offset: u32,
+}
+
+impl SubscriptionLayout {
+    const PREVIEW1: Self = {
+        use self::types::preview1::{
+            subscription, subscription_clock, subscription_fd_readwrite, subscription_u,
+        };
+        let payload = subscription::U_OFFSET + subscription_u::PAYLOAD_OFFSET;
+        Self {
+            size: subscription::SIZE,
+            userdata_offset: subscription::USERDATA_OFFSET,
+            tag_offset: subscription::U_OFFSET,
+            clock_id_offset: payload + subscription_clock::ID_OFFSET,
+            clock_timeout_offset: payload + subscription_clock::TIMEOUT_OFFSET,
+            clock_flags_offset: payload + subscription_clock::FLAGS_OFFSET,
+            fd_offset: payload + subscription_fd_readwrite::FILE_DESCRIPTOR_OFFSET,
+        }
+    };
+
+    const UNSTABLE: Self = {
+        use self::types::unstable::{
+            subscription, subscription_clock, subscription_fd_readwrite, subscription_u,
+        };
+        let payload = subscription::U_OFFSET + subscription_u::PAYLOAD_OFFSET;
+        Self {
+            size: subscription::SIZE,
+            userdata_offset: subscription::USERDATA_OFFSET,
+            tag_offset: subscription::U_OFFSET,
+            clock_id_offset: payload + subscription_clock::ID_OFFSET,
+            clock_timeout_offset: payload + subscription_clock::TIMEOUT_OFFSET,
+            clock_flags_offset: payload + subscription_clock::FLAGS_OFFSET,
+            fd_offset: payload + subscription_fd_readwrite::FILE_DESCRIPTOR_OFFSET,
+        }
+    };
+}
+
+/// An event to be written to `poll_oneoff`'s output array.
+struct Event {
+    userdata: u64,
+    error: Errno,
+    ty: types::preview1::Eventtype,
+}
+
+#[tracing::instrument(skip(host))]
+pub fn poll_oneoff(
+    host: &mut Host,
+    in_: u32,
+    out: u32,
+    nsubscriptions: u32,
+    nevents: GuestPtr<u32>,
+) -> Result<Errno, wasmi::Trap> {
+    poll(
+        host,
+        &SubscriptionLayout::PREVIEW1,
+        in_,
+        out,
+        nsubscriptions,
+        nevents,
+    )
+}
+
+/// `wasi_unstable`'s clock subscriptions have an additional field.
+#[tracing::instrument(skip(host))]
+pub fn unstable_poll_oneoff(
+    host: &mut Host,
+    in_: u32,
+    out: u32,
+    nsubscriptions: u32,
+    nevents: GuestPtr<u32>,
+) -> Result<Errno, wasmi::Trap> {
+    poll(
+        host,
+        &SubscriptionLayout::UNSTABLE,
+        in_,
+        out,
+        nsubscriptions,
+        nevents,
+    )
+}
+
+/// Waits for the first of `nsubscriptions` subscriptions to trigger.
+///
+/// File descriptor subscriptions trigger immediately, except that reads from
+/// stdin can't be waited for yet, so they trigger with `Errno::Notsup`. Clock
+/// subscriptions suspend the guest until the earliest timeout has elapsed.
+/// Absolute timeouts are converted to timeouts relative to the current time
+/// of the subscription's clock, and timeouts which have already passed
+/// trigger immediately.
+fn poll(
+    host: &mut Host,
+    layout: &SubscriptionLayout,
+    in_: u32,
+    out: u32,
+    nsubscriptions: u32,
+    nevents: GuestPtr<u32>,
+) -> Result<Errno, wasmi::Trap> {
+    use self::types::preview1::{subclockflags, Eventtype};
+
+    if nsubscriptions == 0 {
+        return Ok(Errno::Inval);
+    }
+    let in_ = GuestPtr::<u8>::new(in_);
+    let out = GuestPtr::<u8>::new(out);
+
+    let memory = host.memory()?.clone();
+    let mut ready = Vec::new();
+    // the clock subscriptions, as (userdata, timeout) pairs.
+    let mut clocks = Vec::new();
+    memory.with_direct_access(|mem| {
+        for idx in 0..nsubscriptions {
+            let sub = in_.field::<u8>(idx * layout.size)?;
+            let userdata = sub.field::<u64>(layout.userdata_offset)?.read(mem)?;
+            let tag = sub.field::<u8>(layout.tag_offset)?.read(mem)?;
+            match Eventtype::try_from(tag) {
+                Ok(Eventtype::Clock) => {
+                    let id = sub.field::<u32>(layout.clock_id_offset)?.read(mem)?;
+                    let timeout = sub.field::<u64>(layout.clock_timeout_offset)?.read(mem)?;
+                    let flags = sub.field::<u16>(layout.clock_flags_offset)?.read(mem)?;
+                    let timeout = match now(id) {
+                        Ok(now) if flags & subclockflags::SUBSCRIPTION_CLOCK_ABSTIME != 0 => {
+                            timeout.saturating_sub(duration_to_timestamp(now))
+                        }
+                        Ok(_) => timeout,
+                        Err(error) => {
+                            ready.push(Event {
+                                userdata,
+                                error,
+                                ty: Eventtype::Clock,
+                            });
+                            continue;
+                        }
+                    };
+                    if timeout == 0 {
+                        ready.push(Event {
+                            userdata,
+                            error: Errno::Success,
+                            ty: Eventtype::Clock,
+                        });
+                    } else {
+                        clocks.push((userdata, timeout));
+                    }
+                }
+                Ok(ty) => {
+                    // writes never block, and neither do reads from files,
+                    // but reads from stdin wait for a key to be pressed, and
+                    // we can't wait for the keyboard here yet.
+                    let fd = sub.field::<u32>(layout.fd_offset)?.read(mem)?;
+                    let error = match host.fds.get(fd) {
+                        Ok(entry)
+                            if matches!(ty, Eventtype::FdRead)
+                                && matches!(entry.handle, Handle::Stdin) =>
+                        {
+                            Errno::Notsup
+                        }
+                        Ok(_) => Errno::Success,
+                        Err(errno) => errno,
+                    };
+                    ready.push(Event {
+                        userdata,
+                        error,
+                        ty,
+                    });
+                }
+                Err(_) => ready.push(Event {
+                    userdata,
+                    error: Errno::Inval,
+                    ty: Eventtype::Clock,
+                }),
+            }
+        }
+        Ok::<_, wasmi::Trap>(())
+    })?;
+
+    if !ready.is_empty() {
+        return memory.with_direct_access_mut(|mem| write_events(mem, out, nevents, &ready));
+    }
+
+    let timeout = clocks
+        .iter()
+        .map(|&(_, timeout)| timeout)
+        .min()
+        .expect("if no events are ready, there must be at least one clock");
+    Err(host.suspend(async move {
+        sleep(Duration::from_nanos(timeout)).await;
+        let events = clocks
+            .into_iter()
+            .filter(|&(_, t)| t <= timeout)
+            .map(|(userdata, _)| Event {
+                userdata,
+                error: Errno::Success,
+                ty: Eventtype::Clock,
+            })
+            .collect::<Vec<_>>();
+        memory.with_direct_access_mut(|mem| write_events(mem, out, nevents, &events))
+    }))
+}
+
+/// Writes `events` to the array at `out`, and the number of events to
+/// `nevents`.
+fn write_events(
+    mem: &mut [u8],
+    out: GuestPtr<u8>,
+    nevents: GuestPtr<u32>,
+    events: &[Event],
+) -> Result<Errno, wasmi::Trap> {
+    use self::types::preview1::event;
+
+    for (idx, ev) in events.iter().enumerate() {
+        let ptr = out.offset(idx as u32 * event::SIZE)?;
+        GuestSlice::new(ptr, event::SIZE).as_bytes_mut(mem)?.fill(0);
+        ptr.field(event::USERDATA_OFFSET)?.write(mem, ev.userdata)?;
+        ptr.field(event::ERROR_OFFSET)?
+            .write(mem, u16::from(ev.error))?;
+        ptr.field(event::TYPE_OFFSET)?.write(mem, u8::from(ev.ty))?;
+    }
+    nevents.write(mem, events.len() as u32)?;
+    Ok(Errno::Success)
+}
+
+/// Sleeps for `duration`, even if it's longer than the timer's maximum sleep
+/// duration.
+pub(super) async fn sleep(mut duration: Duration) {
+    loop {
+        match time::try_sleep(duration) {
+            Ok(sleep) => return sleep.await,
+            Err(time::TimerError::DurationTooLong { max, .. }) => {
+                time::sleep(max).await;
+                duration -= max;
+            }
+            Err(error) => {
+                tracing::warn!(%error, "WASM guest cannot sleep");
+                return;
+            }
+        }
+    }
+}